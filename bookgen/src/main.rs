@@ -0,0 +1,358 @@
+mod pgn;
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Instant;
+
+use library::book::keys::polyglot_key;
+use library::book::{encode_move, move_text, Book, RawEntry};
+use library::chess::game::{Game, Side};
+use library::chess::moves::parse_san_move;
+
+use pgn::{Outcome, PgnGame, PgnReader};
+
+/// Games between progress reports.
+const REPORT_INTERVAL: u64 = 10000;
+
+fn usage() {
+    eprintln!("usage: bookgen build <output> <pgn>... [options]");
+    eprintln!("       bookgen merge <output> <book>...  positions of earlier books take");
+    eprintln!("                                         precedence over later ones");
+    eprintln!("       bookgen dump <book> [fen]        every entry, or the moves of one");
+    eprintln!("                                         position");
+    eprintln!();
+    eprintln!("build options:");
+    eprintln!("  --min-elo <n>      skip moves by players rated below n (default 0)");
+    eprintln!("  --min-games <n>    skip moves played in fewer than n games (default 1)");
+    eprintln!("  --max-ply <n>      only the first n plies of each game (default 60)");
+    eprintln!("  --only <side>      only moves by white or black, for repertoire books");
+}
+
+struct Filters {
+    min_elo: u32,
+    min_games: u32,
+    max_ply: usize,
+    only: Option<Side>,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self {
+            min_elo: 0,
+            min_games: 1,
+            max_ply: 60,
+            only: None,
+        }
+    }
+}
+
+/// Results of the games a move was played in, from the point of view of the side
+/// that played it.
+#[derive(Default, Clone, Copy)]
+struct Stats {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl Stats {
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Two points per win and one per draw, as Polyglot weighs its books.
+    fn weight(&self) -> u64 {
+        2 * self.wins as u64 + self.draws as u64
+    }
+}
+
+/// Moves by position key and encoded move.
+type Statistics = HashMap<(u64, u16), Stats>;
+
+fn elo(game: &PgnGame, side: Side) -> u32 {
+    let header = if side == Side::White {
+        "WhiteElo"
+    } else {
+        "BlackElo"
+    };
+    game.header(header)
+        .and_then(|elo| elo.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Adds the moves of `pgn` to `statistics`. Returns `None` if the game can't be used,
+/// because it's unfinished, a variant or has a move we can't read.
+fn add_game(pgn: &PgnGame, filters: &Filters, statistics: &mut Statistics) -> Option<()> {
+    let outcome = pgn.outcome?;
+    if pgn
+        .header("Variant")
+        .is_some_and(|variant| !variant.eq_ignore_ascii_case("standard"))
+    {
+        return None;
+    }
+    let mut game = match pgn.header("FEN") {
+        Some(fen) => match Game::try_from_fen(fen.to_string()) {
+            Ok(game) => game,
+            Err(error) => {
+                eprintln!("skipping game with invalid FEN {}: {}", fen, error);
+                return None;
+            }
+        },
+        None => Game::new_classical(),
+    };
+    let elos = [elo(pgn, Side::White), elo(pgn, Side::Black)];
+
+    // read the whole game first so that one we can't read adds nothing
+    let mut played = Vec::new();
+    for san in pgn.moves.iter().take(filters.max_ply) {
+        let mv = parse_san_move(&mut game, san)?;
+        played.push((polyglot_key(&game), encode_move(mv), game.active()));
+        game.make_move(mv);
+    }
+
+    for (key, mv, side) in played {
+        if elos[side as usize] < filters.min_elo || filters.only.is_some_and(|only| only != side) {
+            continue;
+        }
+        let stats = statistics.entry((key, mv)).or_default();
+        match (outcome, side) {
+            (Outcome::Draw, _) => stats.draws += 1,
+            (Outcome::WhiteWin, Side::White) | (Outcome::BlackWin, Side::Black) => stats.wins += 1,
+            _ => stats.losses += 1,
+        }
+    }
+    Some(())
+}
+
+/// Turns the statistics into book entries. Weights are scaled down per position when
+/// they don't fit in the 16 bits of the format, and moves that only ever lost are left
+/// out, since a zero weight is never played anyway.
+fn book_entries(statistics: &Statistics, min_games: u32) -> Vec<RawEntry> {
+    let mut by_position: HashMap<u64, Vec<(u16, u64)>> = HashMap::new();
+    for ((key, mv), stats) in statistics {
+        if stats.games() >= min_games && stats.weight() > 0 {
+            by_position
+                .entry(*key)
+                .or_default()
+                .push((*mv, stats.weight()));
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (key, mut moves) in by_position {
+        moves.sort_by_key(|(mv, weight)| (std::cmp::Reverse(*weight), *mv));
+        let max = moves[0].1.max(1);
+        for (mv, weight) in moves {
+            let weight = if max > u16::MAX as u64 {
+                // keep moves that scored at all playable
+                (weight * u16::MAX as u64 / max).max(weight.min(1))
+            } else {
+                weight
+            };
+            entries.push(RawEntry {
+                key,
+                mv,
+                weight: weight as u16,
+                learn: 0,
+            });
+        }
+    }
+    entries
+}
+
+fn build(args: &[String]) {
+    let Some(output) = args.first() else {
+        usage();
+        return;
+    };
+
+    let mut filters = Filters::default();
+    let mut inputs = Vec::new();
+    let mut index = 1;
+    while index < args.len() {
+        let value = args.get(index + 1);
+        let number = value.and_then(|value| value.parse::<u32>().ok());
+        match (args[index].as_str(), number) {
+            ("--min-elo", Some(elo)) => filters.min_elo = elo,
+            ("--min-games", Some(games)) => filters.min_games = games,
+            ("--max-ply", Some(plies)) => filters.max_ply = plies as usize,
+            ("--only", _) => {
+                filters.only = match value.map(|value| value.as_str()) {
+                    Some("white") => Some(Side::White),
+                    Some("black") => Some(Side::Black),
+                    _ => {
+                        eprintln!("--only takes white or black");
+                        return;
+                    }
+                }
+            }
+            (option, _) if option.starts_with("--") => {
+                eprintln!("invalid option {}", option);
+                usage();
+                return;
+            }
+            (input, _) => {
+                inputs.push(input.to_string());
+                index += 1;
+                continue;
+            }
+        }
+        index += 2;
+    }
+    if inputs.is_empty() {
+        usage();
+        return;
+    }
+
+    let start = Instant::now();
+    let mut statistics = Statistics::new();
+    let (mut read, mut used) = (0u64, 0u64);
+    for input in &inputs {
+        let file = File::open(input).expect("could not open PGN file");
+        let mut reader = PgnReader::new(BufReader::new(file));
+        while let Some(pgn) = reader.next_game().expect("could not read PGN file") {
+            read += 1;
+            if add_game(&pgn, &filters, &mut statistics).is_some() {
+                used += 1;
+            }
+            if read % REPORT_INTERVAL == 0 {
+                println!("{} games read, {} used", read, used);
+            }
+        }
+    }
+
+    let book = Book::from_entries(book_entries(&statistics, filters.min_games));
+    book.write(Path::new(output)).expect("could not write book");
+    println!(
+        "{} games read, {} used, {} entries written to {} in {:.1}s",
+        read,
+        used,
+        book.len(),
+        output,
+        start.elapsed().as_secs_f64()
+    );
+}
+
+fn merge(args: &[String]) {
+    if args.len() < 2 {
+        usage();
+        return;
+    }
+
+    let mut entries: Vec<RawEntry> = Vec::new();
+    let mut keys = HashSet::new();
+    for input in &args[1..] {
+        let book = Book::open(Path::new(input)).expect("could not read book");
+        let new_keys: Vec<u64> = book
+            .raw_entries()
+            .iter()
+            .map(|entry| entry.key)
+            .filter(|key| !keys.contains(key))
+            .collect();
+        entries.extend(
+            book.raw_entries()
+                .iter()
+                .filter(|entry| entry.weight > 0 && !keys.contains(&entry.key)),
+        );
+        keys.extend(new_keys);
+    }
+
+    let book = Book::from_entries(entries);
+    book.write(Path::new(&args[0]))
+        .expect("could not write book");
+    println!("{} entries written to {}", book.len(), args[0]);
+}
+
+fn dump(args: &[String]) {
+    let Some(path) = args.first() else {
+        usage();
+        return;
+    };
+    let book = Book::open(Path::new(path)).expect("could not read book");
+
+    if args.len() == 1 {
+        for entry in book.raw_entries() {
+            println!(
+                "{:016x} {} {} {}",
+                entry.key,
+                move_text(entry.mv),
+                entry.weight,
+                entry.learn
+            );
+        }
+        return;
+    }
+
+    let mut game = Game::from_fen(args[1..].join(" "));
+    let entries = book.entries(&mut game);
+    let total: u64 = entries.iter().map(|entry| entry.weight as u64).sum();
+    for entry in entries {
+        println!(
+            "{} weight {} ({:.1}%)",
+            entry.mv,
+            entry.weight,
+            100.0 * entry.weight as f64 / total.max(1) as f64
+        );
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|command| command.as_str()) {
+        Some("build") => build(&args[1..]),
+        Some("merge") => merge(&args[1..]),
+        Some("dump") => dump(&args[1..]),
+        _ => usage(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(pgn: &str) -> PgnGame {
+        PgnReader::new(pgn.as_bytes())
+            .next_game()
+            .unwrap()
+            .expect("no game in PGN")
+    }
+
+    #[test]
+    fn invalid_fen_headers_are_skipped() {
+        let mut statistics = Statistics::new();
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+            "garbage",
+        ] {
+            let pgn = read(&format!("[FEN \"{}\"]\n\n1. e4 e5 1-0\n", fen));
+            assert!(add_game(&pgn, &Filters::default(), &mut statistics).is_none());
+        }
+        assert!(statistics.is_empty());
+
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let pgn = read(&format!("[FEN \"{}\"]\n\n1. e4 e5 1-0\n", fen));
+        assert!(add_game(&pgn, &Filters::default(), &mut statistics).is_some());
+        assert_eq!(statistics.len(), 2);
+    }
+
+    #[test]
+    fn lost_moves_are_left_out() {
+        let mut statistics = Statistics::new();
+        let pgn = read("1. e4 e5 1-0\n");
+        add_game(&pgn, &Filters::default(), &mut statistics).unwrap();
+        let pgn = read("1. d4 d5 0-1\n");
+        add_game(&pgn, &Filters::default(), &mut statistics).unwrap();
+
+        let entries = book_entries(&statistics, 1);
+        // e4 and d5 won, e5 and d4 lost
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.weight == 2));
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    WhiteWin,
+    Draw,
+    BlackWin,
+}
+
+/// A game as written in the file: tag pairs, the main line in SAN and the result.
+pub struct PgnGame {
+    pub headers: HashMap<String, String>,
+    pub moves: Vec<String>,
+    /// `None` for unfinished games, written `*`.
+    pub outcome: Option<Outcome>,
+}
+
+impl PgnGame {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|value| value.as_str())
+    }
+}
+
+/// Reads games one at a time, so collections don't have to fit in memory.
+pub struct PgnReader<R: BufRead> {
+    input: R,
+    /// The first tag pair of the next game, read while looking for the end of this one.
+    pending: Option<String>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            pending: None,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if let Some(line) = self.pending.take() {
+            return Ok(Some(line));
+        }
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line))
+    }
+
+    /// The next game, or `None` at the end of the input.
+    pub fn next_game(&mut self) -> io::Result<Option<PgnGame>> {
+        let mut headers = HashMap::new();
+        let mut movetext = String::new();
+        // tag pairs in a comment that spans lines don't start a new game
+        let mut comment_depth = 0;
+
+        while let Some(line) = self.read_line()? {
+            let trimmed = line.trim();
+            if comment_depth == 0 && trimmed.starts_with('[') {
+                if !movetext.trim().is_empty() {
+                    self.pending = Some(line);
+                    break;
+                }
+                if let Some((name, value)) = parse_tag(trimmed) {
+                    headers.insert(name, value);
+                }
+                continue;
+            }
+            if trimmed.starts_with('%') {
+                continue;
+            }
+            for c in trimmed.chars() {
+                match c {
+                    '{' => comment_depth += 1,
+                    '}' => comment_depth = 0,
+                    _ => {}
+                }
+            }
+            movetext.push_str(trimmed);
+            movetext.push('\n');
+        }
+
+        if headers.is_empty() && movetext.trim().is_empty() {
+            return Ok(None);
+        }
+        let (moves, outcome) = parse_movetext(&movetext);
+        Ok(Some(PgnGame {
+            headers,
+            moves,
+            outcome,
+        }))
+    }
+}
+
+/// `[Name "value"]`, with `\"` and `\\` escapes in the value.
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.trim_end().strip_suffix(']')?;
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let value = value.replace("\\\"", "\"").replace("\\\\", "\\");
+    Some((name.to_string(), value))
+}
+
+/// The main line's moves and the result, skipping comments, variations, move numbers
+/// and annotation glyphs.
+fn parse_movetext(text: &str) -> (Vec<String>, Option<Outcome>) {
+    let mut moves = Vec::new();
+    let mut outcome = None;
+    let mut variation_depth = 0;
+    let mut chars = text.chars().peekable();
+    let mut token = String::new();
+
+    let mut finish = |token: &mut String, variation_depth: i32| {
+        if variation_depth == 0 && !token.is_empty() {
+            match token.as_str() {
+                "1-0" => outcome = Some(Outcome::WhiteWin),
+                "0-1" => outcome = Some(Outcome::BlackWin),
+                "1/2-1/2" => outcome = Some(Outcome::Draw),
+                "*" => {}
+                _ if token.starts_with('$') => {}
+                _ => {
+                    // move numbers can be glued to the move, as in `1.e4`
+                    let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                    if !san.is_empty() {
+                        moves.push(san.to_string());
+                    }
+                }
+            }
+        }
+        token.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                finish(&mut token, variation_depth);
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                }
+            }
+            ';' => {
+                finish(&mut token, variation_depth);
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => {
+                finish(&mut token, variation_depth);
+                variation_depth += 1;
+            }
+            ')' => {
+                finish(&mut token, variation_depth);
+                variation_depth = (variation_depth - 1).max(0);
+            }
+            _ if c.is_whitespace() => finish(&mut token, variation_depth),
+            _ => token.push(c),
+        }
+    }
+    finish(&mut token, variation_depth);
+
+    (moves, outcome)
+}
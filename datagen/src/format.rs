@@ -0,0 +1,129 @@
+use std::io::{self, Write};
+
+use library::chess::game::{Game, Side};
+
+/// Bytes per position in the binary format.
+pub const RECORD_SIZE: usize = 32;
+
+/// Game outcome from white's point of view.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    BlackWin = 0,
+    Draw = 1,
+    WhiteWin = 2,
+}
+
+/// One training position with its search score. The outcome is only known once the
+/// game is over, so it's passed in when writing.
+pub struct Record {
+    pub fen: String,
+    pub position: [u8; 29],
+    /// Centipawns from white's point of view.
+    pub score: i16,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Binary,
+    Text,
+}
+
+impl Outcome {
+    pub fn winner(side: Side) -> Self {
+        match side {
+            Side::White => Outcome::WhiteWin,
+            Side::Black => Outcome::BlackWin,
+        }
+    }
+
+    /// 1 for a white win, 0.5 for a draw, 0 for a black win.
+    pub fn text(&self) -> &'static str {
+        match self {
+            Outcome::BlackWin => "0.0",
+            Outcome::Draw => "0.5",
+            Outcome::WhiteWin => "1.0",
+        }
+    }
+}
+
+/// Packs the position into the first 29 bytes of a record:
+///
+/// - bytes 0..8: occupancy bitboard, little endian
+/// - bytes 8..24: one nibble per occupied square in square order, low nibble first,
+///   holding `piece + 6` for black pieces and `piece` for white ones
+/// - byte 24: side to move in bit 7, castling rights (white kingside, white queenside,
+///   black kingside, black queenside) in bits 0 to 3
+/// - byte 25: en passant square, or 64 for none
+/// - byte 26: halfmove clock
+/// - bytes 27..29: fullmove number, little endian
+///
+/// The score (i16, little endian) and the outcome (`Outcome as u8`) make up the rest.
+fn pack_position(game: &Game) -> [u8; 29] {
+    let board = game.board();
+    let mut bytes = [0; 29];
+
+    let occupied = board.occupied();
+    bytes[..8].copy_from_slice(&occupied.data().to_le_bytes());
+    for (index, square) in occupied.enumerate() {
+        let (side, piece) = board.piece_at(square).expect("occupied square is empty");
+        let code = piece as u8 + if side == Side::Black { 6 } else { 0 };
+        bytes[8 + index / 2] |= code << (4 * (index % 2));
+    }
+
+    let mut flags = if game.active() == Side::Black {
+        0x80
+    } else {
+        0
+    };
+    for (bit, (side, kingside)) in [
+        (Side::White, true),
+        (Side::White, false),
+        (Side::Black, true),
+        (Side::Black, false),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let (short, long) = game.castle_rights(side);
+        if (kingside && short) || (!kingside && long) {
+            flags |= 1 << bit;
+        }
+    }
+    bytes[24] = flags;
+    bytes[25] = game.ep_square().map_or(64, |square| square.index());
+    bytes[26] = game.half_move();
+    bytes[27..29].copy_from_slice(&game.full_move().to_le_bytes());
+
+    bytes
+}
+
+impl Record {
+    /// `score` is from the side to move's point of view.
+    pub fn new(game: &Game, score: i32) -> Self {
+        let score = if game.active() == Side::White {
+            score
+        } else {
+            -score
+        };
+
+        Self {
+            fen: game.to_fen(),
+            position: pack_position(game),
+            score: score.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+        }
+    }
+
+    /// Text records are `<fen> | <score> | <result>`, which the `tune` tool reads as is.
+    pub fn write(&self, out: &mut impl Write, format: Format, outcome: Outcome) -> io::Result<()> {
+        match format {
+            Format::Binary => {
+                let mut bytes = [0; RECORD_SIZE];
+                bytes[..29].copy_from_slice(&self.position);
+                bytes[29..31].copy_from_slice(&self.score.to_le_bytes());
+                bytes[31] = outcome as u8;
+                out.write_all(&bytes)
+            }
+            Format::Text => writeln!(out, "{} | {} | {}", self.fen, self.score, outcome.text()),
+        }
+    }
+}
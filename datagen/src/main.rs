@@ -0,0 +1,165 @@
+mod format;
+mod play;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+use format::Format;
+use play::{Player, Settings};
+
+const DEFAULT_GAMES: u64 = 1000;
+const DEFAULT_NODES: u64 = 5000;
+const DEFAULT_RANDOM_PLIES: usize = 8;
+/// Games between progress reports.
+const REPORT_INTERVAL: u64 = 100;
+
+fn usage() {
+    eprintln!("usage: datagen <output> [options]");
+    eprintln!();
+    eprintln!(
+        "  --games <n>         games to play (default {})",
+        DEFAULT_GAMES
+    );
+    eprintln!(
+        "  --nodes <n>         nodes per move (default {})",
+        DEFAULT_NODES
+    );
+    eprintln!(
+        "  --random-plies <n>  random moves at the start of each game (default {})",
+        DEFAULT_RANDOM_PLIES
+    );
+    eprintln!("  --book <file>       EPD or FEN file to pick starting positions from");
+    eprintln!("  --threads <n>       games played at once (default: all cores)");
+    eprintln!("  --seed <n>          seed for the openings (default 1)");
+    eprintln!("  --text              write `<fen> | <score> | <result>` lines instead of");
+    eprintln!("                      32 byte binary records");
+}
+
+/// Starting positions, one per line. EPD lines only have four fields, so they get
+/// zeroed move counters; anything after the position is ignored.
+fn load_book(path: &str) -> Vec<String> {
+    let text = fs::read_to_string(path).expect("could not read book");
+    text.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 || line.starts_with('#') {
+                return None;
+            }
+            let counters = match fields.get(4..6) {
+                Some(counters) if counters.iter().all(|field| field.parse::<u32>().is_ok()) => {
+                    counters.join(" ")
+                }
+                _ => "0 1".to_string(),
+            };
+            Some(format!("{} {}", fields[..4].join(" "), counters))
+        })
+        .collect()
+}
+
+/// Seed for one game, well mixed so that neighbouring games don't look alike.
+fn game_seed(seed: u64, game: u64) -> u64 {
+    let mut x = seed ^ game.wrapping_mul(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(output) = args.first() else {
+        usage();
+        return;
+    };
+
+    let mut games = DEFAULT_GAMES;
+    let mut settings = Settings {
+        nodes: DEFAULT_NODES,
+        random_plies: DEFAULT_RANDOM_PLIES,
+        book: Vec::new(),
+    };
+    let mut threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let mut seed = 1;
+    let mut format = Format::Binary;
+
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        let mut value = || options.next().expect("missing option value").as_str();
+        match option.as_str() {
+            "--games" => games = value().parse().expect("games must be a number"),
+            "--nodes" => settings.nodes = value().parse().expect("nodes must be a number"),
+            "--random-plies" => {
+                settings.random_plies = value().parse().expect("random plies must be a number")
+            }
+            "--book" => settings.book = load_book(value()),
+            "--threads" => threads = value().parse().expect("threads must be a number"),
+            "--seed" => seed = value().parse().expect("seed must be a number"),
+            "--text" => format = Format::Text,
+            _ => {
+                eprintln!("unknown option {}", option);
+                usage();
+                return;
+            }
+        }
+    }
+
+    let mut out = BufWriter::new(File::create(output).expect("could not create output"));
+    let start = Instant::now();
+    let next_game = AtomicU64::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let (settings, next_game) = (&settings, &next_game);
+            scope.spawn(move || {
+                let mut player = Player::new();
+                loop {
+                    let game = next_game.fetch_add(1, Ordering::Relaxed);
+                    if game >= games {
+                        break;
+                    }
+                    let played = player.play(settings, game_seed(seed, game));
+                    if sender.send((game, played)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // games are written in order, so the output doesn't depend on the thread count
+        let mut pending = BTreeMap::new();
+        let mut written = 0;
+        let mut positions = 0;
+        for (game, played) in receiver {
+            pending.insert(game, played);
+            while let Some((records, outcome)) = pending.remove(&written) {
+                for record in records.iter() {
+                    record
+                        .write(&mut out, format, outcome)
+                        .expect("could not write output");
+                }
+                positions += records.len();
+                written += 1;
+
+                if written % REPORT_INTERVAL == 0 || written == games {
+                    let seconds = start.elapsed().as_secs_f64().max(1e-9);
+                    println!(
+                        "{} games, {} positions, {:.0} positions/s",
+                        written,
+                        positions,
+                        positions as f64 / seconds
+                    );
+                }
+            }
+        }
+    });
+
+    out.flush().expect("could not write output");
+}
@@ -0,0 +1,144 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use library::chess::game::Game;
+use library::chess::moves::generate_legal_moves;
+use library::search::limits::SearchLimits;
+use library::search::score::is_mate_score;
+use library::search::searcher::Searcher;
+use library::search::transposition::TranspositionTable;
+use library::utils::random::Random;
+
+use crate::format::{Outcome, Record};
+
+const HASH_MB: usize = 16;
+/// Games that haven't ended by then are scored as draws.
+const MAX_PLIES: usize = 400;
+/// Openings the first search already considers this lopsided are thrown away.
+const MAX_OPENING_SCORE: i32 = 1000;
+/// A game is drawn once the score stays within this margin for `DRAW_PLIES` plies
+/// after `DRAW_MIN_PLY`.
+const DRAW_MARGIN: i32 = 10;
+const DRAW_PLIES: usize = 8;
+const DRAW_MIN_PLY: usize = 80;
+/// A game is won once the score stays above this for `WIN_PLIES` plies.
+const WIN_MARGIN: i32 = 2000;
+const WIN_PLIES: usize = 4;
+
+pub struct Settings {
+    pub nodes: u64,
+    pub random_plies: usize,
+    /// FENs to start from, each followed by `random_plies` random moves.
+    pub book: Vec<String>,
+}
+
+/// Plays self-play games, one search thread each. Everything about a game follows
+/// from its seed, so the same seed gives the same games on any number of threads.
+pub struct Player {
+    searcher: Searcher,
+}
+
+impl Player {
+    pub fn new() -> Self {
+        let tt = Arc::new(TranspositionTable::new(HASH_MB));
+        Self {
+            searcher: Searcher::new(tt, Arc::new(AtomicBool::new(false))),
+        }
+    }
+
+    /// Plays random moves from the book or the starting position until it finds an
+    /// opening that isn't over and isn't already decided.
+    fn opening(&mut self, settings: &Settings, random: &mut Random) -> Game {
+        loop {
+            let mut game = if settings.book.is_empty() {
+                Game::new_classical()
+            } else {
+                let index = random.next_below(settings.book.len() as u64) as usize;
+                Game::from_fen(settings.book[index].clone())
+            };
+
+            let mut finished = false;
+            for _ in 0..settings.random_plies {
+                let moves = generate_legal_moves(&mut game);
+                if moves.is_empty() {
+                    finished = true;
+                    break;
+                }
+                game.make_move(moves[random.next_below(moves.len() as u64) as usize]);
+            }
+            if finished || generate_legal_moves(&mut game).is_empty() {
+                continue;
+            }
+
+            let result = self.searcher.search(
+                &mut game,
+                &SearchLimits::new_nodes(settings.nodes),
+                &mut |_| {},
+            );
+            if result.score.abs() <= MAX_OPENING_SCORE {
+                return game;
+            }
+        }
+    }
+
+    /// One game from a fresh opening. Positions in check, positions where the best move
+    /// is a capture and mate scores are left out.
+    pub fn play(&mut self, settings: &Settings, seed: u64) -> (Vec<Record>, Outcome) {
+        self.searcher.clear();
+        let mut random = Random::new(seed);
+        let mut game = self.opening(settings, &mut random);
+        let limits = SearchLimits::new_nodes(settings.nodes);
+
+        let mut records = Vec::new();
+        let mut draw_plies = 0;
+        let mut win_plies = 0;
+        let outcome = loop {
+            if game.is_draw() || game.ply() >= MAX_PLIES {
+                break Outcome::Draw;
+            }
+
+            let result = self.searcher.search(&mut game, &limits, &mut |_| {});
+            let Some(best_move) = result.best_move else {
+                // no legal moves
+                break if game.is_in_check() {
+                    Outcome::winner(game.active().opposite())
+                } else {
+                    Outcome::Draw
+                };
+            };
+            let score = result.score;
+            let leader = if score > 0 {
+                game.active()
+            } else {
+                game.active().opposite()
+            };
+            if is_mate_score(score) {
+                break Outcome::winner(leader);
+            }
+
+            win_plies = if score.abs() >= WIN_MARGIN {
+                win_plies + 1
+            } else {
+                0
+            };
+            if win_plies >= WIN_PLIES {
+                break Outcome::winner(leader);
+            }
+            draw_plies = if score.abs() <= DRAW_MARGIN && game.ply() >= DRAW_MIN_PLY {
+                draw_plies + 1
+            } else {
+                0
+            };
+            if draw_plies >= DRAW_PLIES {
+                break Outcome::Draw;
+            }
+
+            if !game.is_in_check() && !best_move.is_capture() {
+                records.push(Record::new(&game, score));
+            }
+            game.make_move(best_move);
+        };
+
+        (records, outcome)
+    }
+}
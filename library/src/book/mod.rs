@@ -0,0 +1,292 @@
+pub mod keys;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::chess::board::Piece;
+use crate::chess::game::Game;
+use crate::chess::moves::{generate_legal_moves, Move};
+use crate::utils::notation;
+use crate::utils::random::Random;
+
+use keys::polyglot_key;
+
+/// Every entry is a key, a move, a weight and learning data, all big-endian.
+const ENTRY_SIZE: usize = 16;
+
+const PROMOTION_PIECES: [Piece; 4] = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+/// How to choose between the moves the book has for a position.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Selection {
+    /// Always the move with the highest weight.
+    Best,
+    /// A random move, each as likely as its weight says.
+    Weighted,
+}
+
+/// How the engine plays from the book, set from the UCI options.
+#[derive(Clone, Copy, Debug)]
+pub struct BookSettings {
+    pub selection: Selection,
+    /// Last move number, as counted in FENs, that is played from the book.
+    pub max_depth: u16,
+}
+
+impl Default for BookSettings {
+    fn default() -> Self {
+        Self {
+            selection: Selection::Weighted,
+            max_depth: 255,
+        }
+    }
+}
+
+/// A book move for a position.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BookEntry {
+    pub mv: Move,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+/// An entry as stored in the file, with the position only known by its key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RawEntry {
+    pub key: u64,
+    /// Encoded as in `encode_move`.
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+/// An opening book in the Polyglot `.bin` format.
+pub struct Book {
+    /// Sorted by key, as in the file.
+    entries: Vec<RawEntry>,
+}
+
+impl Book {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "book size is not a multiple of the entry size",
+            ));
+        }
+
+        let entries = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| RawEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(entry[10..12].try_into().unwrap()),
+                learn: u32::from_be_bytes(entry[12..16].try_into().unwrap()),
+            })
+            .collect();
+        Ok(Self::from_entries(entries))
+    }
+
+    /// A book of `entries`, which can be in any order. Entries of the same position
+    /// keep their order.
+    pub fn from_entries(mut entries: Vec<RawEntry>) -> Self {
+        // lookups rely on the order, which a hand-made file might not have
+        entries.sort_by_key(|entry| entry.key);
+        Self { entries }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.mv.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.learn.to_be_bytes());
+        }
+        fs::write(path, bytes)
+    }
+
+    /// Every entry, sorted by key.
+    pub fn raw_entries(&self) -> &[RawEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All the book's moves for `game`, in file order. Entries whose move isn't legal,
+    /// which can happen with hash collisions, are left out.
+    pub fn entries(&self, game: &mut Game) -> Vec<BookEntry> {
+        let key = polyglot_key(game);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                Some(BookEntry {
+                    mv: decode_move(game, entry.mv)?,
+                    weight: entry.weight,
+                    learn: entry.learn,
+                })
+            })
+            .collect()
+    }
+
+    /// A move to play in `game`, or `None` if the game is out of the book or past its
+    /// depth. Moves with zero weight are never played.
+    pub fn pick(
+        &self,
+        game: &mut Game,
+        settings: &BookSettings,
+        random: &mut Random,
+    ) -> Option<Move> {
+        if game.full_move() > settings.max_depth {
+            return None;
+        }
+
+        let entries: Vec<BookEntry> = self
+            .entries(game)
+            .into_iter()
+            .filter(|entry| entry.weight > 0)
+            .collect();
+        match settings.selection {
+            Selection::Best => entries
+                .iter()
+                .max_by_key(|entry| entry.weight)
+                .map(|entry| entry.mv),
+            Selection::Weighted => {
+                let total: u64 = entries.iter().map(|entry| entry.weight as u64).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut choice = random.next_below(total);
+                for entry in &entries {
+                    if choice < entry.weight as u64 {
+                        return Some(entry.mv);
+                    }
+                    choice -= entry.weight as u64;
+                }
+                None
+            }
+        }
+    }
+}
+
+/// A move in Polyglot's encoding: the target square in the low six bits, then the
+/// origin, then the promotion piece.
+pub fn encode_move(mv: Move) -> u16 {
+    let promotion = match mv.promotion() {
+        Some(piece) => 1 + PROMOTION_PIECES.iter().position(|p| *p == piece).unwrap() as u16,
+        None => 0,
+    };
+    // castling is stored as the king taking its rook, just like Polyglot writes it
+    promotion << 12 | (mv.from().index() as u16) << 6 | mv.to().index() as u16
+}
+
+/// Writes an encoded move like a UCI move, castling as king takes rook.
+pub fn move_text(raw: u16) -> String {
+    let square = |bits: u16| notation::bit_to_algebraic_square((bits & 63) as u8);
+    let promotion = match (raw >> 12) & 7 {
+        0 => String::new(),
+        piece => PROMOTION_PIECES
+            .get(piece as usize - 1)
+            .map_or("?".to_string(), |piece| piece.to_char().to_string()),
+    };
+    format!("{}{}{}", square(raw >> 6), square(raw), promotion)
+}
+
+/// The legal move a Polyglot move stands for. Polyglot writes castling as the king
+/// taking its own rook, e.g. `e1h1` for white castling kingside.
+pub fn decode_move(game: &mut Game, raw: u16) -> Option<Move> {
+    generate_legal_moves(game)
+        .into_iter()
+        .find(|mv| encode_move(*mv) == raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::keys::polyglot_key;
+    use super::{Book, BookSettings, Selection};
+    use crate::chess::game::Game;
+    use crate::chess::moves::parse_uci_move;
+    use crate::utils::random::Random;
+
+    fn play(moves: &[&str]) -> Game {
+        let mut game = Game::new_classical();
+        for text in moves {
+            let mv = parse_uci_move(&mut game, text).unwrap();
+            game.make_move(mv);
+        }
+        game
+    }
+
+    #[test]
+    fn keys_match_the_specification() {
+        for (moves, key) in [
+            (&[][..], 0x463b96181691fc9c),
+            (&["e2e4"][..], 0x823c9b50fd114196),
+            (&["e2e4", "d7d5"][..], 0x0756b94461c50fb0),
+            (&["e2e4", "d7d5", "e4e5"][..], 0x662fafb965db29d4),
+            (&["e2e4", "d7d5", "e4e5", "f7f5"][..], 0x22a48b5a8e47ff78),
+            (
+                &["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"][..],
+                0x652a607ca3f242c1,
+            ),
+            (
+                &["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"][..],
+                0x00fdd303c946bdd9,
+            ),
+            (
+                &["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"][..],
+                0x3c8123ea7b067637,
+            ),
+            (
+                &["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"][..],
+                0x5c3f9b829b279560,
+            ),
+        ] {
+            assert_eq!(polyglot_key(&play(moves)), key, "{:?}", moves);
+        }
+    }
+
+    #[test]
+    fn castling_is_king_takes_rook() {
+        let mut game = Game::from_fen(
+            "r3k2r/pppqbppp/2np1n2/4p3/4P3/2NP1N2/PPPQBPPP/R3K2R w KQkq - 0 1".to_string(),
+        );
+        let key = polyglot_key(&game).to_be_bytes();
+        let mut bytes = Vec::new();
+        // e1h1 with weight 3, e1a1 with weight 1
+        for (mv, weight) in [(0x0107u16, 3u16), (0x0100, 1)] {
+            bytes.extend_from_slice(&key);
+            bytes.extend_from_slice(&mv.to_be_bytes());
+            bytes.extend_from_slice(&weight.to_be_bytes());
+            bytes.extend_from_slice(&0u32.to_be_bytes());
+        }
+        let book = Book::from_bytes(&bytes).unwrap();
+
+        let moves: Vec<String> = book
+            .entries(&mut game)
+            .iter()
+            .map(|entry| entry.mv.to_string())
+            .collect();
+        assert_eq!(moves, ["e1g1", "e1c1"]);
+
+        let settings = BookSettings {
+            selection: Selection::Best,
+            ..Default::default()
+        };
+        let best = book.pick(&mut game, &settings, &mut Random::new(1));
+        assert_eq!(best.map(|mv| mv.to_string()), Some("e1g1".to_string()));
+    }
+}
@@ -1,12 +1,24 @@
 use crate::utils::bitboard::Bitboard;
 
-use super::game::Side;
+use super::game::{FenError, Side};
+use super::move_tables;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Square {
     index: u8,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Piece {
+    Pawn = 0,
+    Knight = 1,
+    Bishop = 2,
+    Rook = 3,
+    Queen = 4,
+    King = 5,
+}
+
+#[derive(Clone)]
 pub struct SideBoard {
     pawns: Bitboard,
     knights: Bitboard,
@@ -14,8 +26,11 @@ pub struct SideBoard {
     rooks: Bitboard,
     queens: Bitboard,
     kings: Bitboard,
+    /// Crazyhouse pieces in hand, by piece up to the queen.
+    pocket: [u8; 5],
 }
 
+#[derive(Clone)]
 pub struct Board {
     white: SideBoard,
     black: SideBoard,
@@ -27,7 +42,7 @@ impl Square {
     }
 
     pub fn new(index: u8) -> Self {
-        Self { index: index }
+        Self { index }
     }
 
     pub fn new_file_rank(file: u8, rank: u8) -> Self {
@@ -47,6 +62,41 @@ impl Square {
     }
 }
 
+impl Piece {
+    pub const ALL: [Piece; 6] = [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+        Piece::King,
+    ];
+
+    pub fn from_char(piece_char: char) -> Option<Self> {
+        match piece_char.to_ascii_lowercase() {
+            'p' => Some(Piece::Pawn),
+            'n' => Some(Piece::Knight),
+            'b' => Some(Piece::Bishop),
+            'r' => Some(Piece::Rook),
+            'q' => Some(Piece::Queen),
+            'k' => Some(Piece::King),
+            _ => None,
+        }
+    }
+
+    /// Lowercase piece letter, as used for black in FEN and for promotions in UCI.
+    pub fn to_char(&self) -> char {
+        match self {
+            Piece::Pawn => 'p',
+            Piece::Knight => 'n',
+            Piece::Bishop => 'b',
+            Piece::Rook => 'r',
+            Piece::Queen => 'q',
+            Piece::King => 'k',
+        }
+    }
+}
+
 impl SideBoard {
     pub fn new(
         pawns: Bitboard,
@@ -57,12 +107,13 @@ impl SideBoard {
         kings: Bitboard,
     ) -> Self {
         Self {
-            pawns: pawns,
-            knights: knights,
-            bishops: bishops,
-            rooks: rooks,
-            queens: queens,
-            kings: kings,
+            pawns,
+            knights,
+            bishops,
+            rooks,
+            queens,
+            kings,
+            pocket: [0; 5],
         }
     }
 
@@ -101,122 +152,161 @@ impl SideBoard {
         self.kings
     }
 
+    pub fn pieces(&self, piece: Piece) -> Bitboard {
+        match piece {
+            Piece::Pawn => self.pawns,
+            Piece::Knight => self.knights,
+            Piece::Bishop => self.bishops,
+            Piece::Rook => self.rooks,
+            Piece::Queen => self.queens,
+            Piece::King => self.kings,
+        }
+    }
+
+    fn pieces_mut(&mut self, piece: Piece) -> &mut Bitboard {
+        match piece {
+            Piece::Pawn => &mut self.pawns,
+            Piece::Knight => &mut self.knights,
+            Piece::Bishop => &mut self.bishops,
+            Piece::Rook => &mut self.rooks,
+            Piece::Queen => &mut self.queens,
+            Piece::King => &mut self.kings,
+        }
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        Piece::ALL
+            .into_iter()
+            .find(|piece| self.pieces(*piece).is_set(square))
+    }
+
+    pub fn add_piece(&mut self, piece: Piece, square: Square) {
+        self.pieces_mut(piece).set(square);
+    }
+
+    pub fn remove_piece(&mut self, piece: Piece, square: Square) {
+        self.pieces_mut(piece).unset(square.index());
+    }
+
+    pub fn king_square(&self) -> Option<Square> {
+        if self.kings.is_empty() {
+            None
+        } else {
+            Some(Square::new(self.kings.find_first()))
+        }
+    }
+
     pub fn occupied(&self) -> Bitboard {
         self.pawns | self.knights | self.bishops | self.rooks | self.queens | self.kings
     }
+
+    /// How many `piece`s are in hand. Always zero outside Crazyhouse.
+    pub fn pocket(&self, piece: Piece) -> u8 {
+        self.pocket[piece as usize]
+    }
+
+    pub fn pocket_is_empty(&self) -> bool {
+        self.pocket.iter().all(|count| *count == 0)
+    }
+
+    pub fn add_to_pocket(&mut self, piece: Piece) {
+        if piece == Piece::King {
+            panic!("Invalid pocket piece {:?}", piece);
+        }
+        self.pocket[piece as usize] += 1;
+    }
+
+    pub fn remove_from_pocket(&mut self, piece: Piece) {
+        if self.pocket(piece) == 0 {
+            panic!("Invalid drop of {:?} from an empty pocket", piece);
+        }
+        self.pocket[piece as usize] -= 1;
+    }
 }
 
 impl Board {
     pub fn new(white: SideBoard, black: SideBoard) -> Self {
-        Self {
-            white: white,
-            black: black,
-        }
+        Self { white, black }
     }
 
     pub fn from_fen_pieces(fen: String) -> Self {
-        let row_strings: Vec<&str> = fen.as_str().split("/").collect();
+        Self::try_from_fen_pieces(&fen).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Reads the pieces part of a FEN: eight ranks of exactly eight squares each, from
+    /// the eighth rank down.
+    pub fn try_from_fen_pieces(fen: &str) -> Result<Self, FenError> {
+        let row_strings: Vec<&str> = fen.split('/').collect();
         if row_strings.len() != 8 {
-            panic!("Invalid piece FEN {}", fen);
+            return Err(FenError::Pieces(fen.to_string()));
         }
 
-        let mut white_pawns = Bitboard::new();
-        let mut black_pawns = Bitboard::new();
-        let mut white_knights = Bitboard::new();
-        let mut black_knights = Bitboard::new();
-        let mut white_bishops = Bitboard::new();
-        let mut black_bishops = Bitboard::new();
-        let mut white_rooks = Bitboard::new();
-        let mut black_rooks = Bitboard::new();
-        let mut white_queens = Bitboard::new();
-        let mut black_queens = Bitboard::new();
-        let mut white_kings = Bitboard::new();
-        let mut black_kings = Bitboard::new();
+        let mut white_pieces = SideBoard::new_empty();
+        let mut black_pieces = SideBoard::new_empty();
 
         for rank in (0..8).rev() {
             let row_string = row_strings[7 - rank];
-            let mut file = 0 as u8;
+            let mut file = 0_u8;
             for row_char in row_string.chars() {
+                if file >= 8 {
+                    return Err(FenError::Pieces(fen.to_string()));
+                }
                 let square = Square::new_file_rank(file, rank as u8);
-                if row_char.is_numeric() {
-                    let row_number = row_char.to_digit(10);
-                    match row_number {
-                        Some(x) => file += x as u8,
-                        None => panic!("Invalid piece fen {}", fen),
-                    }
-                } else {
-                    match row_char {
-                        'p' => black_pawns.set(square),
-                        'n' => black_knights.set(square),
-                        'b' => black_bishops.set(square),
-                        'r' => black_rooks.set(square),
-                        'q' => black_queens.set(square),
-                        'k' => black_kings.set(square),
-                        'P' => white_pawns.set(square),
-                        'N' => white_knights.set(square),
-                        'B' => white_bishops.set(square),
-                        'R' => white_rooks.set(square),
-                        'Q' => white_queens.set(square),
-                        'K' => white_kings.set(square),
-                        _ => panic!("Invalid piece character {}", row_char),
-                    }
-
-                    file += 1;
+                if let Some(empty) = row_char.to_digit(10).filter(|empty| *empty > 0) {
+                    file += empty as u8;
+                    continue;
                 }
 
-                if file == 8 {
-                    break;
+                let piece = Piece::from_char(row_char).ok_or(FenError::PieceChar(row_char))?;
+                if row_char.is_ascii_uppercase() {
+                    white_pieces.add_piece(piece, square);
+                } else {
+                    black_pieces.add_piece(piece, square);
                 }
+                file += 1;
+            }
+            if file != 8 {
+                return Err(FenError::Pieces(fen.to_string()));
             }
         }
 
-        let white_pieces = SideBoard::new(
-            white_pawns,
-            white_knights,
-            white_bishops,
-            white_rooks,
-            white_queens,
-            white_kings,
-        );
-        let black_pieces = SideBoard::new(
-            black_pawns,
-            black_knights,
-            black_bishops,
-            black_rooks,
-            black_queens,
-            black_kings,
-        );
-
-        println!("Black pawns:");
-        println!("{}", black_pieces.pawns().to_string());
-        println!("Black knights:");
-        println!("{}", black_pieces.knights().to_string());
-        println!("Black bishops:");
-        println!("{}", black_pieces.bishops().to_string());
-        println!("Black rooks:");
-        println!("{}", black_pieces.rooks().to_string());
-        println!("Black queens:");
-        println!("{}", black_pieces.queens().to_string());
-        println!("Black kings:");
-        println!("{}", black_pieces.kings().to_string());
-
-        println!("White pawns:");
-        println!("{}", white_pieces.pawns().to_string());
-        println!("White knights:");
-        println!("{}", white_pieces.knights().to_string());
-        println!("White bishops:");
-        println!("{}", white_pieces.bishops().to_string());
-        println!("White rooks:");
-        println!("{}", white_pieces.rooks().to_string());
-        println!("White queens:");
-        println!("{}", white_pieces.queens().to_string());
-        println!("White kings:");
-        println!("{}", white_pieces.kings().to_string());
-
-        Self {
+        Ok(Self {
             white: white_pieces,
             black: black_pieces,
+        })
+    }
+
+    pub fn to_fen_pieces(&self) -> String {
+        let mut rows = Vec::new();
+
+        for rank in (0..8).rev() {
+            let mut row = String::new();
+            let mut empty = 0;
+            for file in 0..8 {
+                let square = Square::new_file_rank(file, rank);
+                match self.piece_at(square) {
+                    Some((side, piece)) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        let piece_char = piece.to_char();
+                        row.push(if side == Side::White {
+                            piece_char.to_ascii_uppercase()
+                        } else {
+                            piece_char
+                        });
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            rows.push(row);
         }
+
+        rows.join("/")
     }
 
     pub fn white_pieces(&self) -> &SideBoard {
@@ -235,7 +325,49 @@ impl Board {
         }
     }
 
+    pub fn side_pieces_mut(&mut self, side: Side) -> &mut SideBoard {
+        if side == Side::White {
+            &mut self.white
+        } else {
+            &mut self.black
+        }
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<(Side, Piece)> {
+        if let Some(piece) = self.white.piece_at(square) {
+            return Some((Side::White, piece));
+        }
+
+        self.black
+            .piece_at(square)
+            .map(|piece| (Side::Black, piece))
+    }
+
     pub fn occupied(&self) -> Bitboard {
         self.white.occupied() | self.black.occupied()
     }
+
+    /// All pieces of `by` that attack `square`, given the occupancy `occupied`.
+    pub fn attackers(&self, square: Square, by: Side, occupied: Bitboard) -> Bitboard {
+        let pieces = self.side_pieces(by);
+        let diagonal = pieces.bishops() | pieces.queens();
+        let straight = pieces.rooks() | pieces.queens();
+
+        (move_tables::pawn_attack_board(by.opposite(), square) & pieces.pawns())
+            | (move_tables::attack_table_knight(occupied, square) & pieces.knights())
+            | (move_tables::attack_table_king(occupied, square) & pieces.kings())
+            | (move_tables::attack_table_bishop(occupied, square) & diagonal)
+            | (move_tables::attack_table_rook(occupied, square) & straight)
+    }
+
+    pub fn is_square_attacked(&self, square: Square, by: Side) -> bool {
+        !self.attackers(square, by, self.occupied()).is_empty()
+    }
+
+    pub fn is_in_check(&self, side: Side) -> bool {
+        match self.side_pieces(side).king_square() {
+            Some(king_square) => self.is_square_attacked(king_square, side.opposite()),
+            None => false,
+        }
+    }
 }
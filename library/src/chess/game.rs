@@ -1,20 +1,141 @@
 use crate::chess::board::Board;
 use crate::chess::board::SideBoard;
+use crate::eval::packed::PackedScore;
+use crate::eval::psqt;
+#[cfg(feature = "nnue")]
+use crate::nnue::{accumulator::Accumulators, network::Network};
+use crate::utils::bitboard::Bitboard;
 use crate::utils::notation;
+#[cfg(feature = "nnue")]
+use std::sync::Arc;
 
+use super::board::Piece;
 use super::board::Square;
+use super::move_tables;
+use super::moves::Move;
+use super::setup;
+use super::zobrist;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Variant {
     Classical,
+    /// Fischer Random: the back rank is shuffled, and castling moves are written as the
+    /// king taking its own rook.
+    Chess960,
+    /// Like Chess960, but with each side's back rank shuffled on its own.
+    DoubleChess960,
+    /// Captured pieces go to the capturer's pocket, and can be dropped back on the
+    /// board instead of moving.
+    Crazyhouse,
+    /// Captures explode everything but pawns around the target square, along with the
+    /// capturing piece. Exploding the enemy king wins.
+    Atomic,
+    /// Giving check for the third time wins.
+    ThreeCheck,
+    /// Bringing the king to one of the four center squares wins.
+    KingOfTheHill,
+    /// Losing chess: captures are compulsory, the king is an ordinary piece, and losing
+    /// every piece or being stalemated wins.
+    Antichess,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+impl Variant {
+    /// Whether castling is written as the king taking its rook.
+    pub fn is_chess960(&self) -> bool {
+        matches!(self, Variant::Chess960 | Variant::DoubleChess960)
+    }
+
+    /// Whether a side that can capture has to, as in Antichess.
+    pub fn captures_are_forced(&self) -> bool {
+        matches!(self, Variant::Antichess)
+    }
+
+    /// Whether a side without legal moves wins rather than draws when not in check.
+    pub fn stalemate_wins(&self) -> bool {
+        matches!(self, Variant::Antichess)
+    }
+
+    /// Whether the game is played by the classical rules, whatever the start position.
+    /// Tablebases, opening books and endgame knowledge only apply then.
+    pub fn has_standard_rules(&self) -> bool {
+        matches!(
+            self,
+            Variant::Classical | Variant::Chess960 | Variant::DoubleChess960
+        )
+    }
+}
+
+/// d4, e4, d5 and e5, the hill of King of the Hill.
+const CENTER: u64 = 0x0000001818000000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Side {
     White = 0,
     Black = 1,
 }
 
+/// Why a FEN couldn't be read.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum FenError {
+    /// A FEN has six fields, or seven with Three-check counts.
+    FieldCount(usize),
+    ActiveColor(String),
+    /// A Crazyhouse pocket opened with `[` but never closed.
+    Pocket(String),
+    PocketPiece(char),
+    /// The pieces don't make eight ranks of eight squares.
+    Pieces(String),
+    PieceChar(char),
+    PawnOnBackRank,
+    /// How many kings white and black have, when either isn't one.
+    Kings([usize; 2]),
+    Castling(String),
+    EnPassant(String),
+    Checks(String),
+    Clock(String),
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FenError::FieldCount(count) => write!(f, "Invalid fen: {} fields", count),
+            FenError::ActiveColor(color) => write!(f, "Invalid fen color: {}", color),
+            FenError::Pocket(pieces) => write!(f, "Invalid fen pocket: {}", pieces),
+            FenError::PocketPiece(piece) => write!(f, "Invalid fen pocket piece: {}", piece),
+            FenError::Pieces(pieces) => write!(f, "Invalid piece fen: {}", pieces),
+            FenError::PieceChar(piece) => write!(f, "Invalid piece character: {}", piece),
+            FenError::PawnOnBackRank => write!(f, "Invalid fen: pawn on the back rank"),
+            FenError::Kings([white, black]) => {
+                write!(f, "Invalid fen: {} white and {} black kings", white, black)
+            }
+            FenError::Castling(castling) => write!(f, "Invalid fen castling rights: {}", castling),
+            FenError::EnPassant(square) => write!(f, "Invalid fen en passant square: {}", square),
+            FenError::Checks(checks) => write!(f, "Invalid fen checks: {}", checks),
+            FenError::Clock(clock) => write!(f, "Invalid fen move counter: {}", clock),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+/// Everything `make_move` overwrites that can't be recomputed from the move itself.
+#[derive(Clone)]
+struct Undo {
+    mv: Option<Move>,
+    captured: Option<Piece>,
+    castle_rights: [bool; 4],
+    ep_square: Option<Square>,
+    half_move: u8,
+    hash: u64,
+    pawn_hash: u64,
+    psqt: PackedScore,
+    promoted: Bitboard,
+    /// The board before an Atomic capture, quicker to put back than the explosion.
+    board: Option<Box<Board>>,
+    checks: [u8; 2],
+}
+
+#[derive(Clone)]
 pub struct Game {
     kind: Variant,
     board: Board,
@@ -23,16 +144,62 @@ pub struct Game {
     white_castle_queenside: bool,
     black_castle_kingside: bool,
     black_castle_queenside: bool,
+    /// Home file of the rook for each castling right, in the order of `castle_hash`.
+    castle_files: [u8; 4],
     ep_square: Option<Square>,
     half_move: u8,
     full_move: u16,
+    hash: u64,
+    /// Zobrist key of the pawns alone, for the pawn hash table.
+    pawn_hash: u64,
+    /// Material and piece-square score from white's point of view, kept up to date
+    /// by `make_move` so the evaluation doesn't have to walk the board.
+    psqt: PackedScore,
+    /// Pieces that were promoted from pawns, which go back to the pocket as pawns when
+    /// captured in Crazyhouse.
+    promoted: Bitboard,
+    /// Checks given by each side, counted in Three-check only.
+    checks: [u8; 2],
+    #[cfg(feature = "nnue")]
+    accumulators: Accumulators,
+    history: Vec<Undo>,
+}
+
+impl Side {
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::White => Side::Black,
+            Side::Black => Side::White,
+        }
+    }
 }
 
 impl Game {
     pub fn from_fen(fen: String) -> Self {
-        let fen_split: Vec<&str> = fen.as_str().split(" ").collect();
+        Self::from_fen_variant(fen, Variant::Classical)
+    }
+
+    pub fn from_fen_variant(fen: String, kind: Variant) -> Self {
+        Self::try_from_fen_variant(fen, kind).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_from_fen(fen: String) -> Result<Self, FenError> {
+        Self::try_from_fen_variant(fen, Variant::Classical)
+    }
+
+    /// Reads castling rights as `KQkq`, as X-FEN, where a file letter names the rook
+    /// when it isn't the outermost one, or as Shredder-FEN, which only uses file letters.
+    /// Crazyhouse pockets follow the pieces like `[QNbp]`, and promoted pieces are
+    /// marked with a `~` after them. Three-check counts come last, as `+N+M` for the
+    /// checks white and black have given.
+    pub fn try_from_fen_variant(fen: String, kind: Variant) -> Result<Self, FenError> {
+        let mut fen_split: Vec<&str> = fen.as_str().split(' ').collect();
+        let fen_checks = match fen_split.len() {
+            7 => fen_split.pop().unwrap(),
+            _ => "+0+0",
+        };
         if fen_split.len() != 6 {
-            panic!("Invalid FEN {}", fen);
+            return Err(FenError::FieldCount(fen_split.len()));
         }
 
         let fen_pieces = fen_split[0];
@@ -45,43 +212,157 @@ impl Game {
         let active = match fen_active {
             "w" => Side::White,
             "b" => Side::Black,
-            _ => panic!("Invalid fen color: {}", fen_active),
+            _ => return Err(FenError::ActiveColor(fen_active.to_string())),
+        };
+
+        let (fen_pieces, fen_pocket) = match fen_pieces.split_once('[') {
+            Some((pieces, pocket)) => match pocket.strip_suffix(']') {
+                Some(pocket) => (pieces, pocket),
+                None => return Err(FenError::Pocket(fen_pieces.to_string())),
+            },
+            None => (fen_pieces, ""),
         };
+        let (fen_pieces, promoted) = parse_promoted(fen_pieces);
+        let mut board = Board::try_from_fen_pieces(&fen_pieces)?;
+        for pocket_char in fen_pocket.chars() {
+            let side = if pocket_char.is_ascii_uppercase() {
+                Side::White
+            } else {
+                Side::Black
+            };
+            match Piece::from_char(pocket_char) {
+                Some(piece) if piece != Piece::King => {
+                    board.side_pieces_mut(side).add_to_pocket(piece)
+                }
+                _ => return Err(FenError::PocketPiece(pocket_char)),
+            }
+        }
 
-        let mut white_castle_kingside = false;
-        let mut white_castle_queenside = false;
-        let mut black_castle_kingside = false;
-        let mut black_castle_queenside = false;
+        let back_ranks = Bitboard::from_data(0xff000000000000ff);
+        let pawns = board.white_pieces().pawns() | board.black_pieces().pawns();
+        if !(pawns & back_ranks).is_empty() {
+            return Err(FenError::PawnOnBackRank);
+        }
+        // Antichess kings are ordinary pieces, so there can be any number of them
+        let kings = [Side::White, Side::Black].map(|side| board.side_pieces(side).kings().count());
+        if kind != Variant::Antichess && kings != [1, 1] {
+            return Err(FenError::Kings(kings));
+        }
+
+        let mut castle_rights = [false; 4];
+        let mut castle_files = [7, 0, 7, 0];
         if fen_castle != "-" {
             for castle_char in fen_castle.chars() {
-                match castle_char {
-                    'K' => white_castle_kingside = true,
-                    'Q' => white_castle_queenside = true,
-                    'k' => black_castle_kingside = true,
-                    'q' => black_castle_queenside = true,
-                    _ => panic!(),
-                }
+                let side = if castle_char.is_ascii_uppercase() {
+                    Side::White
+                } else {
+                    Side::Black
+                };
+                let pieces = board.side_pieces(side);
+                let home_rank = if side == Side::White { 0 } else { 7 };
+                let king_file = pieces
+                    .king_square()
+                    .filter(|square| square.rank() == home_rank)
+                    .map_or(4, |square| square.file());
+                let rook_files = pieces
+                    .rooks()
+                    .filter(|square| square.rank() == home_rank)
+                    .map(|square| square.file());
+
+                // `K` and `Q` stand for the outermost rook on their side of the king
+                let rook_file = match castle_char.to_ascii_lowercase() {
+                    'k' => rook_files
+                        .filter(|file| *file > king_file)
+                        .max()
+                        .unwrap_or(7),
+                    'q' => rook_files
+                        .filter(|file| *file < king_file)
+                        .min()
+                        .unwrap_or(0),
+                    file @ 'a'..='h' => file as u8 - b'a',
+                    _ => return Err(FenError::Castling(fen_castle.to_string())),
+                };
+                let index = 2 * side as usize + (rook_file < king_file) as usize;
+                castle_rights[index] = true;
+                castle_files[index] = rook_file;
             }
         }
 
         let ep_square = match fen_ep_square {
             "-" => None,
-            _ => Some(Square::new(notation::algebraic_square_to_bit(fen_ep_square))),
+            _ => match notation::parse_algebraic_square(fen_ep_square).map(Square::new) {
+                Some(square) if square.rank() == 2 || square.rank() == 5 => Some(square),
+                _ => return Err(FenError::EnPassant(fen_ep_square.to_string())),
+            },
         };
-        let half_move: u8 = fen_half_move.parse().unwrap();
-        let full_move: u16 = fen_full_move.parse().unwrap();
+        let checks: Vec<u8> = fen_checks
+            .split('+')
+            .skip(1)
+            .filter_map(|count| count.parse().ok())
+            .filter(|count| *count <= 3)
+            .collect();
+        if !fen_checks.starts_with('+') || checks.len() != 2 {
+            return Err(FenError::Checks(fen_checks.to_string()));
+        }
+        let half_move: u8 = fen_half_move
+            .parse()
+            .map_err(|_| FenError::Clock(fen_half_move.to_string()))?;
+        let full_move: u16 = fen_full_move
+            .parse()
+            .map_err(|_| FenError::Clock(fen_full_move.to_string()))?;
 
-        Self {
-            kind: Variant::Classical,
-            board: Board::from_fen_pieces(fen_pieces.to_string()),
-            active: active,
-            white_castle_kingside: white_castle_kingside,
-            white_castle_queenside: white_castle_queenside,
-            black_castle_kingside: black_castle_kingside,
-            black_castle_queenside: black_castle_queenside,
-            ep_square: ep_square,
-            half_move: half_move,
-            full_move: full_move,
+        let mut game = Self {
+            kind,
+            board,
+            active,
+            white_castle_kingside: castle_rights[0],
+            white_castle_queenside: castle_rights[1],
+            black_castle_kingside: castle_rights[2],
+            black_castle_queenside: castle_rights[3],
+            castle_files,
+            ep_square,
+            half_move,
+            full_move,
+            hash: 0,
+            pawn_hash: 0,
+            psqt: PackedScore::ZERO,
+            promoted,
+            checks: [checks[0], checks[1]],
+            #[cfg(feature = "nnue")]
+            accumulators: Accumulators::default(),
+            history: Vec::new(),
+        };
+        game.hash = game.compute_hash();
+        game.pawn_hash = game.compute_pawn_hash();
+        game.psqt = game.compute_psqt();
+        #[cfg(feature = "nnue")]
+        {
+            game.accumulators = Accumulators::new(crate::nnue::current_network(), &game.board);
+        }
+
+        Ok(game)
+    }
+
+    /// The usual start position of `kind`, which is the classical one for Chess960.
+    pub fn new_variant(kind: Variant) -> Self {
+        match kind {
+            Variant::Chess960 | Variant::DoubleChess960 => Self::new_chess960(518),
+            Variant::Crazyhouse => Self::from_fen_variant(
+                String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1"),
+                kind,
+            ),
+            Variant::Antichess => Self::from_fen_variant(
+                String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1"),
+                kind,
+            ),
+            Variant::ThreeCheck => Self::from_fen_variant(
+                String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +0+0"),
+                kind,
+            ),
+            _ => Self::from_fen_variant(
+                String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+                kind,
+            ),
         }
     }
 
@@ -91,8 +372,52 @@ impl Game {
         ))
     }
 
+    /// Chess960 start position number `index`, 0 to 959, as numbered by Scharnagl.
+    /// Position 518 is the classical one.
+    pub fn new_chess960(index: u32) -> Self {
+        let back_rank = setup::chess960_back_rank(index);
+        Self::from_back_ranks(&back_rank, &back_rank)
+    }
+
+    /// The start position with these back ranks, each written from the a to the h file
+    /// like `RNBQKBNR`, in either case. Each side can castle with the outermost rook on
+    /// either side of its king. Ranks that differ make a `DoubleChess960` game.
+    ///
+    /// # Panics
+    ///
+    /// If either rank isn't one king, one queen, two rooks, two knights and two bishops
+    /// on opposite colors, see `setup::is_legal_back_rank`.
+    pub fn from_back_ranks(white: &str, black: &str) -> Self {
+        for rank in [white, black] {
+            if !setup::is_legal_back_rank(rank) {
+                panic!("Invalid back rank {}", rank);
+            }
+        }
+
+        let white = white.to_ascii_uppercase();
+        let black = black.to_ascii_lowercase();
+        let mut castle = setup::castling_files(&white).to_ascii_uppercase();
+        castle.push_str(&setup::castling_files(&black));
+        if castle.is_empty() {
+            castle.push('-');
+        }
+        let kind = if white.eq_ignore_ascii_case(&black) {
+            Variant::Chess960
+        } else {
+            Variant::DoubleChess960
+        };
+
+        Self::from_fen_variant(
+            format!(
+                "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {} - 0 1",
+                black, white, castle
+            ),
+            kind,
+        )
+    }
+
     pub fn new_empty() -> Self {
-        Self {
+        let mut game = Self {
             kind: Variant::Classical,
             board: Board::new(SideBoard::new_empty(), SideBoard::new_empty()),
             active: Side::White,
@@ -100,10 +425,107 @@ impl Game {
             white_castle_queenside: false,
             black_castle_kingside: false,
             black_castle_queenside: false,
+            castle_files: [7, 0, 7, 0],
             ep_square: None,
             half_move: 0,
             full_move: 1,
+            hash: 0,
+            pawn_hash: 0,
+            psqt: PackedScore::ZERO,
+            promoted: Bitboard::new(),
+            checks: [0; 2],
+            #[cfg(feature = "nnue")]
+            accumulators: Accumulators::default(),
+            history: Vec::new(),
+        };
+        game.hash = game.compute_hash();
+        game.pawn_hash = game.compute_pawn_hash();
+        game.psqt = game.compute_psqt();
+        #[cfg(feature = "nnue")]
+        {
+            game.accumulators = Accumulators::new(crate::nnue::current_network(), &game.board);
+        }
+
+        game
+    }
+
+    /// Castling rights are written as X-FEN, which is plain `KQkq` for classical games.
+    /// Crazyhouse games also write their pockets and promoted pieces, and Three-check
+    /// games the checks given.
+    pub fn to_fen(&self) -> String {
+        let mut castle = String::new();
+        for (index, right) in self.castle_rights_array().into_iter().enumerate() {
+            if !right {
+                continue;
+            }
+            let side = if index < 2 { Side::White } else { Side::Black };
+            let kingside = index % 2 == 0;
+            let file = self.castle_files[index];
+            let home_rank = if side == Side::White { 0 } else { 7 };
+            let outermost = !self.board.side_pieces(side).rooks().any(|square| {
+                square.rank() == home_rank
+                    && if kingside {
+                        square.file() > file
+                    } else {
+                        square.file() < file
+                    }
+            });
+
+            let castle_char = match (outermost, kingside) {
+                (true, true) => 'k',
+                (true, false) => 'q',
+                (false, _) => (b'a' + file) as char,
+            };
+            castle.push(if side == Side::White {
+                castle_char.to_ascii_uppercase()
+            } else {
+                castle_char
+            });
+        }
+        if castle.is_empty() {
+            castle.push('-');
+        }
+
+        let ep_square = match self.ep_square {
+            Some(square) => notation::bit_to_algebraic_square(square.index()),
+            None => String::from("-"),
+        };
+
+        let mut pieces = self.board.to_fen_pieces();
+        if self.kind == Variant::Crazyhouse {
+            pieces = mark_promoted(&pieces, self.promoted);
+            pieces.push('[');
+            for side in [Side::White, Side::Black] {
+                for piece in Piece::ALL.into_iter().rev() {
+                    let count = match piece {
+                        Piece::King => 0,
+                        _ => self.board.side_pieces(side).pocket(piece),
+                    };
+                    let piece_char = if side == Side::White {
+                        piece.to_char().to_ascii_uppercase()
+                    } else {
+                        piece.to_char()
+                    };
+                    pieces.extend(std::iter::repeat_n(piece_char, count as usize));
+                }
+            }
+            pieces.push(']');
         }
+
+        let mut fen = format!(
+            "{} {} {} {} {} {}",
+            pieces,
+            if self.active == Side::White { "w" } else { "b" },
+            castle,
+            ep_square,
+            self.half_move,
+            self.full_move
+        );
+        if self.kind == Variant::ThreeCheck {
+            fen.push_str(&format!(" +{}+{}", self.checks[0], self.checks[1]));
+        }
+
+        fen
     }
 
     pub fn kind(&self) -> Variant {
@@ -121,4 +543,1209 @@ impl Game {
     pub fn ep_square(&self) -> Option<Square> {
         self.ep_square
     }
+
+    pub fn half_move(&self) -> u8 {
+        self.half_move
+    }
+
+    pub fn full_move(&self) -> u16 {
+        self.full_move
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn pawn_hash(&self) -> u64 {
+        self.pawn_hash
+    }
+
+    pub fn psqt(&self) -> PackedScore {
+        self.psqt
+    }
+
+    /// How many checks `side` has given in Three-check.
+    pub fn checks(&self, side: Side) -> u8 {
+        self.checks[side as usize]
+    }
+
+    #[cfg(feature = "nnue")]
+    pub fn accumulators(&self) -> &Accumulators {
+        &self.accumulators
+    }
+
+    /// Switches this game to `network` (or back to the hand-crafted evaluation),
+    /// rebuilding the accumulators for every position still in the history.
+    #[cfg(feature = "nnue")]
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        let moves: Vec<Option<Move>> = self.history.iter().map(|undo| undo.mv).collect();
+        for _ in 0..moves.len() {
+            self.unmake_move();
+        }
+
+        self.accumulators = Accumulators::new(network, &self.board);
+        for mv in moves {
+            match mv {
+                Some(mv) => self.make_move(mv),
+                None => self.make_null_move(),
+            }
+        }
+    }
+
+    /// Number of moves made with `make_move` that can still be unmade.
+    pub fn ply(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn last_move(&self) -> Option<Move> {
+        self.history.last().and_then(|undo| undo.mv)
+    }
+
+    /// (kingside, queenside) castling rights for `side`.
+    pub fn castle_rights(&self, side: Side) -> (bool, bool) {
+        if side == Side::White {
+            (self.white_castle_kingside, self.white_castle_queenside)
+        } else {
+            (self.black_castle_kingside, self.black_castle_queenside)
+        }
+    }
+
+    /// Where the rook castling with the king towards `kingside` starts.
+    pub fn castle_rook_square(&self, side: Side, kingside: bool) -> Square {
+        let index = 2 * side as usize + !kingside as usize;
+        let home_rank = if side == Side::White { 0 } else { 7 };
+        Square::new_file_rank(self.castle_files[index], home_rank)
+    }
+
+    pub fn is_in_check(&self) -> bool {
+        self.board
+            .side_pieces(self.active)
+            .king_square()
+            .is_some_and(|square| self.is_attacked_for_king(self.active, square))
+    }
+
+    /// Whether `side`'s king would be in check on `square`. In Atomic it never is next
+    /// to the enemy king, since capturing it would blow up both kings, and in Antichess
+    /// there's no check at all.
+    pub fn is_attacked_for_king(&self, side: Side, square: Square) -> bool {
+        self.is_attacked_for_king_through(side, square, self.board.occupied())
+    }
+
+    /// Like `is_attacked_for_king`, with sliders only blocked by the pieces in `occupied`.
+    pub fn is_attacked_for_king_through(
+        &self,
+        side: Side,
+        square: Square,
+        occupied: Bitboard,
+    ) -> bool {
+        if self.kind == Variant::Antichess {
+            return false;
+        }
+        if self.kind == Variant::Atomic {
+            let enemy_king = self.board.side_pieces(side.opposite()).kings();
+            if !(move_tables::attack_table_king(self.board.occupied(), square) & enemy_king)
+                .is_empty()
+            {
+                return false;
+            }
+        }
+        !self
+            .board
+            .attackers(square, side.opposite(), occupied)
+            .is_empty()
+    }
+
+    /// Whether `side` may leave the position like this after its move: its king is
+    /// not in check, and in Atomic still on the board unless the enemy king exploded.
+    pub fn is_king_safe(&self, side: Side) -> bool {
+        let Some(king_square) = self.board.side_pieces(side).king_square() else {
+            return self.kind != Variant::Atomic;
+        };
+        if self.kind == Variant::Atomic
+            && self.board.side_pieces(side.opposite()).kings().is_empty()
+        {
+            return true;
+        }
+        !self.is_attacked_for_king(side, king_square)
+    }
+
+    /// Whether the side to move has already lost by the rules of the variant, before any
+    /// question of mate: in Atomic when its king has exploded, in Three-check after the
+    /// third check, and in King of the Hill when the enemy king reached the center.
+    pub fn is_variant_loss(&self) -> bool {
+        let opponent = self.active.opposite();
+        match self.kind {
+            Variant::Atomic => self.board.side_pieces(self.active).kings().is_empty(),
+            Variant::ThreeCheck => self.checks[opponent as usize] >= 3,
+            Variant::KingOfTheHill => {
+                !(self.board.side_pieces(opponent).kings() & Bitboard::from_data(CENTER)).is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    fn castle_rights_array(&self) -> [bool; 4] {
+        [
+            self.white_castle_kingside,
+            self.white_castle_queenside,
+            self.black_castle_kingside,
+            self.black_castle_queenside,
+        ]
+    }
+
+    fn set_castle_rights_array(&mut self, rights: [bool; 4]) {
+        self.white_castle_kingside = rights[0];
+        self.white_castle_queenside = rights[1];
+        self.black_castle_kingside = rights[2];
+        self.black_castle_queenside = rights[3];
+    }
+
+    fn castle_hash(rights: [bool; 4]) -> u64 {
+        let mut hash = 0;
+        for (index, right) in rights.into_iter().enumerate() {
+            if right {
+                hash ^= zobrist::castling_key(index);
+            }
+        }
+
+        hash
+    }
+
+    fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for side in [Side::White, Side::Black] {
+            let pieces = self.board.side_pieces(side);
+            for piece in Piece::ALL {
+                for square in pieces.pieces(piece) {
+                    hash ^= zobrist::piece_key(side, piece, square);
+                }
+            }
+        }
+
+        for side in [Side::White, Side::Black] {
+            let pieces = self.board.side_pieces(side);
+            for piece in Piece::ALL.into_iter().filter(|piece| *piece != Piece::King) {
+                for count in 1..=pieces.pocket(piece) {
+                    hash ^= zobrist::pocket_key(side, piece, count);
+                }
+            }
+        }
+
+        for side in [Side::White, Side::Black] {
+            for count in 1..=self.checks[side as usize] {
+                hash ^= zobrist::check_key(side, count);
+            }
+        }
+
+        hash ^= Self::castle_hash(self.castle_rights_array());
+        if let Some(ep_square) = self.ep_square {
+            hash ^= zobrist::ep_key(ep_square);
+        }
+        if self.active == Side::Black {
+            hash ^= zobrist::side_key();
+        }
+
+        hash
+    }
+
+    fn compute_pawn_hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for side in [Side::White, Side::Black] {
+            for square in self.board.side_pieces(side).pawns() {
+                hash ^= zobrist::piece_key(side, Piece::Pawn, square);
+            }
+        }
+
+        hash
+    }
+
+    fn compute_psqt(&self) -> PackedScore {
+        let mut score = PackedScore::ZERO;
+
+        for side in [Side::White, Side::Black] {
+            let pieces = self.board.side_pieces(side);
+            for piece in Piece::ALL {
+                for square in pieces.pieces(piece) {
+                    score += psqt::psqt(side, piece, square);
+                }
+                if piece != Piece::King {
+                    for _ in 0..pieces.pocket(piece) {
+                        score += psqt::pocket(side, piece);
+                    }
+                }
+            }
+        }
+
+        score
+    }
+
+    fn put_piece(&mut self, side: Side, piece: Piece, square: Square) {
+        self.board.side_pieces_mut(side).add_piece(piece, square);
+        self.hash ^= zobrist::piece_key(side, piece, square);
+        if piece == Piece::Pawn {
+            self.pawn_hash ^= zobrist::piece_key(side, piece, square);
+        }
+        self.psqt += psqt::psqt(side, piece, square);
+        #[cfg(feature = "nnue")]
+        self.accumulators
+            .update(&self.board, side, piece, square, true);
+    }
+
+    fn take_piece(&mut self, side: Side, piece: Piece, square: Square) {
+        self.board.side_pieces_mut(side).remove_piece(piece, square);
+        self.hash ^= zobrist::piece_key(side, piece, square);
+        if piece == Piece::Pawn {
+            self.pawn_hash ^= zobrist::piece_key(side, piece, square);
+        }
+        self.psqt -= psqt::psqt(side, piece, square);
+        #[cfg(feature = "nnue")]
+        self.accumulators
+            .update(&self.board, side, piece, square, false);
+    }
+
+    fn put_in_pocket(&mut self, side: Side, piece: Piece) {
+        let pieces = self.board.side_pieces_mut(side);
+        pieces.add_to_pocket(piece);
+        self.hash ^= zobrist::pocket_key(side, piece, pieces.pocket(piece));
+        self.psqt += psqt::pocket(side, piece);
+    }
+
+    fn take_from_pocket(&mut self, side: Side, piece: Piece) {
+        let pieces = self.board.side_pieces_mut(side);
+        self.hash ^= zobrist::pocket_key(side, piece, pieces.pocket(piece));
+        pieces.remove_from_pocket(piece);
+        self.psqt -= psqt::pocket(side, piece);
+    }
+
+    /// Plays `mv`, which must be pseudolegal in the current position.
+    pub fn make_move(&mut self, mv: Move) {
+        let side = self.active;
+        let opponent = side.opposite();
+        let from = mv.from();
+        let to = mv.to();
+        let piece = match mv.dropped_piece() {
+            Some(piece) => piece,
+            None => self
+                .board
+                .side_pieces(side)
+                .piece_at(from)
+                .expect("make_move: no piece on from square"),
+        };
+
+        let captured_square = if mv.is_en_passant() {
+            Square::new_file_rank(to.file(), from.rank())
+        } else {
+            to
+        };
+        let captured = if mv.is_capture() {
+            self.board.side_pieces(opponent).piece_at(captured_square)
+        } else {
+            None
+        };
+
+        self.history.push(Undo {
+            mv: Some(mv),
+            captured,
+            castle_rights: self.castle_rights_array(),
+            ep_square: self.ep_square,
+            half_move: self.half_move,
+            hash: self.hash,
+            pawn_hash: self.pawn_hash,
+            psqt: self.psqt,
+            promoted: self.promoted,
+            board: (self.kind == Variant::Atomic && captured.is_some())
+                .then(|| Box::new(self.board.clone())),
+            checks: self.checks,
+        });
+        #[cfg(feature = "nnue")]
+        self.accumulators.push();
+
+        if let Some(captured) = captured {
+            self.take_piece(opponent, captured, captured_square);
+            if self.kind == Variant::Crazyhouse {
+                let pocketed = if self.promoted.is_set(captured_square) {
+                    Piece::Pawn
+                } else {
+                    captured
+                };
+                self.put_in_pocket(side, pocketed);
+            }
+            self.promoted.unset(captured_square.index());
+        }
+
+        if mv.is_drop() {
+            self.take_from_pocket(side, piece);
+            self.put_piece(side, piece, to);
+        } else if mv.is_castle() {
+            // the king and rook can land on each other's squares, so lift both first
+            let (king_to, rook_to) = castle_destinations(mv);
+            self.take_piece(side, Piece::King, from);
+            self.take_piece(side, Piece::Rook, to);
+            self.put_piece(side, Piece::King, king_to);
+            self.put_piece(side, Piece::Rook, rook_to);
+        } else {
+            self.take_piece(side, piece, from);
+            self.put_piece(side, mv.promotion().unwrap_or(piece), to);
+            if mv.promotion().is_some() || self.promoted.is_set(from) {
+                self.promoted.unset(from.index());
+                self.promoted.set(to);
+            }
+        }
+
+        let blast = if self.kind == Variant::Atomic && captured.is_some() {
+            self.explode(to)
+        } else {
+            Bitboard::new()
+        };
+
+        // Moving the king loses both rights, and any move touching a rook home square
+        // loses the matching one.
+        let old_rights = self.castle_rights_array();
+        let mut rights = old_rights;
+        if piece == Piece::King {
+            rights[2 * side as usize] = false;
+            rights[2 * side as usize + 1] = false;
+        }
+        for (index, right) in rights.iter_mut().enumerate() {
+            let home_rank = if index < 2 { 0 } else { 7 };
+            let rook_square = Square::new_file_rank(self.castle_files[index], home_rank);
+            if from == rook_square || to == rook_square || blast.is_set(rook_square) {
+                *right = false;
+            }
+        }
+        // an exploded king takes both of its rights with it
+        for exploded in [Side::White, Side::Black] {
+            if !blast.is_empty() && self.board.side_pieces(exploded).kings().is_empty() {
+                rights[2 * exploded as usize] = false;
+                rights[2 * exploded as usize + 1] = false;
+            }
+        }
+        if rights != old_rights {
+            self.hash ^= Self::castle_hash(old_rights) ^ Self::castle_hash(rights);
+            self.set_castle_rights_array(rights);
+        }
+
+        if let Some(ep_square) = self.ep_square {
+            self.hash ^= zobrist::ep_key(ep_square);
+        }
+        self.ep_square = None;
+        if piece == Piece::Pawn && (from.rank() as i8 - to.rank() as i8).abs() == 2 {
+            let ep_square = Square::new_file_rank(from.file(), (from.rank() + to.rank()) / 2);
+            self.ep_square = Some(ep_square);
+            self.hash ^= zobrist::ep_key(ep_square);
+        }
+
+        if piece == Piece::Pawn || captured.is_some() {
+            self.half_move = 0;
+        } else {
+            self.half_move = self.half_move.saturating_add(1);
+        }
+        if side == Side::Black {
+            self.full_move += 1;
+        }
+
+        self.active = opponent;
+        self.hash ^= zobrist::side_key();
+        if self.kind == Variant::ThreeCheck && self.checks[side as usize] < 3 && self.is_in_check()
+        {
+            self.checks[side as usize] += 1;
+            self.hash ^= zobrist::check_key(side, self.checks[side as usize]);
+        }
+        #[cfg(feature = "nnue")]
+        self.accumulators.finish(&self.board);
+    }
+
+    /// Blows up the capturing piece on `square` and every piece around it but pawns,
+    /// as Atomic captures do. Returns the squares that were cleared.
+    fn explode(&mut self, square: Square) -> Bitboard {
+        let mut blast = Bitboard::from_square(square);
+        let pawns = self.board.white_pieces().pawns() | self.board.black_pieces().pawns();
+        blast |= move_tables::attack_table_king(self.board.occupied(), square)
+            & self.board.occupied()
+            & !pawns;
+
+        for square in blast {
+            if let Some((side, piece)) = self.board.piece_at(square) {
+                self.take_piece(side, piece, square);
+            }
+        }
+        self.promoted &= !blast;
+
+        blast
+    }
+
+    /// Takes back the last move played with `make_move` or `make_null_move`.
+    pub fn unmake_move(&mut self) {
+        let undo = self.history.pop().expect("unmake_move: no move to unmake");
+        #[cfg(feature = "nnue")]
+        self.accumulators.pop();
+
+        self.active = self.active.opposite();
+        let side = self.active;
+        if side == Side::Black {
+            self.full_move -= 1;
+        }
+
+        if let Some(board) = undo.board {
+            self.board = *board;
+        } else if let Some(mv) = undo.mv {
+            let from = mv.from();
+            let to = mv.to();
+            let pieces = self.board.side_pieces_mut(side);
+            if let Some(piece) = mv.dropped_piece() {
+                pieces.remove_piece(piece, to);
+                pieces.add_to_pocket(piece);
+            } else if mv.is_castle() {
+                let (king_to, rook_to) = castle_destinations(mv);
+                pieces.remove_piece(Piece::King, king_to);
+                pieces.remove_piece(Piece::Rook, rook_to);
+                pieces.add_piece(Piece::King, from);
+                pieces.add_piece(Piece::Rook, to);
+            } else {
+                let moved = pieces
+                    .piece_at(to)
+                    .expect("unmake_move: no piece on to square");
+                let piece = if mv.promotion().is_some() {
+                    Piece::Pawn
+                } else {
+                    moved
+                };
+                pieces.remove_piece(moved, to);
+                pieces.add_piece(piece, from);
+            }
+
+            if let Some(captured) = undo.captured {
+                let captured_square = if mv.is_en_passant() {
+                    Square::new_file_rank(to.file(), from.rank())
+                } else {
+                    to
+                };
+                self.board
+                    .side_pieces_mut(side.opposite())
+                    .add_piece(captured, captured_square);
+                if self.kind == Variant::Crazyhouse {
+                    let pocketed = if undo.promoted.is_set(captured_square) {
+                        Piece::Pawn
+                    } else {
+                        captured
+                    };
+                    self.board
+                        .side_pieces_mut(side)
+                        .remove_from_pocket(pocketed);
+                }
+            }
+        }
+
+        self.set_castle_rights_array(undo.castle_rights);
+        self.ep_square = undo.ep_square;
+        self.half_move = undo.half_move;
+        self.hash = undo.hash;
+        self.pawn_hash = undo.pawn_hash;
+        self.psqt = undo.psqt;
+        self.promoted = undo.promoted;
+        self.checks = undo.checks;
+    }
+
+    /// Passes the turn without moving, for null move pruning.
+    pub fn make_null_move(&mut self) {
+        self.history.push(Undo {
+            mv: None,
+            captured: None,
+            castle_rights: self.castle_rights_array(),
+            ep_square: self.ep_square,
+            half_move: self.half_move,
+            hash: self.hash,
+            pawn_hash: self.pawn_hash,
+            psqt: self.psqt,
+            promoted: self.promoted,
+            board: None,
+            checks: self.checks,
+        });
+        #[cfg(feature = "nnue")]
+        self.accumulators.push();
+
+        if let Some(ep_square) = self.ep_square {
+            self.hash ^= zobrist::ep_key(ep_square);
+        }
+        self.ep_square = None;
+        self.half_move = self.half_move.saturating_add(1);
+        if self.active == Side::Black {
+            self.full_move += 1;
+        }
+        self.active = self.active.opposite();
+        self.hash ^= zobrist::side_key();
+    }
+
+    /// True if the current position already occurred since the last irreversible move.
+    pub fn is_repetition(&self) -> bool {
+        let reversible = (self.half_move as usize).min(self.history.len());
+        self.history
+            .iter()
+            .rev()
+            .take(reversible)
+            .skip(1)
+            .step_by(2)
+            .any(|undo| undo.hash == self.hash)
+    }
+
+    /// True if any position since the last irreversible move occurred twice.
+    pub fn has_repeated(&self) -> bool {
+        let reversible = (self.half_move as usize).min(self.history.len());
+        let mut hashes: Vec<u64> = self
+            .history
+            .iter()
+            .rev()
+            .take(reversible)
+            .map(|undo| undo.hash)
+            .collect();
+        hashes.push(self.hash);
+        hashes.sort_unstable();
+        hashes.windows(2).any(|pair| pair[0] == pair[1])
+    }
+
+    /// Fifty move rule, repetition, or material that can't mate on either side.
+    pub fn is_draw(&self) -> bool {
+        self.half_move >= 100 || self.is_repetition() || self.is_insufficient_material()
+    }
+
+    pub fn is_insufficient_material(&self) -> bool {
+        let white = self.board.white_pieces();
+        let black = self.board.black_pieces();
+        if !white.pocket_is_empty() || !black.pocket_is_empty() {
+            return false;
+        }
+        match self.kind {
+            // a king can always walk to the center, and anything can be given away
+            Variant::KingOfTheHill | Variant::Antichess => return false,
+            // any piece at all can give checks
+            Variant::ThreeCheck => return (white.occupied() | black.occupied()).count() <= 2,
+            _ => {}
+        }
+        let heavy = white.pawns() | white.rooks() | white.queens() | black.pawns();
+        if !(heavy | black.rooks() | black.queens()).is_empty() {
+            return false;
+        }
+
+        let minors = white.knights() | white.bishops() | black.knights() | black.bishops();
+        minors.count() <= 1
+    }
+}
+
+/// Strips the `~` marking promoted pieces from the pieces part of a FEN, returning the
+/// squares it marked.
+fn parse_promoted(fen_pieces: &str) -> (String, Bitboard) {
+    let mut promoted = Bitboard::new();
+    let mut pieces = String::new();
+    let (mut file, mut rank) = (0u8, 7u8);
+    for piece_char in fen_pieces.chars() {
+        match piece_char {
+            '~' if (1..=8).contains(&file) => {
+                promoted.set(Square::new_file_rank(file - 1, rank));
+                continue;
+            }
+            '/' => {
+                file = 0;
+                rank = rank.saturating_sub(1);
+            }
+            _ => match piece_char.to_digit(10) {
+                Some(empty) => file = file.saturating_add(empty as u8),
+                None => file = file.saturating_add(1),
+            },
+        }
+        pieces.push(piece_char);
+    }
+
+    (pieces, promoted)
+}
+
+/// Marks the `promoted` pieces in the pieces part of a FEN with a `~` after them.
+fn mark_promoted(fen_pieces: &str, promoted: Bitboard) -> String {
+    let mut pieces = String::new();
+    let (mut file, mut rank) = (0u8, 7u8);
+    for piece_char in fen_pieces.chars() {
+        pieces.push(piece_char);
+        match piece_char {
+            '/' => {
+                file = 0;
+                rank = rank.saturating_sub(1);
+            }
+            _ => match piece_char.to_digit(10) {
+                Some(empty) => file += empty as u8,
+                None => {
+                    if promoted.is_set(Square::new_file_rank(file, rank)) {
+                        pieces.push('~');
+                    }
+                    file += 1;
+                }
+            },
+        }
+    }
+
+    pieces
+}
+
+/// Where the king and rook end up after castling, which is written as the king moving
+/// onto its rook: the g and f files on the kingside, c and d on the queenside.
+pub fn castle_destinations(mv: Move) -> (Square, Square) {
+    let rank = mv.from().rank();
+    let (king_file, rook_file) = if mv.to().file() > mv.from().file() {
+        (6, 5)
+    } else {
+        (2, 3)
+    };
+    (
+        Square::new_file_rank(king_file, rank),
+        Square::new_file_rank(rook_file, rank),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FenError, Game, Side, Variant};
+    use crate::chess::moves::{generate_legal_moves, parse_san_move, parse_uci_move, san_move};
+    use crate::utils::random::Random;
+
+    fn perft(game: &mut Game, depth: u32) -> u64 {
+        let moves = generate_legal_moves(game);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            game.make_move(mv);
+            nodes += perft(game, depth - 1);
+            game.unmake_move();
+        }
+        nodes
+    }
+
+    #[test]
+    fn classical_perft() {
+        // the start position and the usual perft positions 2 to 6
+        let positions: [(&str, &[u64]); 6] = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                &[20, 400, 8902, 197281],
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                &[48, 2039, 97862],
+            ),
+            (
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                &[14, 191, 2812, 43238],
+            ),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                &[6, 264, 9467],
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                &[44, 1486, 62379],
+            ),
+            (
+                "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                &[46, 2079, 89890],
+            ),
+        ];
+        for (fen, counts) in positions {
+            let mut game = Game::from_fen(fen.to_string());
+            for (depth, nodes) in (1..).zip(counts) {
+                assert_eq!(perft(&mut game, depth), *nodes, "{} depth {}", fen, depth);
+            }
+        }
+    }
+
+    #[test]
+    fn chess960_start_positions() {
+        assert_eq!(
+            Game::new_chess960(518).to_fen(),
+            Game::new_classical().to_fen()
+        );
+        assert_eq!(
+            Game::new_chess960(0).to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn chess960_castling_fields() {
+        let shredder = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let game = Game::from_fen_variant(shredder.to_string(), Variant::Chess960);
+        // both rooks are the outermost ones on their side of the king
+        assert_eq!(
+            game.to_fen(),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+        let x_fen = Game::from_fen_variant(game.to_fen(), Variant::Chess960);
+        assert_eq!(x_fen.hash(), game.hash());
+
+        // an inner rook needs its file
+        let inner = "4k3/8/8/8/8/8/8/RR2K3 w B - 0 1";
+        let game = Game::from_fen_variant(inner.to_string(), Variant::Chess960);
+        assert_eq!(game.to_fen(), inner);
+    }
+
+    #[test]
+    fn chess960_perft() {
+        // the first positions of the standard Chess960 perft suite, which have kings
+        // and rooks starting on the squares castling moves them to
+        let positions: [(&str, &[u64]); 10] = [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                &[21, 528, 12189, 326672],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                &[21, 807, 18002],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                &[20, 479, 10471, 273318],
+            ),
+            (
+                "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                &[22, 593, 13440, 382958],
+            ),
+            (
+                "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+                &[28, 1120, 31058],
+            ),
+            (
+                "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+                &[29, 899, 26578],
+            ),
+            (
+                "q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9",
+                &[30, 860, 24566],
+            ),
+            (
+                "qbn1brkr/ppp1p1p1/2n4p/3p1p2/P7/6PP/QPPPPP2/1BNNBRKR w HFhf - 0 9",
+                &[25, 635, 17054, 465806],
+            ),
+            (
+                "qnnbbrkr/1p2ppp1/2pp3p/p7/1P5P/2NP4/P1P1PPP1/Q1NBBRKR w HFhf - 0 9",
+                &[24, 572, 15243, 384260],
+            ),
+            (
+                "qn1rbbkr/ppp2p1p/1n1pp1p1/8/3P4/P6P/1PP1PPPK/QNNRBB1R w hd - 2 9",
+                &[28, 811, 23175],
+            ),
+        ];
+        for (fen, counts) in positions {
+            let mut game = Game::from_fen_variant(fen.to_string(), Variant::Chess960);
+            for (depth, nodes) in (1..).zip(counts) {
+                assert_eq!(perft(&mut game, depth), *nodes, "{} depth {}", fen, depth);
+            }
+        }
+    }
+
+    #[test]
+    fn chess960_castling_through_attacks() {
+        // the king goes from b1 to c1, the rook from a1 to d1: only the king's squares
+        // need to be safe
+        let mut game = Game::from_fen_variant(
+            "3rk3/8/8/8/8/8/8/RK6 w A - 0 1".to_string(),
+            Variant::Chess960,
+        );
+        assert!(parse_uci_move(&mut game, "b1a1").is_some());
+        let mut game = Game::from_fen_variant(
+            "2r1k3/8/8/8/8/8/8/RK6 w A - 0 1".to_string(),
+            Variant::Chess960,
+        );
+        assert!(parse_uci_move(&mut game, "b1a1").is_none());
+
+        // castling from g1 towards a1 crosses f1 to c1
+        let mut game = Game::from_fen_variant(
+            "4k3/8/8/8/8/8/5r2/R5K1 w A - 0 1".to_string(),
+            Variant::Chess960,
+        );
+        assert!(parse_uci_move(&mut game, "g1a1").is_none());
+    }
+
+    #[test]
+    fn chess960_castling_onto_the_rook() {
+        // the king stays on g1 while the rook jumps over it to f1
+        let mut game = Game::from_fen_variant(
+            "4k3/8/8/8/8/8/8/4K1R1 w G - 0 1".to_string(),
+            Variant::Chess960,
+        );
+        assert!(parse_uci_move(&mut game, "e1g1").is_some());
+        let mut game = Game::from_fen_variant(
+            "4k3/8/8/8/8/8/8/5KR1 w G - 0 1".to_string(),
+            Variant::Chess960,
+        );
+        let castle = parse_uci_move(&mut game, "f1g1").unwrap();
+        assert!(castle.is_castle());
+        assert_eq!(castle.to_string(), "f1g1");
+        game.make_move(castle);
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        game.unmake_move();
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/5KR1 w K - 0 1");
+    }
+
+    #[test]
+    fn crazyhouse_perft() {
+        let mut game = Game::new_variant(Variant::Crazyhouse);
+        for (depth, nodes) in [(1, 20), (2, 400), (3, 8902), (4, 197281), (5, 4888832)] {
+            assert_eq!(perft(&mut game, depth), nodes);
+        }
+
+        // every kind of drop, for both sides
+        let mut game = Game::from_fen_variant(
+            "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1".to_string(),
+            Variant::Crazyhouse,
+        );
+        for (depth, nodes) in [(1, 301), (2, 75353)] {
+            assert_eq!(perft(&mut game, depth), nodes);
+        }
+    }
+
+    #[test]
+    fn crazyhouse_pockets() {
+        let fen = "4k3/1P6/8/8/8/8/7K/1r6[Nr] w - - 0 1";
+        let mut game = Game::from_fen_variant(fen.to_string(), Variant::Crazyhouse);
+        assert_eq!(game.to_fen(), fen);
+
+        let mut play = |text: &str| {
+            let mv = parse_uci_move(&mut game, text).unwrap();
+            game.make_move(mv);
+            game.to_fen()
+        };
+        assert_eq!(play("b7b8q"), "1Q~2k3/8/8/8/8/8/7K/1r6[Nr] b - - 0 1");
+        // the promoted queen goes back to the pocket as a pawn
+        assert_eq!(play("b1b8"), "1r2k3/8/8/8/8/8/7K/8[Nrp] w - - 0 2");
+        assert_eq!(play("N@c7"), "1r2k3/2N5/8/8/8/8/7K/8[rp] b - - 1 2");
+
+        let restored = Game::from_fen_variant(game.to_fen(), Variant::Crazyhouse);
+        assert_eq!(restored.hash(), game.hash());
+        assert_eq!(restored.psqt(), game.psqt());
+        for _ in 0..3 {
+            game.unmake_move();
+        }
+        assert_eq!(game.to_fen(), fen);
+    }
+
+    #[test]
+    fn san_moves() {
+        let san = |fen: &str, kind: Variant, uci: &str| {
+            let mut game = Game::from_fen_variant(fen.to_string(), kind);
+            let mv = parse_uci_move(&mut game, uci).unwrap();
+            san_move(&mut game, mv)
+        };
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san(start, Variant::Classical, "e2e4"), "e4");
+        assert_eq!(san(start, Variant::Classical, "g1f3"), "Nf3");
+        let castling = "4k3/8/8/R7/8/8/8/R3K2R w KQ - 0 1";
+        assert_eq!(san(castling, Variant::Classical, "e1g1"), "O-O");
+        assert_eq!(san(castling, Variant::Classical, "e1c1"), "O-O-O");
+        assert_eq!(san(castling, Variant::Classical, "a1a3"), "R1a3");
+        let knights = "4k3/8/8/8/8/8/8/1N3NK1 w - - 0 1";
+        assert_eq!(san(knights, Variant::Classical, "b1d2"), "Nbd2");
+        assert_eq!(san(knights, Variant::Classical, "b1c3"), "Nc3");
+        let promotion = "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san(promotion, Variant::Classical, "b7b8q"), "b8=Q+");
+        let mate = "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2";
+        assert_eq!(san(mate, Variant::Classical, "d8h4"), "Qh4#");
+        let pocket = "4k3/8/8/8/8/8/8/4K3[NP] w - - 0 1";
+        assert_eq!(san(pocket, Variant::Crazyhouse, "P@e4"), "P@e4");
+        assert_eq!(san(pocket, Variant::Crazyhouse, "N@d6"), "N@d6+");
+
+        // every move reads back as itself
+        for (fen, kind) in [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                Variant::Classical,
+            ),
+            (
+                "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1",
+                Variant::Crazyhouse,
+            ),
+        ] {
+            let mut game = Game::from_fen_variant(fen.to_string(), kind);
+            for mv in generate_legal_moves(&mut game) {
+                let text = san_move(&mut game, mv);
+                assert_eq!(parse_san_move(&mut game, &text), Some(mv), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn atomic_perft() {
+        let mut game = Game::new_variant(Variant::Atomic);
+        for (depth, nodes) in [(1, 20), (2, 400), (3, 8902), (4, 197326)] {
+            assert_eq!(perft(&mut game, depth), nodes);
+        }
+
+        // positions from the usual Atomic perft suite: captures around the kings, and
+        // castling with the kings touching, where the king may stand in line with a rook
+        let positions: [(&str, &[u64]); 5] = [
+            (
+                "rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1",
+                &[40, 1238, 45237],
+            ),
+            (
+                "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1",
+                &[28, 833, 23353],
+            ),
+            ("8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1", &[18, 180, 4364, 61401]),
+            ("r3k1rR/5K2/8/8/8/8/8/8 b kq - 0 1", &[25, 282, 6753, 98729]),
+            (
+                "Rr2k1rR/3K4/3p4/8/8/8/7P/8 w kq - 0 1",
+                &[21, 465, 10631, 241478],
+            ),
+        ];
+        for (fen, counts) in positions {
+            let mut game = Game::from_fen_variant(fen.to_string(), Variant::Atomic);
+            for (depth, nodes) in (1..).zip(counts) {
+                assert_eq!(perft(&mut game, depth), *nodes, "{} depth {}", fen, depth);
+            }
+        }
+    }
+
+    #[test]
+    fn atomic_explosions() {
+        // the queen takes on d7 and blows up the black king, the knight and itself
+        let fen = "r1b1kbnr/pppnpppp/8/3Q4/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1";
+        let mut game = Game::from_fen_variant(fen.to_string(), Variant::Atomic);
+        let mv = parse_uci_move(&mut game, "d5d7").unwrap();
+        game.make_move(mv);
+        assert_eq!(
+            game.to_fen(),
+            "r4bnr/ppp1pppp/8/8/8/8/PPPPPPPP/RNB1KBNR b KQ - 0 1"
+        );
+        assert!(game.is_variant_loss());
+        game.unmake_move();
+        assert_eq!(game.to_fen(), fen);
+
+        // kings can't capture, and can stand next to each other
+        let mut game = Game::from_fen_variant(
+            "8/8/8/3kq3/4K3/8/8/8 w - - 0 1".to_string(),
+            Variant::Atomic,
+        );
+        assert!(!game.is_in_check());
+        assert!(parse_uci_move(&mut game, "e4e5").is_none());
+        assert!(parse_uci_move(&mut game, "e4d4").is_some());
+
+        // a capture next to our own king blows it up too
+        let mut game = Game::from_fen_variant(
+            "4k3/8/8/8/8/8/3n4/3QK3 w - - 0 1".to_string(),
+            Variant::Atomic,
+        );
+        assert!(parse_uci_move(&mut game, "d1d2").is_none());
+    }
+
+    #[test]
+    fn three_check_counts() {
+        let fen = "rnbqkbnr/ppp2ppp/8/3pp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3 +0+2";
+        let mut game = Game::from_fen_variant(fen.to_string(), Variant::ThreeCheck);
+        assert_eq!(game.to_fen(), fen);
+
+        let mv = parse_uci_move(&mut game, "f1b5").unwrap();
+        game.make_move(mv);
+        assert_eq!(game.checks(Side::White), 1);
+        assert!(game.to_fen().ends_with(" +1+2"));
+        assert_eq!(
+            Game::from_fen_variant(game.to_fen(), Variant::ThreeCheck).hash(),
+            game.hash()
+        );
+        game.unmake_move();
+
+        // the third check wins
+        let mv = parse_uci_move(&mut game, "d2d3").unwrap();
+        game.make_move(mv);
+        let mv = parse_uci_move(&mut game, "f8b4").unwrap();
+        game.make_move(mv);
+        assert!(game.is_variant_loss());
+    }
+
+    #[test]
+    fn king_of_the_hill() {
+        let mut game = Game::from_fen_variant(
+            "8/8/2k5/8/8/4K3/8/8 w - - 0 1".to_string(),
+            Variant::KingOfTheHill,
+        );
+        assert!(!game.is_draw());
+        let mv = parse_uci_move(&mut game, "e3d4").unwrap();
+        game.make_move(mv);
+        assert!(game.is_variant_loss());
+    }
+
+    #[test]
+    fn antichess_perft() {
+        let mut game = Game::new_variant(Variant::Antichess);
+        for (depth, nodes) in [(1, 20), (2, 400), (3, 8067), (4, 153299)] {
+            assert_eq!(perft(&mut game, depth), nodes);
+        }
+
+        // pawn races from the usual Antichess perft suite: forced captures that end the
+        // game, and promotions to every piece including the king
+        let positions: [(&str, &[u64]); 2] = [
+            ("8/1p6/8/8/8/8/P7/8 w - - 0 1", &[2, 4, 4, 3, 1, 0]),
+            (
+                "8/2p5/8/8/8/8/P7/8 w - - 0 1",
+                &[2, 4, 4, 4, 4, 4, 4, 4, 12, 36, 312, 2557, 30873],
+            ),
+        ];
+        for (fen, counts) in positions {
+            let mut game = Game::from_fen_variant(fen.to_string(), Variant::Antichess);
+            for (depth, nodes) in (1..).zip(counts) {
+                assert_eq!(perft(&mut game, depth), *nodes, "{} depth {}", fen, depth);
+            }
+        }
+    }
+
+    #[test]
+    fn antichess_rules() {
+        // the capture is forced, the king is fair game and pawns can promote to a king
+        let mut game = Game::from_fen_variant(
+            "8/1P6/8/8/8/8/5k2/4K3 w - - 0 1".to_string(),
+            Variant::Antichess,
+        );
+        assert!(!game.is_in_check());
+        let moves: Vec<String> = generate_legal_moves(&mut game)
+            .iter()
+            .map(|mv| mv.to_string())
+            .collect();
+        assert_eq!(moves, ["e1f2"]);
+
+        let mut game = Game::from_fen_variant(
+            "8/1P6/8/8/8/8/8/4k3 w - - 0 1".to_string(),
+            Variant::Antichess,
+        );
+        assert!(parse_uci_move(&mut game, "b7b8k").is_some());
+
+        // with several captures on offer any of them will do, but nothing else
+        let mut game = Game::from_fen_variant(
+            "8/8/8/1n1p4/2P1P3/8/8/R7 w - - 0 1".to_string(),
+            Variant::Antichess,
+        );
+        let mut moves: Vec<String> = generate_legal_moves(&mut game)
+            .iter()
+            .map(|mv| mv.to_string())
+            .collect();
+        moves.sort();
+        assert_eq!(moves, ["c4b5", "c4d5", "e4d5"]);
+
+        // blocked in with pieces left is a stalemate, which wins
+        let mut game = Game::from_fen_variant(
+            "8/8/8/8/8/p7/P7/8 w - - 0 1".to_string(),
+            Variant::Antichess,
+        );
+        assert!(generate_legal_moves(&mut game).is_empty());
+        assert!(game.kind().stalemate_wins());
+        assert!(!game.is_insufficient_material());
+    }
+
+    #[test]
+    fn invalid_fens() {
+        let error = |fen: &str| Game::try_from_fen(fen.to_string()).err();
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+        assert!(error(&format!("{} w KQkq - 0 1", start)).is_none());
+
+        assert_eq!(error("garbage"), Some(FenError::FieldCount(1)));
+        assert_eq!(
+            error(&format!("{} w KQkq -", start)),
+            Some(FenError::FieldCount(4))
+        );
+        assert_eq!(
+            error(&format!("{} x KQkq - 0 1", start)),
+            Some(FenError::ActiveColor("x".to_string()))
+        );
+        for pieces in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR",
+            "rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR",
+            "rnbqkbnr/pppppppp/8p/8/8/8/PPPPPPPP/RNBQKBNR",
+        ] {
+            assert_eq!(
+                error(&format!("{} w KQkq - 0 1", pieces)),
+                Some(FenError::Pieces(pieces.to_string()))
+            );
+        }
+        assert_eq!(
+            error("rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            Some(FenError::PieceChar('x'))
+        );
+        assert_eq!(
+            error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNP w Qkq - 0 1"),
+            Some(FenError::PawnOnBackRank)
+        );
+        assert_eq!(
+            error("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1"),
+            Some(FenError::Kings([1, 0]))
+        );
+        assert_eq!(
+            error(&format!("{} w KQxq - 0 1", start)),
+            Some(FenError::Castling("KQxq".to_string()))
+        );
+        for ep_square in ["e9", "e4", "i3", "e"] {
+            assert_eq!(
+                error(&format!("{} w KQkq {} 0 1", start, ep_square)),
+                Some(FenError::EnPassant(ep_square.to_string()))
+            );
+        }
+        for (half_move, full_move, clock) in
+            [("x", "1", "x"), ("0", "-1", "-1"), ("300", "1", "300")]
+        {
+            assert_eq!(
+                error(&format!("{} w KQkq - {} {}", start, half_move, full_move)),
+                Some(FenError::Clock(clock.to_string()))
+            );
+        }
+
+        // the variant fields
+        let error = |fen: &str, kind| Game::try_from_fen_variant(fen.to_string(), kind).err();
+        assert_eq!(
+            error(&format!("{}[Qp w KQkq - 0 1", start), Variant::Crazyhouse),
+            Some(FenError::Pocket(format!("{}[Qp", start)))
+        );
+        assert_eq!(
+            error(&format!("{}[Kp] w KQkq - 0 1", start), Variant::Crazyhouse),
+            Some(FenError::PocketPiece('K'))
+        );
+        for checks in ["+4+0", "+0", "0+0", "+a+b"] {
+            assert_eq!(
+                error(
+                    &format!("{} w KQkq - 0 1 {}", start, checks),
+                    Variant::ThreeCheck
+                ),
+                Some(FenError::Checks(checks.to_string()))
+            );
+        }
+        assert!(error("8/8/8/8/8/8/8/KK6 w - - 0 1", Variant::Antichess).is_none());
+    }
+
+    #[test]
+    fn incremental_psqt_matches_recompute() {
+        let mut random = Random::new(7);
+        for (fen, kind) in [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                Variant::Classical,
+            ),
+            (
+                "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R[] w KQkq - 0 1",
+                Variant::Crazyhouse,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                Variant::Atomic,
+            ),
+        ] {
+            let mut game = Game::from_fen_variant(fen.to_string(), kind);
+            for _ in 0..400 {
+                let moves = generate_legal_moves(&mut game);
+                // back up every so often, and whenever the game is over
+                if moves.is_empty() || (game.ply() > 0 && random.next_below(4) == 0) {
+                    game.unmake_move();
+                } else {
+                    game.make_move(moves[random.next_below(moves.len() as u64) as usize]);
+                }
+                assert_eq!(game.psqt(), game.compute_psqt(), "{}", game.to_fen());
+            }
+        }
+    }
 }
@@ -2,3 +2,5 @@ pub mod board;
 pub mod game;
 pub mod move_tables;
 pub mod moves;
+pub mod setup;
+pub mod zobrist;
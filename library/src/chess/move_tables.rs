@@ -20,8 +20,7 @@ lazy_static! {
 fn compute_pawn_move_tables() -> [[Bitboard; 64]; 2] {
     let mut result = [[Bitboard::new(); 64]; 2];
 
-    for side in 0..2 {
-        let mut side_result = [Bitboard::new(); 64];
+    for (side, side_result) in result.iter_mut().enumerate() {
         let direction = if side == 0 { 1 } else { -1 };
 
         for index in 0..64 {
@@ -31,15 +30,13 @@ fn compute_pawn_move_tables() -> [[Bitboard; 64]; 2] {
             let mut board = Bitboard::new();
 
             let rank_offset = rank as i8 + direction;
-            if rank_offset < 0 || rank_offset > 7 {
+            if !(0..=7).contains(&rank_offset) {
                 continue;
             }
 
             board.set(Square::new_file_rank(file, rank_offset as u8));
             side_result[index as usize] = board;
         }
-
-        result[side] = side_result;
     }
 
     result
@@ -48,8 +45,7 @@ fn compute_pawn_move_tables() -> [[Bitboard; 64]; 2] {
 fn compute_pawn_double_move_tables() -> [[Bitboard; 64]; 2] {
     let mut result = [[Bitboard::new(); 64]; 2];
 
-    for side in 0..2 {
-        let mut side_result = [Bitboard::new(); 64];
+    for (side, side_result) in result.iter_mut().enumerate() {
         let direction = if side == 0 { 2 } else { -2 };
         let double_move_rank = if side == 0 { 1 } else { 6 };
 
@@ -66,19 +62,16 @@ fn compute_pawn_double_move_tables() -> [[Bitboard; 64]; 2] {
 
             side_result[index as usize] = board;
         }
-
-        result[side] = side_result;
     }
 
     result
 }
 
 fn compute_pawn_capture_tables() -> [[Bitboard; 64]; 2] {
-    const offsets: [i8; 2] = [-1, 1];
+    const OFFSETS: [i8; 2] = [-1, 1];
     let mut result = [[Bitboard::new(); 64]; 2];
 
-    for side in 0..2 {
-        let mut side_result = [Bitboard::new(); 64];
+    for (side, side_result) in result.iter_mut().enumerate() {
         let direction = if side == 0 { 1 } else { -1 };
 
         for index in 0..64 {
@@ -87,13 +80,13 @@ fn compute_pawn_capture_tables() -> [[Bitboard; 64]; 2] {
             let rank = square.rank();
             let mut board = Bitboard::new();
 
-            for offset in offsets {
+            for offset in OFFSETS {
                 let file_offset = file as i8 + offset;
                 let rank_offset = rank as i8 + direction;
-                if file_offset < 0 || file_offset > 7 {
+                if !(0..=7).contains(&file_offset) {
                     continue;
                 }
-                if rank_offset < 0 || rank_offset > 7 {
+                if !(0..=7).contains(&rank_offset) {
                     continue;
                 }
 
@@ -102,15 +95,13 @@ fn compute_pawn_capture_tables() -> [[Bitboard; 64]; 2] {
 
             side_result[index as usize] = board;
         }
-
-        result[side] = side_result;
     }
 
     result
 }
 
 fn compute_knight_table() -> [Bitboard; 64] {
-    const offsets: [(i8, i8); 8] = [
+    const OFFSETS: [(i8, i8); 8] = [
         (-2, 1),
         (-1, 2),
         (1, 2),
@@ -129,13 +120,13 @@ fn compute_knight_table() -> [Bitboard; 64] {
         let rank = square.rank() as i8;
         let mut board = Bitboard::new();
 
-        for (file_offset, rank_offset) in offsets {
+        for (file_offset, rank_offset) in OFFSETS {
             let attack_file = file + file_offset;
             let attack_rank = rank + rank_offset;
-            if attack_file < 0 || attack_file > 7 {
+            if !(0..=7).contains(&attack_file) {
                 continue;
             }
-            if attack_rank < 0 || attack_rank > 7 {
+            if !(0..=7).contains(&attack_rank) {
                 continue;
             }
 
@@ -149,7 +140,7 @@ fn compute_knight_table() -> [Bitboard; 64] {
 }
 
 fn compute_king_table() -> [Bitboard; 64] {
-    const offsets: [(i8, i8); 8] = [
+    const OFFSETS: [(i8, i8); 8] = [
         (-1, -1),
         (-1, 0),
         (-1, 1),
@@ -168,13 +159,13 @@ fn compute_king_table() -> [Bitboard; 64] {
         let rank = square.rank() as i8;
         let mut board = Bitboard::new();
 
-        for (file_offset, rank_offset) in offsets {
+        for (file_offset, rank_offset) in OFFSETS {
             let attack_file = file + file_offset;
             let attack_rank = rank + rank_offset;
-            if attack_file < 0 || attack_file > 7 {
+            if !(0..=7).contains(&attack_file) {
                 continue;
             }
-            if attack_rank < 0 || attack_rank > 7 {
+            if !(0..=7).contains(&attack_rank) {
                 continue;
             }
 
@@ -196,17 +187,17 @@ fn compute_ray_table<const FILE_DIRECTION: i8, const RANK_DIRECTION: i8>() -> [B
         let mut rank = square.rank() as i8 + RANK_DIRECTION;
 
         loop {
-            if file < 0 || file > 7 {
+            if !(0..=7).contains(&file) {
                 break;
             }
-            if rank < 0 || rank > 7 {
+            if !(0..=7).contains(&rank) {
                 break;
             }
 
             board.set(Square::new_file_rank(file as u8, rank as u8));
 
-            file = file + FILE_DIRECTION;
-            rank = rank + RANK_DIRECTION;
+            file += FILE_DIRECTION;
+            rank += RANK_DIRECTION;
         }
 
         result[square.index() as usize] = board;
@@ -220,63 +211,56 @@ fn ray_attack_table<const GENERATE_DIAGONAL: bool, const GENERATE_HORIZONTAL: bo
     from: Square,
 ) -> Bitboard {
     let mut attacked = Bitboard::new();
-    let mut blockers = Bitboard::new();
-    let mut blocking_square = Square::new(0);
+    let mut blockers;
+    let mut blocking_square;
 
     if GENERATE_DIAGONAL {
         let mut northeast_attacked = RAY_TABLE_NORTHEAST[from.index() as usize];
         blockers = (northeast_attacked & occupied) | Bitboard::from_data(0x8000000000000000);
         blocking_square = Square::new(blockers.find_first());
-        northeast_attacked =
-            northeast_attacked ^ RAY_TABLE_NORTHEAST[blocking_square.index() as usize];
+        northeast_attacked ^= RAY_TABLE_NORTHEAST[blocking_square.index() as usize];
 
         let mut northwest_attacked = RAY_TABLE_NORTHWEST[from.index() as usize];
         blockers = (northwest_attacked & occupied) | Bitboard::from_data(0x8000000000000000);
         blocking_square = Square::new(blockers.find_first());
-        northwest_attacked =
-            northwest_attacked ^ RAY_TABLE_NORTHWEST[blocking_square.index() as usize];
+        northwest_attacked ^= RAY_TABLE_NORTHWEST[blocking_square.index() as usize];
 
         let mut southeast_attacked = RAY_TABLE_SOUTHEAST[from.index() as usize];
         blockers = (southeast_attacked & occupied) | Bitboard::from_data(1);
         blocking_square = Square::new(blockers.find_last());
-        southeast_attacked =
-            southeast_attacked ^ RAY_TABLE_SOUTHEAST[blocking_square.index() as usize];
+        southeast_attacked ^= RAY_TABLE_SOUTHEAST[blocking_square.index() as usize];
 
         let mut southwest_attacked = RAY_TABLE_SOUTHWEST[from.index() as usize];
         blockers = (southwest_attacked & occupied) | Bitboard::from_data(1);
         blocking_square = Square::new(blockers.find_last());
-        southwest_attacked =
-            southwest_attacked ^ RAY_TABLE_SOUTHWEST[blocking_square.index() as usize];
-
-        attacked = attacked
-            | northeast_attacked
-            | northwest_attacked
-            | southeast_attacked
-            | southwest_attacked;
+        southwest_attacked ^= RAY_TABLE_SOUTHWEST[blocking_square.index() as usize];
+
+        attacked |=
+            northeast_attacked | northwest_attacked | southeast_attacked | southwest_attacked;
     }
 
     if GENERATE_HORIZONTAL {
         let mut north_attacked = RAY_TABLE_NORTH[from.index() as usize];
         blockers = (north_attacked & occupied) | Bitboard::from_data(0x8000000000000000);
         blocking_square = Square::new(blockers.find_first());
-        north_attacked = north_attacked ^ RAY_TABLE_NORTH[blocking_square.index() as usize];
+        north_attacked ^= RAY_TABLE_NORTH[blocking_square.index() as usize];
 
         let mut east_attacked = RAY_TABLE_EAST[from.index() as usize];
         blockers = (east_attacked & occupied) | Bitboard::from_data(0x8000000000000000);
         blocking_square = Square::new(blockers.find_first());
-        east_attacked = east_attacked ^ RAY_TABLE_EAST[blocking_square.index() as usize];
+        east_attacked ^= RAY_TABLE_EAST[blocking_square.index() as usize];
 
         let mut south_attacked = RAY_TABLE_SOUTH[from.index() as usize];
         blockers = (south_attacked & occupied) | Bitboard::from_data(1);
         blocking_square = Square::new(blockers.find_last());
-        south_attacked = south_attacked ^ RAY_TABLE_SOUTH[blocking_square.index() as usize];
+        south_attacked ^= RAY_TABLE_SOUTH[blocking_square.index() as usize];
 
         let mut west_attacked = RAY_TABLE_WEST[from.index() as usize];
         blockers = (west_attacked & occupied) | Bitboard::from_data(1);
         blocking_square = Square::new(blockers.find_last());
-        west_attacked = west_attacked ^ RAY_TABLE_WEST[blocking_square.index() as usize];
+        west_attacked ^= RAY_TABLE_WEST[blocking_square.index() as usize];
 
-        attacked = attacked | north_attacked | east_attacked | south_attacked | west_attacked;
+        attacked |= north_attacked | east_attacked | south_attacked | west_attacked;
     }
 
     attacked
@@ -294,7 +278,7 @@ pub fn pawn_attack_board(side: Side, square: Square) -> Bitboard {
     PAWN_CAPTURE_TABLES[side as usize][square.index() as usize]
 }
 
-pub fn attack_table_knight(occupied: Bitboard, from: Square) -> Bitboard {
+pub fn attack_table_knight(_occupied: Bitboard, from: Square) -> Bitboard {
     KNIGHT_ATTACK_TABLE[from.index() as usize]
 }
 
@@ -310,6 +294,6 @@ pub fn attack_table_queen(occupied: Bitboard, from: Square) -> Bitboard {
     ray_attack_table::<true, true>(occupied, from)
 }
 
-pub fn attack_table_king(occupied: Bitboard, from: Square) -> Bitboard {
+pub fn attack_table_king(_occupied: Bitboard, from: Square) -> Bitboard {
     KING_ATTACK_TABLE[from.index() as usize]
 }
@@ -1,16 +1,110 @@
+use std::fmt;
+
 use super::super::utils::bitboard::Bitboard;
+use super::board::Piece;
 use super::board::SideBoard;
 use super::board::Square;
+use super::game::castle_destinations;
 use super::game::Game;
 use super::game::Side;
+use super::game::Variant;
 use super::move_tables::pawn_double_move_board;
-
+use crate::utils::notation;
+
+const PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+const POCKET_PIECES: [Piece; 5] = [
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+/// The first and last ranks, where pawns can't be dropped.
+const BACK_RANKS: u64 = 0xff000000000000ff;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Move {
     from: Square,
     to: Square,
     capture: bool,
     en_passant: bool,
-    promotion: u8,
+    castle: bool,
+    promotion: Option<Piece>,
+    /// The piece put on `to` from the pocket, in Crazyhouse. `from` is `to` for drops.
+    drop: Option<Piece>,
+}
+
+impl Move {
+    pub fn from(&self) -> Square {
+        self.from
+    }
+
+    pub fn to(&self) -> Square {
+        self.to
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.capture
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.en_passant
+    }
+
+    pub fn is_castle(&self) -> bool {
+        self.castle
+    }
+
+    pub fn promotion(&self) -> Option<Piece> {
+        self.promotion
+    }
+
+    /// The piece dropped from the pocket, for Crazyhouse drops.
+    pub fn dropped_piece(&self) -> Option<Piece> {
+        self.drop
+    }
+
+    pub fn is_drop(&self) -> bool {
+        self.drop.is_some()
+    }
+
+    /// Captures and promotions, i.e. the moves quiescence search looks at.
+    pub fn is_tactical(&self) -> bool {
+        self.capture || self.promotion.is_some()
+    }
+
+    /// UCI notation. Castling is written as the king's two square move, or in Chess960
+    /// as the king taking its own rook, which is how castling moves are stored. Drops
+    /// are written like `P@e4`.
+    pub fn to_uci(&self, chess960: bool) -> String {
+        if let Some(piece) = self.drop {
+            return format!(
+                "{}@{}",
+                piece.to_char().to_ascii_uppercase(),
+                notation::bit_to_algebraic_square(self.to.index())
+            );
+        }
+        let to = if self.castle && !chess960 {
+            castle_destinations(*self).0
+        } else {
+            self.to
+        };
+        let mut text = format!(
+            "{}{}",
+            notation::bit_to_algebraic_square(self.from.index()),
+            notation::bit_to_algebraic_square(to.index())
+        );
+        if let Some(promotion) = self.promotion {
+            text.push(promotion.to_char());
+        }
+        text
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_uci(false))
+    }
 }
 
 pub fn generate_ray_moves(
@@ -28,11 +122,13 @@ pub fn generate_ray_moves(
     for to_square in attacked {
         let capture = opponent_occupied.is_set(to_square);
         result.push(Move {
-            from: from,
+            from,
             to: to_square,
-            capture: capture,
+            capture,
             en_passant: false,
-            promotion: 0,
+            castle: false,
+            promotion: None,
+            drop: None,
         });
     }
 
@@ -60,40 +156,28 @@ pub fn generate_pawn_moves(
         let capture_board =
             super::move_tables::pawn_attack_board(side, from_square) & opponent_occupied;
 
-        let mut ep_board = Bitboard::new();
-        match ep_square {
+        let ep_board = match ep_square {
             Some(ep_square) => {
-                let side_offset = if side == Side::White { -1 } else { 1 };
-                let ep_square_w = Square::new_file_rank(
-                    (from_square.file() as i8 + -1) as u8,
-                    (from_square.rank() as i8 + side_offset) as u8,
-                );
-                let ep_square_e = Square::new_file_rank(
-                    (from_square.file() as i8 + 1) as u8,
-                    (from_square.rank() as i8 + side_offset) as u8,
-                );
-                if ep_square_w.index() == ep_square.index()
-                    || ep_square_e.index() == ep_square.index()
-                {
-                    ep_board.set(ep_square);
-                }
+                super::move_tables::pawn_attack_board(side, from_square)
+                    & Bitboard::from_square(ep_square)
             }
-            _ => {}
-        }
+            None => Bitboard::new(),
+        };
 
         let promotion_rank = if side == Side::White { 7 } else { 0 };
 
         // pushes
         for to_square in move_board {
             if to_square.rank() == promotion_rank {
-                for promote in 0..3 {
-                    // TODO
+                for promote in PROMOTION_PIECES {
                     result.push(Move {
                         from: from_square,
                         to: to_square,
                         capture: false,
                         en_passant: false,
-                        promotion: promote,
+                        castle: false,
+                        promotion: Some(promote),
+                        drop: None,
                     });
                 }
             } else {
@@ -102,7 +186,9 @@ pub fn generate_pawn_moves(
                     to: to_square,
                     capture: false,
                     en_passant: false,
-                    promotion: 0,
+                    castle: false,
+                    promotion: None,
+                    drop: None,
                 });
             }
         }
@@ -110,14 +196,15 @@ pub fn generate_pawn_moves(
         // normal captures
         for to_square in capture_board {
             if to_square.rank() == promotion_rank {
-                for promote in 0..3 {
-                    // TODO
+                for promote in PROMOTION_PIECES {
                     result.push(Move {
                         from: from_square,
                         to: to_square,
                         capture: true,
                         en_passant: false,
-                        promotion: promote,
+                        castle: false,
+                        promotion: Some(promote),
+                        drop: None,
                     });
                 }
             } else {
@@ -126,7 +213,9 @@ pub fn generate_pawn_moves(
                     to: to_square,
                     capture: true,
                     en_passant: false,
-                    promotion: 0,
+                    castle: false,
+                    promotion: None,
+                    drop: None,
                 });
             }
         }
@@ -138,7 +227,9 @@ pub fn generate_pawn_moves(
                 to: to_square,
                 capture: true,
                 en_passant: true,
-                promotion: 0,
+                castle: false,
+                promotion: None,
+                drop: None,
             });
         }
     }
@@ -165,9 +256,11 @@ pub fn generate_knight_moves(
             result.push(Move {
                 from: from_square,
                 to: to_square,
-                capture: capture,
+                capture,
                 en_passant: false,
-                promotion: 0,
+                castle: false,
+                promotion: None,
+                drop: None,
             });
         }
     }
@@ -203,8 +296,8 @@ pub fn generate_rook_moves(
 ) -> Vec<Move> {
     let mut result = Vec::new();
 
-    let our_bishops = our_pieces.rooks();
-    for from_square in our_bishops {
+    let our_rooks = our_pieces.rooks();
+    for from_square in our_rooks {
         let attacked = super::move_tables::attack_table_rook(occupied, from_square);
         result.append(&mut generate_ray_moves(
             from_square,
@@ -224,8 +317,8 @@ pub fn generate_queen_moves(
 ) -> Vec<Move> {
     let mut result = Vec::new();
 
-    let our_bishops = our_pieces.queens();
-    for from_square in our_bishops {
+    let our_queens = our_pieces.queens();
+    for from_square in our_queens {
         let attacked = super::move_tables::attack_table_queen(occupied, from_square);
         result.append(&mut generate_ray_moves(
             from_square,
@@ -256,9 +349,11 @@ pub fn generate_king_moves(
             result.push(Move {
                 from: from_square,
                 to: to_square,
-                capture: capture,
+                capture,
                 en_passant: false,
-                promotion: 0,
+                castle: false,
+                promotion: None,
+                drop: None,
             });
         }
     }
@@ -312,6 +407,303 @@ pub fn generate_pseudolegal_moves(game: &Game) -> Vec<Move> {
         opponent_pieces,
         occupied,
     ));
+    result.append(&mut generate_castle_moves(game));
+    if game.kind() == Variant::Antichess {
+        // the king is just another piece to promote to
+        let king_promotions: Vec<Move> = result
+            .iter()
+            .filter(|mv| mv.promotion == Some(Piece::Queen))
+            .map(|mv| Move {
+                promotion: Some(Piece::King),
+                ..*mv
+            })
+            .collect();
+        result.extend(king_promotions);
+    }
+    if game.kind() == Variant::Atomic {
+        // a king can't capture, it would explode along with its victim
+        result.retain(|mv| !(mv.capture && our_pieces.kings().is_set(mv.from)));
+    }
+    if game.kind() == Variant::Crazyhouse {
+        result.append(&mut generate_drop_moves(game));
+    }
 
     result
 }
+
+/// Crazyhouse drops of the pieces in the pocket of the side to move, onto any empty
+/// square except the first and last ranks for pawns.
+pub fn generate_drop_moves(game: &Game) -> Vec<Move> {
+    let mut result = Vec::new();
+
+    let board = game.board();
+    let pieces = board.side_pieces(game.active());
+    let empty = !board.occupied();
+    for piece in POCKET_PIECES {
+        if pieces.pocket(piece) == 0 {
+            continue;
+        }
+        let targets = if piece == Piece::Pawn {
+            empty & !Bitboard::from_data(BACK_RANKS)
+        } else {
+            empty
+        };
+        for to in targets {
+            result.push(Move {
+                from: to,
+                to,
+                capture: false,
+                en_passant: false,
+                castle: false,
+                promotion: None,
+                drop: Some(piece),
+            });
+        }
+    }
+
+    result
+}
+
+/// Castling moves, stored as the king moving onto its rook. Whether the king lands in
+/// check is left to the legality test.
+pub fn generate_castle_moves(game: &Game) -> Vec<Move> {
+    let mut result = Vec::new();
+
+    let side = game.active();
+    let board = game.board();
+    let occupied = board.occupied();
+    let (kingside, queenside) = game.castle_rights(side);
+    if !(kingside || queenside) || game.kind() == Variant::Antichess {
+        return result;
+    }
+    let Some(king_square) = board.side_pieces(side).king_square() else {
+        return result;
+    };
+    if game.is_attacked_for_king(side, king_square) {
+        return result;
+    }
+
+    let rank = king_square.rank();
+    let span = |a: Square, b: Square| {
+        (a.file().min(b.file())..=a.file().max(b.file()))
+            .map(move |file| Square::new_file_rank(file, rank))
+    };
+    for (allowed, towards_kingside) in [(kingside, true), (queenside, false)] {
+        if !allowed {
+            continue;
+        }
+        let rook_square = game.castle_rook_square(side, towards_kingside);
+        if !board.side_pieces(side).rooks().is_set(rook_square) {
+            continue;
+        }
+
+        let castle = Move {
+            from: king_square,
+            to: rook_square,
+            capture: false,
+            en_passant: false,
+            castle: true,
+            promotion: None,
+            drop: None,
+        };
+        let (king_to, rook_to) = castle_destinations(castle);
+
+        // In Chess960 the king and rook can start anywhere, so everything either of
+        // them crosses has to be empty apart from the two of them.
+        let blocked = span(king_square, king_to)
+            .chain(span(rook_square, rook_to))
+            .any(|square| {
+                square != king_square && square != rook_square && occupied.is_set(square)
+            });
+        if blocked {
+            continue;
+        }
+        // The king doesn't shield the squares it crosses, which matters in Atomic, where
+        // it may castle away from a rook while touching the enemy king. Where it lands
+        // is checked like after any other move, with the rook in its new place.
+        let mut without_king = occupied;
+        without_king.unset(king_square.index());
+        if span(king_square, king_to)
+            .filter(|square| *square != king_to)
+            .any(|square| game.is_attacked_for_king_through(side, square, without_king))
+        {
+            continue;
+        }
+
+        result.push(castle);
+    }
+
+    result
+}
+
+/// Pseudolegal moves filtered down to those that don't leave our king in check, and to
+/// the captures when the variant forces them.
+pub fn generate_legal_moves(game: &mut Game) -> Vec<Move> {
+    let side = game.active();
+    let mut result = generate_pseudolegal_moves(game);
+
+    result.retain(|mv| {
+        game.make_move(*mv);
+        let legal = game.is_king_safe(side);
+        game.unmake_move();
+        legal
+    });
+    if game.kind().captures_are_forced() {
+        retain_captures_if_any(&mut result);
+    }
+
+    result
+}
+
+/// Keeps only the captures in `moves`, unless there are none.
+pub fn retain_captures_if_any(moves: &mut Vec<Move>) {
+    if moves.iter().any(|mv| mv.capture) {
+        moves.retain(|mv| mv.capture);
+    }
+}
+
+/// Finds the legal move written in UCI long algebraic notation, e.g. `e2e4` or `e7e8q`.
+/// Chess960 games write castling as the king taking its rook.
+pub fn parse_uci_move(game: &mut Game, text: &str) -> Option<Move> {
+    let chess960 = game.kind().is_chess960();
+    generate_legal_moves(game)
+        .into_iter()
+        .find(|mv| mv.to_uci(chess960) == text)
+}
+
+/// Finds the legal move written in standard algebraic notation, e.g. `Nbd7`, `exd5`,
+/// `O-O`, `e8=Q+` or the Crazyhouse drop `N@f3`. Ambiguous moves aren't found.
+pub fn parse_san_move(game: &mut Game, text: &str) -> Option<Move> {
+    let text = text.trim_end_matches(['+', '#', '!', '?']);
+    let moves = generate_legal_moves(game);
+
+    if let Some((piece, square)) = text.split_once('@') {
+        // pawn drops are sometimes written without the P
+        let piece = match piece {
+            "" => Piece::Pawn,
+            _ => Piece::from_char(piece.to_ascii_lowercase().chars().next()?)?,
+        };
+        return moves.into_iter().find(|mv| {
+            mv.drop == Some(piece) && notation::bit_to_algebraic_square(mv.to.index()) == square
+        });
+    }
+
+    let castle = match text {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    };
+    if let Some(kingside) = castle {
+        return moves
+            .into_iter()
+            .find(|mv| mv.castle && (mv.to.file() > mv.from.file()) == kingside);
+    }
+
+    // promotions are written `e8=Q` and sometimes `e8Q`
+    let (text, promotion) = match text.char_indices().last()? {
+        (index, piece_char) if piece_char.is_ascii_uppercase() => {
+            let piece = Piece::from_char(piece_char.to_ascii_lowercase())?;
+            (text[..index].trim_end_matches('='), Some(piece))
+        }
+        _ => (text, None),
+    };
+    let (piece, text) = match text.chars().next()? {
+        piece_char if piece_char.is_ascii_uppercase() => (
+            Piece::from_char(piece_char.to_ascii_lowercase())?,
+            &text[1..],
+        ),
+        _ => (Piece::Pawn, text),
+    };
+    if text.len() < 2 || !text.is_char_boundary(text.len() - 2) {
+        return None;
+    }
+    let (from_hint, to) = text.split_at(text.len() - 2);
+    let to = to.as_bytes();
+    if !(b'a'..=b'h').contains(&to[0]) || !(b'1'..=b'8').contains(&to[1]) {
+        return None;
+    }
+    let to = Square::new_file_rank(to[0] - b'a', to[1] - b'1');
+    let from_hint = from_hint.trim_end_matches('x');
+
+    let mut found = moves.into_iter().filter(|mv| {
+        mv.to == to
+            && !mv.castle
+            && mv.promotion == promotion
+            && game.board().side_pieces(game.active()).piece_at(mv.from) == Some(piece)
+            && from_hint.bytes().all(|hint| match hint {
+                b'a'..=b'h' => mv.from.file() == hint - b'a',
+                b'1'..=b'8' => mv.from.rank() == hint - b'1',
+                _ => false,
+            })
+    });
+    let mv = found.next()?;
+    found.next().is_none().then_some(mv)
+}
+
+/// Writes the legal move `mv` in standard algebraic notation, the way `parse_san_move`
+/// reads it: `Nbd7`, `exd5`, `O-O`, `e8=Q+` or the Crazyhouse drop `N@f3`.
+pub fn san_move(game: &mut Game, mv: Move) -> String {
+    let to = notation::bit_to_algebraic_square(mv.to.index());
+    let mut text = if let Some(piece) = mv.drop {
+        format!("{}@{}", piece.to_char().to_ascii_uppercase(), to)
+    } else if mv.castle {
+        let kingside = mv.to.file() > mv.from.file();
+        String::from(if kingside { "O-O" } else { "O-O-O" })
+    } else {
+        let moves = generate_legal_moves(game);
+        let pieces = game.board().side_pieces(game.active());
+        let piece = pieces.piece_at(mv.from).expect("no piece to move");
+        let mut text = String::new();
+        if piece == Piece::Pawn {
+            if mv.capture {
+                text.push((b'a' + mv.from.file()) as char);
+            }
+        } else {
+            text.push(piece.to_char().to_ascii_uppercase());
+            // other pieces of the same kind that could go to the same square
+            let others: Vec<Square> = moves
+                .into_iter()
+                .filter(|other| {
+                    other.to == mv.to
+                        && other.from != mv.from
+                        && !other.castle
+                        && other.drop.is_none()
+                        && pieces.piece_at(other.from) == Some(piece)
+                })
+                .map(|other| other.from)
+                .collect();
+            let file = (b'a' + mv.from.file()) as char;
+            let rank = (b'1' + mv.from.rank()) as char;
+            if others.iter().all(|other| other.file() != mv.from.file()) {
+                if !others.is_empty() {
+                    text.push(file);
+                }
+            } else if others.iter().all(|other| other.rank() != mv.from.rank()) {
+                text.push(rank);
+            } else {
+                text.push(file);
+                text.push(rank);
+            }
+        }
+        if mv.capture {
+            text.push('x');
+        }
+        text.push_str(&to);
+        if let Some(promotion) = mv.promotion {
+            text.push('=');
+            text.push(promotion.to_char().to_ascii_uppercase());
+        }
+        text
+    };
+
+    game.make_move(mv);
+    if game.is_in_check() {
+        text.push(if generate_legal_moves(game).is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    game.unmake_move();
+    text
+}
@@ -0,0 +1,214 @@
+use crate::utils::random::Random;
+
+use super::game::Game;
+
+/// Pieces of a back rank, lowercase, in the order the random setups place them after
+/// the bishops.
+const OTHER_PIECES: [char; 6] = ['q', 'n', 'n', 'r', 'r', 'k'];
+
+/// Black's back rank, lowercase, for Chess960 position `index`: the bishops go on
+/// opposite colors, then the queen and knights on the free squares, and the king
+/// between the rooks on the last three.
+pub fn chess960_back_rank(index: u32) -> String {
+    assert!(index < 960, "Invalid Chess960 position {}", index);
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+
+    let mut rank = [' '; 8];
+    let mut n = index as usize;
+    rank[2 * (n % 4) + 1] = 'b';
+    n /= 4;
+    rank[2 * (n % 4)] = 'b';
+    n /= 4;
+
+    let queen = free_files(&rank)[n % 6];
+    rank[queen] = 'q';
+    n /= 6;
+
+    let empty = free_files(&rank);
+    let (first, second) = KNIGHTS[n];
+    rank[empty[first]] = 'n';
+    rank[empty[second]] = 'n';
+
+    for (file, piece) in free_files(&rank).into_iter().zip(['r', 'k', 'r']) {
+        rank[file] = piece;
+    }
+    rank.iter().collect()
+}
+
+fn free_files(rank: &[char; 8]) -> Vec<usize> {
+    (0..8).filter(|file| rank[*file] == ' ').collect()
+}
+
+/// One king, one queen, two rooks, two knights and two bishops on opposite colors, in
+/// any case and any other order.
+pub fn is_legal_back_rank(rank: &str) -> bool {
+    let rank = rank.to_ascii_lowercase();
+    if rank.len() != 8 {
+        return false;
+    }
+    let count = |piece: char| rank.chars().filter(|c| *c == piece).count();
+    let counts_match = [('k', 1), ('q', 1), ('r', 2), ('n', 2), ('b', 2)]
+        .iter()
+        .all(|(piece, expected)| count(*piece) == *expected);
+
+    let bishop_colors: Vec<usize> = rank
+        .char_indices()
+        .filter(|(_, piece)| *piece == 'b')
+        .map(|(file, _)| file % 2)
+        .collect();
+    counts_match && bishop_colors.len() == 2 && bishop_colors[0] != bishop_colors[1]
+}
+
+/// The castling field for a legal back rank, in its case, as Shredder-FEN files: the
+/// outermost rook on each side of the king, if there is one.
+pub fn castling_files(rank: &str) -> String {
+    let files: Vec<char> = rank.chars().collect();
+    let king = files
+        .iter()
+        .position(|piece| piece.eq_ignore_ascii_case(&'k'))
+        .expect("back rank without a king");
+    let is_rook = |file: &usize| files[*file].eq_ignore_ascii_case(&'r');
+
+    let kingside = (king + 1..8).rev().find(is_rook);
+    let queenside = (0..king).find(is_rook);
+    [kingside, queenside]
+        .into_iter()
+        .flatten()
+        .map(|file| {
+            let letter = (b'a' + file as u8) as char;
+            if files[file].is_ascii_uppercase() {
+                letter.to_ascii_uppercase()
+            } else {
+                letter
+            }
+        })
+        .collect()
+}
+
+/// Makes start positions from a seed, so that a tournament can be replayed.
+pub struct SetupGenerator {
+    random: Random,
+}
+
+impl SetupGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            random: Random::new(seed),
+        }
+    }
+
+    /// A random Chess960 position, the same for both sides.
+    pub fn chess960(&mut self) -> Game {
+        Game::new_chess960(self.random.next_below(960) as u32)
+    }
+
+    /// Double Fischer Random: each side gets its own Chess960 back rank.
+    pub fn double_chess960(&mut self) -> Game {
+        let white = chess960_back_rank(self.random.next_below(960) as u32);
+        let black = chess960_back_rank(self.random.next_below(960) as u32);
+        Game::from_back_ranks(&white, &black)
+    }
+
+    /// Transcendental chess: each side's back rank is shuffled on its own, and only the
+    /// bishops are restricted, to opposite colors. The king doesn't have to be between
+    /// the rooks, so a side may only be able to castle one way.
+    pub fn transcendental(&mut self) -> Game {
+        let white = self.shuffled_back_rank();
+        let black = self.shuffled_back_rank();
+        Game::from_back_ranks(&white, &black)
+    }
+
+    fn shuffled_back_rank(&mut self) -> String {
+        let mut rank = [' '; 8];
+        rank[2 * self.random.next_below(4) as usize + 1] = 'b';
+        rank[2 * self.random.next_below(4) as usize] = 'b';
+
+        let mut free = free_files(&rank);
+        for piece in OTHER_PIECES {
+            let file = free.swap_remove(self.random.next_below(free.len() as u64) as usize);
+            rank[file] = piece;
+        }
+        rank.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{castling_files, is_legal_back_rank, SetupGenerator};
+    use crate::chess::game::{Game, Variant};
+    use crate::chess::moves::generate_legal_moves;
+    use crate::utils::random::Random;
+
+    #[test]
+    fn back_ranks() {
+        assert!(is_legal_back_rank("RNBQKBNR"));
+        assert!(is_legal_back_rank("rrkqnnbb"));
+        // bishops on the same color
+        assert!(!is_legal_back_rank("RNBQKNBR"));
+        assert!(!is_legal_back_rank("RNBQKBN"));
+
+        assert_eq!(castling_files("RNBQKBNR"), "HA");
+        assert_eq!(castling_files("rrkqnnbb"), "a");
+        assert_eq!(castling_files("KRRQNNBB"), "C");
+    }
+
+    #[test]
+    fn asymmetric_setups() {
+        let game = Game::from_back_ranks("RNBQKBNR", "nrkbbqrn");
+        assert_eq!(game.kind(), Variant::DoubleChess960);
+        assert_eq!(
+            game.to_fen(),
+            "nrkbbqrn/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn generator_is_seeded() {
+        let fens = |seed| {
+            let mut generator = SetupGenerator::new(seed);
+            let games = [
+                generator.chess960(),
+                generator.double_chess960(),
+                generator.transcendental(),
+            ];
+            games.map(|game| game.to_fen())
+        };
+        assert_eq!(fens(7), fens(7));
+        assert_ne!(fens(7), fens(8));
+    }
+
+    #[test]
+    fn random_games_unmake_cleanly() {
+        let mut generator = SetupGenerator::new(1);
+        let mut random = Random::new(2);
+        for _ in 0..20 {
+            let mut game = generator.transcendental();
+            let start = game.to_fen();
+            let mut plies = 0;
+            for _ in 0..80 {
+                let moves = generate_legal_moves(&mut game);
+                if moves.is_empty() {
+                    break;
+                }
+                game.make_move(moves[random.next_below(moves.len() as u64) as usize]);
+                plies += 1;
+                assert_eq!(Game::from_fen(game.to_fen()).hash(), game.hash());
+            }
+            for _ in 0..plies {
+                game.unmake_move();
+            }
+            assert_eq!(game.to_fen(), start);
+        }
+    }
+}
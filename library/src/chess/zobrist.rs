@@ -0,0 +1,88 @@
+use super::board::{Piece, Square};
+use super::game::Side;
+use crate::utils::random::Random;
+
+pub struct ZobristKeys {
+    pieces: [[[u64; 64]; 6]; 2],
+    castling: [u64; 4],
+    ep_file: [u64; 8],
+    side: u64,
+    /// Crazyhouse pockets, one key for each piece in hand so a pocket hashes as the
+    /// first `count` keys.
+    pocket: [[[u64; 32]; 5]; 2],
+    /// Three-check, one key for each check given, like the pockets.
+    checks: [[u64; 3]; 2],
+}
+
+lazy_static! {
+    static ref KEYS: ZobristKeys = compute_keys();
+}
+
+fn compute_keys() -> ZobristKeys {
+    let mut random = Random::new(0x3243f6a8885a308d);
+    let mut keys = ZobristKeys {
+        pieces: [[[0; 64]; 6]; 2],
+        castling: [0; 4],
+        ep_file: [0; 8],
+        side: 0,
+        pocket: [[[0; 32]; 5]; 2],
+        checks: [[0; 3]; 2],
+    };
+
+    for side_keys in keys.pieces.iter_mut() {
+        for piece_keys in side_keys.iter_mut() {
+            for key in piece_keys.iter_mut() {
+                *key = random.next_u64();
+            }
+        }
+    }
+    for key in keys.castling.iter_mut() {
+        *key = random.next_u64();
+    }
+    for key in keys.ep_file.iter_mut() {
+        *key = random.next_u64();
+    }
+    keys.side = random.next_u64();
+    // after the others so that standard chess keys don't change
+    for side_keys in keys.pocket.iter_mut() {
+        for piece_keys in side_keys.iter_mut() {
+            for key in piece_keys.iter_mut() {
+                *key = random.next_u64();
+            }
+        }
+    }
+    for side_keys in keys.checks.iter_mut() {
+        for key in side_keys.iter_mut() {
+            *key = random.next_u64();
+        }
+    }
+
+    keys
+}
+
+pub fn piece_key(side: Side, piece: Piece, square: Square) -> u64 {
+    KEYS.pieces[side as usize][piece as usize][square.index() as usize]
+}
+
+/// Castling right index: 0 white kingside, 1 white queenside, 2 black kingside, 3 black queenside.
+pub fn castling_key(index: usize) -> u64 {
+    KEYS.castling[index]
+}
+
+pub fn ep_key(square: Square) -> u64 {
+    KEYS.ep_file[square.file() as usize]
+}
+
+pub fn side_key() -> u64 {
+    KEYS.side
+}
+
+/// Key of the `count`th `piece` in `side`'s pocket, counting from one.
+pub fn pocket_key(side: Side, piece: Piece, count: u8) -> u64 {
+    KEYS.pocket[side as usize][piece as usize][count as usize - 1]
+}
+
+/// Key of the `count`th check given by `side` in Three-check, counting from one.
+pub fn check_key(side: Side, count: u8) -> u64 {
+    KEYS.checks[side as usize][count as usize - 1]
+}
@@ -0,0 +1,61 @@
+use crate::chess::board::{Board, Piece, Square};
+use crate::chess::game::Side;
+use crate::chess::move_tables;
+use crate::utils::bitboard::Bitboard;
+
+use super::masks;
+
+/// Squares attacked by each side, per piece type and overall.
+pub struct AttackMaps {
+    by_piece: [[Bitboard; 6]; 2],
+    all: [Bitboard; 2],
+}
+
+impl AttackMaps {
+    pub fn new(board: &Board) -> Self {
+        let occupied = board.occupied();
+        let mut maps = Self {
+            by_piece: [[Bitboard::new(); 6]; 2],
+            all: [Bitboard::new(); 2],
+        };
+
+        for side in [Side::White, Side::Black] {
+            let pieces = board.side_pieces(side);
+            for piece in Piece::ALL {
+                let attacks = if piece == Piece::Pawn {
+                    masks::pawn_attacks(side, pieces.pawns())
+                } else {
+                    let mut attacks = Bitboard::new();
+                    for square in pieces.pieces(piece) {
+                        attacks |= piece_attacks(piece, occupied, square);
+                    }
+                    attacks
+                };
+                maps.by_piece[side as usize][piece as usize] = attacks;
+                maps.all[side as usize] |= attacks;
+            }
+        }
+
+        maps
+    }
+
+    pub fn by_piece(&self, side: Side, piece: Piece) -> Bitboard {
+        self.by_piece[side as usize][piece as usize]
+    }
+
+    pub fn all(&self, side: Side) -> Bitboard {
+        self.all[side as usize]
+    }
+}
+
+/// Attacks of a single non-pawn piece.
+pub fn piece_attacks(piece: Piece, occupied: Bitboard, square: Square) -> Bitboard {
+    match piece {
+        Piece::Pawn => panic!("piece_attacks: pawn attacks depend on the side"),
+        Piece::Knight => move_tables::attack_table_knight(occupied, square),
+        Piece::Bishop => move_tables::attack_table_bishop(occupied, square),
+        Piece::Rook => move_tables::attack_table_rook(occupied, square),
+        Piece::Queen => move_tables::attack_table_queen(occupied, square),
+        Piece::King => move_tables::attack_table_king(occupied, square),
+    }
+}
@@ -0,0 +1,591 @@
+use std::collections::HashMap;
+
+use crate::chess::board::{Board, Piece, SideBoard, Square};
+use crate::chess::game::Side;
+use crate::tablebase::generate::generate;
+use crate::tablebase::material::Material;
+use crate::tablebase::{Dtm, Position, Tablebase};
+use crate::utils::bitboard::Bitboard;
+
+use super::masks;
+use super::packed::PackedScore;
+use super::params::PIECE_VALUE;
+use super::pawns::PawnEntry;
+
+/// Scale factors are out of this, and multiply the endgame half of the evaluation.
+pub const SCALE_NORMAL: i32 = 64;
+pub const SCALE_DRAW: i32 = 0;
+/// Added to endgames that are known to be won, beyond any normal evaluation.
+pub const KNOWN_WIN: i32 = 10000;
+
+/// Replaces the evaluation; gets the board, the strong side and the side to move, and
+/// scores from the strong side's point of view.
+type ValueFn = fn(&Board, Side, Side) -> i32;
+/// Gets the board, the strong side and its pawn structure, and returns a scale factor
+/// if it knows better than the general rules.
+type ScaleFn = fn(&Board, Side, &PawnEntry) -> Option<i32>;
+
+#[derive(Clone, Copy)]
+enum Endgame {
+    Value(&'static str, ValueFn),
+    Scale(&'static str, ScaleFn),
+}
+
+lazy_static! {
+    /// Endgames with their own evaluation or scaling, by material key, with the side
+    /// they're written for.
+    static ref ENDGAMES: HashMap<u64, (Endgame, Side)> = {
+        let mut endgames = HashMap::new();
+        register(&mut endgames, "KBNK", Endgame::Value("KBNK", kbnk));
+        register(&mut endgames, "KNNK", Endgame::Value("KNNK", knnk));
+        register(&mut endgames, "KPK", Endgame::Value("KPK", kpk));
+        register(&mut endgames, "KRKP", Endgame::Value("KRKP", krkp));
+        register(&mut endgames, "KQKP", Endgame::Value("KQKP", kqkp));
+        for pawns in 1..=8 {
+            let name = format!("KB{}K", "P".repeat(pawns));
+            register(&mut endgames, &name, Endgame::Scale("KBPsK", kbpsk));
+        }
+        endgames
+    };
+
+    /// Whether the pawn's side wins KPK, by the side to move and the squares of its
+    /// king, the other king and the pawn, with the pawn's side as white. Solved with
+    /// the tablebase generator by `init`, or else the first time it's needed.
+    static ref KPK_WINS: Vec<u64> = solve_kpk();
+}
+
+/// Builds the endgame tables up front. Solving KPK takes a moment, which had better not
+/// come out of the first search that runs into it.
+pub fn init() {
+    lazy_static::initialize(&ENDGAMES);
+    lazy_static::initialize(&KPK_WINS);
+}
+
+/// Packs each side's count of each piece type besides the king into 4 bits.
+fn material_key(counts: [[usize; 5]; 2]) -> u64 {
+    let mut key = 0;
+    for (side, counts) in counts.iter().enumerate() {
+        for (piece, count) in counts.iter().enumerate() {
+            key |= (*count.min(&15) as u64) << (4 * (side * 5 + piece));
+        }
+    }
+    key
+}
+
+fn board_counts(board: &Board) -> [[usize; 5]; 2] {
+    [Side::White, Side::Black].map(|side| {
+        let pieces = board.side_pieces(side);
+        [
+            pieces.pawns().count(),
+            pieces.knights().count(),
+            pieces.bishops().count(),
+            pieces.rooks().count(),
+            pieces.queens().count(),
+        ]
+    })
+}
+
+/// Adds an endgame under a name like `KRKP`, for either side being the first one.
+fn register(endgames: &mut HashMap<u64, (Endgame, Side)>, name: &str, endgame: Endgame) {
+    let mut counts = [[0; 5]; 2];
+    let mut side = 0;
+    for piece_char in name.chars().skip(1) {
+        match Piece::from_char(piece_char) {
+            Some(Piece::King) => side = 1,
+            Some(piece) => counts[side][piece as usize] += 1,
+            None => panic!("invalid endgame {}", name),
+        }
+    }
+
+    endgames.insert(material_key(counts), (endgame, Side::White));
+    endgames.insert(material_key([counts[1], counts[0]]), (endgame, Side::Black));
+}
+
+fn is_bare(board: &Board, side: Side) -> bool {
+    board.side_pieces(side).occupied().count() == 1
+}
+
+/// Only endgames with a bare king or at most four pieces have their own rules.
+fn has_special_rules(board: &Board) -> bool {
+    board.occupied().count() <= 4 || is_bare(board, Side::White) || is_bare(board, Side::Black)
+}
+
+fn king(board: &Board, side: Side) -> Square {
+    board
+        .side_pieces(side)
+        .king_square()
+        .expect("a side without a king")
+}
+
+fn first(pieces: Bitboard) -> Square {
+    Square::new(pieces.find_first())
+}
+
+/// Light squares are 1, dark ones 0.
+fn color(square: Square) -> u8 {
+    (square.file() + square.rank()) & 1
+}
+
+fn material(board: &Board, side: Side) -> i32 {
+    Piece::ALL
+        .into_iter()
+        .map(|piece| {
+            board.side_pieces(side).pieces(piece).count() as i32
+                * PIECE_VALUE.get(piece as usize).eg()
+        })
+        .sum()
+}
+
+/// Grows towards the corners, to drive a lone king to the edge.
+fn push_to_edge(square: Square) -> i32 {
+    let file = (2 * square.file() as i32 - 7).abs();
+    let rank = (2 * square.rank() as i32 - 7).abs();
+    10 * (file + rank)
+}
+
+/// Grows as the kings get closer.
+fn push_close(a: Square, b: Square) -> i32 {
+    140 - 20 * masks::distance(a, b)
+}
+
+/// Enough to mate a bare king without help from pawns.
+fn can_force_mate(board: &Board, side: Side) -> bool {
+    let pieces = board.side_pieces(side);
+    let bishops = pieces.bishops();
+    let bishop_colors = bishops
+        .map(color)
+        .fold(0, |colors, color| colors | 1 << color);
+
+    !pieces.queens().is_empty()
+        || !pieces.rooks().is_empty()
+        || bishop_colors == 3
+        || (!bishops.is_empty() && !pieces.knights().is_empty())
+        || pieces.knights().count() >= 3
+}
+
+/// Anything that can force mate against a bare king: drive the king to the edge and
+/// bring our own closer.
+fn kxk(board: &Board, strong: Side, _active: Side) -> i32 {
+    let weak_king = king(board, strong.opposite());
+    KNOWN_WIN
+        + material(board, strong)
+        + push_to_edge(weak_king)
+        + push_close(king(board, strong), weak_king)
+}
+
+/// Bishop and knight can only mate in a corner of the bishop's color.
+fn kbnk(board: &Board, strong: Side, _active: Side) -> i32 {
+    let weak_king = king(board, strong.opposite());
+    let bishop = first(board.side_pieces(strong).bishops());
+    let corners = if color(bishop) == color(Square::new(0)) {
+        [Square::new(0), Square::new(63)]
+    } else {
+        [Square::new(7), Square::new(56)]
+    };
+    let corner_distance = corners
+        .map(|corner| masks::distance(weak_king, corner))
+        .into_iter()
+        .min()
+        .unwrap_or(7);
+
+    KNOWN_WIN
+        + material(board, strong)
+        + push_close(king(board, strong), weak_king)
+        + 40 * (7 - corner_distance)
+}
+
+/// Two knights can't force mate.
+fn knnk(_board: &Board, _strong: Side, _active: Side) -> i32 {
+    0
+}
+
+fn solve_kpk() -> Vec<u64> {
+    let mut tablebase = Tablebase::new();
+    for name in ["KQK", "KRK", "KBK", "KNK", "KPK"] {
+        let material = Material::from_name(name).expect("valid material");
+        let table = generate(&material, &tablebase);
+        tablebase.insert(table);
+    }
+
+    let mut wins = vec![0; 2 * 64 * 64];
+    for active in [Side::White, Side::Black] {
+        for strong_king in 0..64 {
+            for weak_king in 0..64 {
+                for pawn in 8..56 {
+                    let mut position = Position::new(active);
+                    position.add(Side::White, Piece::King, strong_king);
+                    position.add(Side::Black, Piece::King, weak_king);
+                    position.add(Side::White, Piece::Pawn, pawn);
+                    let won = match tablebase.probe_position(&position) {
+                        Some(Dtm::Win(_)) => active == Side::White,
+                        Some(Dtm::Loss(_)) => active == Side::Black,
+                        _ => false,
+                    };
+                    if won {
+                        let index = kpk_index(active, strong_king, weak_king, pawn);
+                        wins[index / 64] |= 1 << (index % 64);
+                    }
+                }
+            }
+        }
+    }
+    wins
+}
+
+fn kpk_index(active: Side, strong_king: u8, weak_king: u8, pawn: u8) -> usize {
+    ((active as usize * 64 + strong_king as usize) * 64 + weak_king as usize) * 64 + pawn as usize
+}
+
+/// Exact from the bitbase: a draw, or a win that gets better as the pawn advances.
+fn kpk(board: &Board, strong: Side, active: Side) -> i32 {
+    // look it up with the pawn's side as white
+    let relative = |square: Square| {
+        if strong == Side::White {
+            square.index()
+        } else {
+            square.index() ^ 56
+        }
+    };
+    let pawn = first(board.side_pieces(strong).pawns());
+    let relative_active = if active == strong {
+        Side::White
+    } else {
+        Side::Black
+    };
+    let index = kpk_index(
+        relative_active,
+        relative(king(board, strong)),
+        relative(king(board, strong.opposite())),
+        relative(pawn),
+    );
+
+    if KPK_WINS[index / 64] & (1 << (index % 64)) == 0 {
+        return 0;
+    }
+    KNOWN_WIN
+        + PIECE_VALUE.get(Piece::Pawn as usize).eg()
+        + masks::relative_rank(strong, pawn) as i32
+}
+
+/// Rook against pawn: won unless the pawn is far advanced with its king next to it
+/// and ours far away.
+fn krkp(board: &Board, strong: Side, active: Side) -> i32 {
+    let relative = |square: Square| {
+        if strong == Side::White {
+            square
+        } else {
+            Square::new(square.index() ^ 56)
+        }
+    };
+    let strong_king = relative(king(board, strong));
+    let weak_king = relative(king(board, strong.opposite()));
+    let rook = relative(first(board.side_pieces(strong).rooks()));
+    let pawn = relative(first(board.side_pieces(strong.opposite()).pawns()));
+    // the pawn runs down the board from here on
+    let queening = Square::new_file_rank(pawn.file(), 0);
+    let below_pawn = Square::new(pawn.index() - 8);
+    let rook_value = PIECE_VALUE.get(Piece::Rook as usize).eg();
+    let weak_to_move = (active != strong) as i32;
+    let strong_to_move = (active == strong) as i32;
+
+    if strong_king.file() == pawn.file() && strong_king.rank() < pawn.rank() {
+        // our king is in front of the pawn
+        rook_value - masks::distance(strong_king, pawn)
+    } else if masks::distance(weak_king, pawn) >= 3 + weak_to_move
+        && masks::distance(weak_king, rook) >= 3
+    {
+        // their king is too far from both the pawn and the rook
+        rook_value - masks::distance(strong_king, pawn)
+    } else if weak_king.rank() <= 2
+        && masks::distance(weak_king, pawn) == 1
+        && strong_king.rank() >= 3
+        && masks::distance(strong_king, pawn) > 2 + strong_to_move
+    {
+        80 - 8 * masks::distance(strong_king, pawn)
+    } else {
+        200 - 8
+            * (masks::distance(strong_king, below_pawn)
+                - masks::distance(weak_king, below_pawn)
+                - masks::distance(pawn, queening))
+    }
+}
+
+/// Queen against pawn: won, except against a bishop or rook pawn on the seventh
+/// with its king next to it, which can make a stalemate of it.
+fn kqkp(board: &Board, strong: Side, _active: Side) -> i32 {
+    let weak = strong.opposite();
+    let strong_king = king(board, strong);
+    let weak_king = king(board, weak);
+    let pawn = first(board.side_pieces(weak).pawns());
+
+    let mut result = push_close(strong_king, weak_king);
+    let drawish_file = matches!(pawn.file(), 0 | 2 | 5 | 7);
+    if masks::relative_rank(weak, pawn) != 6
+        || masks::distance(weak_king, pawn) != 1
+        || !drawish_file
+    {
+        result += PIECE_VALUE.get(Piece::Queen as usize).eg()
+            - PIECE_VALUE.get(Piece::Pawn as usize).eg();
+    }
+    result
+}
+
+/// Bishop and rook pawns against a bare king: a draw if the pawns are all on one rook
+/// file, the bishop doesn't control the promotion square and the king holds it.
+fn kbpsk(board: &Board, strong: Side, _pawns: &PawnEntry) -> Option<i32> {
+    let pawns = board.side_pieces(strong).pawns();
+    let file = first(pawns).file();
+    if (file != 0 && file != 7) || !(pawns & !masks::file(file)).is_empty() {
+        return None;
+    }
+
+    let promotion_rank = if strong == Side::White { 7 } else { 0 };
+    let queening = Square::new_file_rank(file, promotion_rank);
+    let bishop = first(board.side_pieces(strong).bishops());
+    let weak_king = king(board, strong.opposite());
+    if color(bishop) != color(queening) && masks::distance(weak_king, queening) <= 1 {
+        Some(SCALE_DRAW)
+    } else {
+        None
+    }
+}
+
+fn non_pawn_material(board: &Board, side: Side) -> i32 {
+    material(board, side)
+        - board.side_pieces(side).pawns().count() as i32
+            * PIECE_VALUE.get(Piece::Pawn as usize).eg()
+}
+
+/// Drawishness that applies to any material: no pawns to win with, opposite colored
+/// bishops, and few pawns in general.
+fn general_scale(board: &Board, strong: Side, pawns: &PawnEntry) -> Option<(&'static str, i32)> {
+    let weak = strong.opposite();
+    let strong_pieces = board.side_pieces(strong);
+    let weak_pieces = board.side_pieces(weak);
+    let bishop_value = PIECE_VALUE.get(Piece::Bishop as usize).eg();
+    let rook_value = PIECE_VALUE.get(Piece::Rook as usize).eg();
+
+    if strong_pieces.pawns().is_empty() {
+        let (strong_material, weak_material) = (
+            non_pawn_material(board, strong),
+            non_pawn_material(board, weak),
+        );
+        if strong_material - weak_material <= bishop_value {
+            let factor = if strong_material < rook_value {
+                SCALE_DRAW
+            } else if weak_material <= bishop_value {
+                4
+            } else {
+                14
+            };
+            return Some(("No pawns", factor));
+        }
+    }
+
+    let only_bishops = |pieces: &SideBoard| {
+        pieces.bishops().count() == 1
+            && (pieces.knights() | pieces.rooks() | pieces.queens()).is_empty()
+    };
+    let opposite_bishops = strong_pieces.bishops().count() == 1
+        && weak_pieces.bishops().count() == 1
+        && color(first(strong_pieces.bishops())) != color(first(weak_pieces.bishops()));
+    if opposite_bishops {
+        let factor = if only_bishops(strong_pieces) && only_bishops(weak_pieces) {
+            18 + 4 * pawns.passed(strong).count() as i32
+        } else {
+            22 + 3 * strong_pieces.occupied().count() as i32
+        };
+        if factor < SCALE_NORMAL {
+            return Some(("Opposite bishops", factor));
+        }
+    }
+
+    let factor = 36 + 7 * strong_pieces.pawns().count() as i32;
+    (factor < SCALE_NORMAL).then_some(("Few pawns", factor))
+}
+
+/// A specialized evaluation for the material on the board, if there is one: its name
+/// and score from white's point of view.
+pub fn evaluate(board: &Board, active: Side) -> Option<(&'static str, i32)> {
+    if !has_special_rules(board) {
+        return None;
+    }
+
+    let white_view = |score: i32, strong: Side| {
+        if strong == Side::White {
+            score
+        } else {
+            -score
+        }
+    };
+    if let Some((Endgame::Value(name, value), strong)) =
+        ENDGAMES.get(&material_key(board_counts(board)))
+    {
+        return Some((name, white_view(value(board, *strong, active), *strong)));
+    }
+
+    for strong in [Side::White, Side::Black] {
+        if is_bare(board, strong.opposite()) && can_force_mate(board, strong) {
+            return Some(("KXK", white_view(kxk(board, strong, active), strong)));
+        }
+    }
+    None
+}
+
+/// How much of the endgame half of `score` (white's point of view) the side that's
+/// ahead can hope to keep, out of `SCALE_NORMAL`, and the rule that says so. `None`
+/// leaves the score alone.
+pub fn scale_factor(
+    board: &Board,
+    score: PackedScore,
+    pawns: &PawnEntry,
+) -> Option<(&'static str, i32)> {
+    let strong = if score.eg() >= 0 {
+        Side::White
+    } else {
+        Side::Black
+    };
+
+    if has_special_rules(board) {
+        if let Some((Endgame::Scale(name, scale), side)) =
+            ENDGAMES.get(&material_key(board_counts(board)))
+        {
+            if *side == strong {
+                if let Some(factor) = scale(board, strong, pawns) {
+                    return Some((name, factor));
+                }
+            }
+        }
+    }
+
+    general_scale(board, strong, pawns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, scale_factor, KNOWN_WIN, SCALE_DRAW, SCALE_NORMAL};
+    use crate::chess::board::Board;
+    use crate::chess::game::Game;
+    use crate::eval::packed::PackedScore;
+    use crate::eval::pawns::{evaluate_pawns, PawnEntry};
+    use crate::eval::trace::NoTrace;
+
+    fn position(fen: &str) -> (Board, PawnEntry, Game) {
+        let game = Game::from_fen(fen.to_string());
+        let pawns = evaluate_pawns(game.board(), game.pawn_hash(), &mut NoTrace);
+        (game.board().clone(), pawns, game)
+    }
+
+    /// The same position with the colors swapped. Endgame positions have no castling
+    /// rights or en passant squares to flip.
+    fn flip(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let ranks: Vec<String> = fields[0]
+            .split('/')
+            .rev()
+            .map(|rank| {
+                rank.chars()
+                    .map(|piece| match piece.is_ascii_uppercase() {
+                        true => piece.to_ascii_lowercase(),
+                        false => piece.to_ascii_uppercase(),
+                    })
+                    .collect()
+            })
+            .collect();
+        let active = if fields[1] == "w" { "b" } else { "w" };
+        format!(
+            "{} {} - - {} {}",
+            ranks.join("/"),
+            active,
+            fields[4],
+            fields[5]
+        )
+    }
+
+    fn value(fen: &str) -> Option<(&'static str, i32)> {
+        let (board, _, game) = position(fen);
+        evaluate(&board, game.active())
+    }
+
+    fn scale(fen: &str, score: PackedScore) -> Option<(&'static str, i32)> {
+        let (board, pawns, _) = position(fen);
+        scale_factor(&board, score, &pawns)
+    }
+
+    #[test]
+    fn kbnk_drives_the_king_to_the_bishop_corner() {
+        // a dark squared bishop mates on a1 and h8
+        let near = value("8/8/8/8/4K3/8/1k6/2BN4 w - - 0 1").unwrap();
+        let far = value("8/8/8/8/4K3/8/6k1/2BN4 w - - 0 1").unwrap();
+        assert_eq!(near.0, "KBNK");
+        assert!(near.1 > far.1 && far.1 > KNOWN_WIN);
+
+        // a light squared one on h1 and a8
+        let near = value("8/8/8/8/4K3/8/6k1/1B1N4 w - - 0 1").unwrap();
+        let far = value("8/8/8/8/4K3/8/1k6/1B1N4 w - - 0 1").unwrap();
+        assert!(near.1 > far.1);
+    }
+
+    #[test]
+    fn kpk_from_the_bitbase() {
+        let (name, won) = value("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(name, "KPK");
+        assert!(won > KNOWN_WIN);
+        // the rook pawn can't shake the king off its corner
+        assert_eq!(value("k7/8/8/8/8/8/P7/K7 w - - 0 1"), Some(("KPK", 0)));
+    }
+
+    #[test]
+    fn wrong_rook_pawn_is_a_draw() {
+        let winning = PackedScore::new(300, 300);
+        // the bishop doesn't cover a8
+        assert_eq!(
+            scale("k7/8/8/8/8/8/P7/K1B5 w - - 0 1", winning),
+            Some(("KBPsK", SCALE_DRAW))
+        );
+        assert_ne!(
+            scale("k7/8/8/8/8/8/P7/KB6 w - - 0 1", winning).map(|(name, _)| name),
+            Some("KBPsK")
+        );
+        // the king is too far from it
+        assert_ne!(
+            scale("8/8/8/8/8/3k4/P7/K1B5 w - - 0 1", winning).map(|(name, _)| name),
+            Some("KBPsK")
+        );
+    }
+
+    #[test]
+    fn opposite_bishops_scale_down() {
+        let winning = PackedScore::new(150, 150);
+        let opposite = scale("4k3/8/3b4/8/3PP3/5B2/8/4K3 w - - 0 1", winning).unwrap();
+        let same = scale("4k3/8/4b3/8/3PP3/5B2/8/4K3 w - - 0 1", winning).unwrap();
+        // two passed pawns with only the bishops left
+        assert_eq!(opposite, ("Opposite bishops", 26));
+        assert_eq!(same.0, "Few pawns");
+        assert!(opposite.1 < same.1 && same.1 < SCALE_NORMAL);
+    }
+
+    #[test]
+    fn endgames_apply_to_either_color() {
+        for fen in [
+            "8/8/8/8/4K3/8/1k6/2BN4 w - - 0 1",
+            "8/8/8/8/4K3/8/1k6/2NN4 w - - 0 1",
+            "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1",
+            "8/8/8/4k3/8/8/7p/K6R w - - 0 1",
+            "8/8/8/4k3/8/8/7p/K6Q b - - 0 1",
+        ] {
+            let (name, score) = value(fen).expect(fen);
+            assert_eq!(value(&flip(fen)), Some((name, -score)), "{}", fen);
+        }
+
+        let winning = PackedScore::new(300, 300);
+        for fen in [
+            "k7/8/8/8/8/8/P7/K1B5 w - - 0 1",
+            "k7/8/8/8/8/P7/P7/K1B5 b - - 0 1",
+        ] {
+            let factor = scale(fen, winning);
+            assert_eq!(factor, Some(("KBPsK", SCALE_DRAW)), "{}", fen);
+            assert_eq!(scale(&flip(fen), -winning), factor, "{}", fen);
+        }
+    }
+}
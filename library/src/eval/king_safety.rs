@@ -0,0 +1,208 @@
+use crate::chess::board::{Board, Piece, Square};
+use crate::chess::game::Side;
+use crate::chess::move_tables;
+use crate::utils::bitboard::Bitboard;
+
+use super::attacks::{piece_attacks, AttackMaps};
+use super::masks;
+use super::packed::PackedScore;
+use super::params::*;
+use super::trace::Tracer;
+
+lazy_static! {
+    static ref KING_ZONES: [[Bitboard; 64]; 2] = compute_king_zones();
+}
+
+/// The king's square, the squares around it, and one more rank towards the enemy.
+fn compute_king_zones() -> [[Bitboard; 64]; 2] {
+    let mut result = [[Bitboard::new(); 64]; 2];
+
+    for (side, side_result) in result.iter_mut().enumerate() {
+        for index in 0..64 {
+            let square = Square::new(index);
+            let mut zone = move_tables::attack_table_king(Bitboard::new(), square);
+            zone.set(square);
+
+            let forward = if side == 0 {
+                zone.data() << 8
+            } else {
+                zone.data() >> 8
+            };
+            side_result[index as usize] = zone | Bitboard::from_data(forward);
+        }
+    }
+
+    result
+}
+
+pub fn king_zone(side: Side, square: Square) -> Bitboard {
+    KING_ZONES[side as usize][square.index() as usize]
+}
+
+/// Danger units against `side`'s king; `KING_DANGER` turns them into a penalty.
+/// 0 when the king isn't under any real attack.
+pub fn king_danger(board: &Board, attacks: &AttackMaps, side: Side) -> i32 {
+    let Some(king_square) = board.side_pieces(side).king_square() else {
+        return 0;
+    };
+    let enemy = side.opposite();
+    let enemy_pieces = board.side_pieces(enemy);
+    let occupied = board.occupied();
+    let zone = king_zone(side, king_square);
+
+    let mut danger = 0;
+    let mut attackers = 0;
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        for square in enemy_pieces.pieces(piece) {
+            let hits = (piece_attacks(piece, occupied, square) & zone).count() as i32;
+            if hits > 0 {
+                attackers += 1;
+                danger += KING_ZONE_ATTACK[piece as usize] * hits;
+            }
+        }
+    }
+
+    // a lone attacker can't mate, however close it gets
+    if attackers < 2 {
+        return 0;
+    }
+
+    // Squares a piece could check from without being taken.
+    let safe = !attacks.all(side) & !enemy_pieces.occupied();
+    let knight_checks = move_tables::attack_table_knight(occupied, king_square);
+    let bishop_checks = move_tables::attack_table_bishop(occupied, king_square);
+    let rook_checks = move_tables::attack_table_rook(occupied, king_square);
+    let checks = [
+        (Piece::Knight, knight_checks),
+        (Piece::Bishop, bishop_checks),
+        (Piece::Rook, rook_checks),
+        (Piece::Queen, bishop_checks | rook_checks),
+    ];
+    for (piece, checks) in checks {
+        let safe_checks = checks & safe & attacks.by_piece(enemy, piece);
+        danger += SAFE_CHECK[piece as usize] * safe_checks.count() as i32;
+    }
+
+    if board.side_pieces(side).queens().is_empty() {
+        danger += NO_DEFENDING_QUEEN;
+    }
+
+    danger.clamp(0, KING_DANGER.len() as i32 - 1)
+}
+
+/// Pawn shelter in front of `side`'s king and enemy pawns storming towards it.
+fn shelter(
+    board: &Board,
+    side: Side,
+    king_square: Square,
+    tracer: &mut impl Tracer,
+) -> PackedScore {
+    let own = board.side_pieces(side).pawns();
+    let enemy = board.side_pieces(side.opposite()).pawns();
+    let king_rank = masks::relative_rank(side, king_square);
+    let center = king_square.file().clamp(1, 6);
+    let mut score = PackedScore::ZERO;
+
+    for file in center - 1..=center + 1 {
+        let in_front = |pawns: Bitboard| {
+            (pawns & masks::file(file))
+                .map(|square| masks::relative_rank(side, square))
+                .filter(|rank| *rank >= king_rank)
+                .min()
+        };
+
+        let shelter_rank = in_front(own).unwrap_or(0) as usize;
+        let storm_rank = in_front(enemy).unwrap_or(0) as usize;
+        score += PAWN_SHELTER.apply(tracer, side, shelter_rank, 1);
+        score += PAWN_STORM.apply(tracer, side, storm_rank, 1);
+    }
+
+    score
+}
+
+/// King safety, indexed by side, each from its own point of view.
+pub fn evaluate(board: &Board, attacks: &AttackMaps, tracer: &mut impl Tracer) -> [PackedScore; 2] {
+    let mut scores = [PackedScore::ZERO; 2];
+
+    for side in [Side::White, Side::Black] {
+        let Some(king_square) = board.side_pieces(side).king_square() else {
+            continue;
+        };
+
+        let penalty = KING_DANGER[king_danger(board, attacks, side) as usize];
+        scores[side as usize] = shelter(board, side, king_square, tracer)
+            - PackedScore::new(penalty, penalty * KING_DANGER_ENDGAME / 16);
+    }
+
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, king_danger, king_zone};
+    use crate::chess::board::Square;
+    use crate::chess::game::{Game, Side};
+    use crate::eval::attacks::AttackMaps;
+    use crate::eval::trace::NoTrace;
+
+    fn danger(fen: &str, side: Side) -> i32 {
+        let game = Game::from_fen(fen.to_string());
+        king_danger(game.board(), &AttackMaps::new(game.board()), side)
+    }
+
+    #[test]
+    fn king_zones_reach_one_rank_forward() {
+        // e1: the king's square, the five around it and three more on the third rank
+        assert_eq!(king_zone(Side::White, Square::new(4)).count(), 9);
+        assert_eq!(king_zone(Side::Black, Square::new(60)).count(), 9);
+        // a corner has fewer neighbours
+        assert_eq!(king_zone(Side::White, Square::new(0)).count(), 6);
+    }
+
+    #[test]
+    fn danger_grows_with_the_attackers() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(danger(start, Side::White), 0);
+        assert_eq!(danger(start, Side::Black), 0);
+
+        // a lone queen next to the king can't mate on its own
+        assert_eq!(
+            danger("6k1/5ppp/7Q/8/8/8/5PPP/6K1 b - - 0 1", Side::Black),
+            0
+        );
+
+        let queen_and_knight = danger(
+            "r4rk1/ppp2ppp/8/6NQ/8/8/PPP2PPP/R5K1 b - - 0 1",
+            Side::Black,
+        );
+        let with_bishop = danger(
+            "r4rk1/ppp2ppp/8/6NQ/8/3B4/PPP2PPP/R5K1 b - - 0 1",
+            Side::Black,
+        );
+        assert!(queen_and_knight > 0);
+        assert!(with_bishop > queen_and_knight);
+        // and the attacking side's king is fine
+        assert_eq!(
+            danger(
+                "r4rk1/ppp2ppp/8/6NQ/8/3B4/PPP2PPP/R5K1 b - - 0 1",
+                Side::White
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn shelter_prefers_unmoved_pawns() {
+        let score = |fen: &str| {
+            let game = Game::from_fen(fen.to_string());
+            evaluate(game.board(), &AttackMaps::new(game.board()), &mut NoTrace)[0]
+        };
+        let intact = score("4k3/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let pushed = score("4k3/8/8/8/8/6PP/5P2/6K1 w - - 0 1");
+        let stormed = score("4k3/8/8/8/6p1/6PP/5P2/6K1 w - - 0 1");
+        let open = score("4k3/8/8/8/8/8/5P2/6K1 w - - 0 1");
+        assert!(intact.mg() > pushed.mg());
+        assert!(pushed.mg() > open.mg());
+        assert!(stormed.mg() < pushed.mg());
+    }
+}
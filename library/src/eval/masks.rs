@@ -0,0 +1,142 @@
+use crate::chess::board::Square;
+use crate::chess::game::Side;
+use crate::chess::move_tables;
+use crate::utils::bitboard::Bitboard;
+
+lazy_static! {
+    static ref FILES: [Bitboard; 8] = compute_files();
+    static ref RANKS: [Bitboard; 8] = compute_ranks();
+    static ref ADJACENT_FILES: [Bitboard; 8] = compute_adjacent_files();
+    static ref FORWARD_FILE: [[Bitboard; 64]; 2] = compute_forward_spans(false, true);
+    static ref ATTACK_SPAN: [[Bitboard; 64]; 2] = compute_forward_spans(true, false);
+    static ref PASSED_PAWN_MASK: [[Bitboard; 64]; 2] = compute_forward_spans(true, true);
+}
+
+fn compute_files() -> [Bitboard; 8] {
+    let mut result = [Bitboard::new(); 8];
+
+    for (file, file_result) in result.iter_mut().enumerate() {
+        for rank in 0..8 {
+            file_result.set(Square::new_file_rank(file as u8, rank));
+        }
+    }
+
+    result
+}
+
+fn compute_ranks() -> [Bitboard; 8] {
+    let mut result = [Bitboard::new(); 8];
+
+    for (rank, rank_result) in result.iter_mut().enumerate() {
+        for file in 0..8 {
+            rank_result.set(Square::new_file_rank(file, rank as u8));
+        }
+    }
+
+    result
+}
+
+fn compute_adjacent_files() -> [Bitboard; 8] {
+    let files = compute_files();
+    let mut result = [Bitboard::new(); 8];
+
+    for (file, file_result) in result.iter_mut().enumerate() {
+        if file > 0 {
+            *file_result |= files[file - 1];
+        }
+        if file < 7 {
+            *file_result |= files[file + 1];
+        }
+    }
+
+    result
+}
+
+/// Squares strictly in front of each square, on the adjacent files and/or its own file.
+fn compute_forward_spans(adjacent: bool, own_file: bool) -> [[Bitboard; 64]; 2] {
+    let mut result = [[Bitboard::new(); 64]; 2];
+
+    for (side, side_result) in result.iter_mut().enumerate() {
+        for index in 0..64 {
+            let square = Square::new(index);
+            let mut board = Bitboard::new();
+
+            for rank in 0..8_u8 {
+                let in_front = if side == 0 {
+                    rank > square.rank()
+                } else {
+                    rank < square.rank()
+                };
+                if !in_front {
+                    continue;
+                }
+
+                for file in 0..8_u8 {
+                    let distance = (file as i8 - square.file() as i8).abs();
+                    if (own_file && distance == 0) || (adjacent && distance == 1) {
+                        board.set(Square::new_file_rank(file, rank));
+                    }
+                }
+            }
+
+            side_result[index as usize] = board;
+        }
+    }
+
+    result
+}
+
+pub fn file(file: u8) -> Bitboard {
+    FILES[file as usize]
+}
+
+pub fn rank(rank: u8) -> Bitboard {
+    RANKS[rank as usize]
+}
+
+pub fn adjacent_files(file: u8) -> Bitboard {
+    ADJACENT_FILES[file as usize]
+}
+
+/// Squares in front of `square` on its file, from `side`'s point of view.
+pub fn forward_file(side: Side, square: Square) -> Bitboard {
+    FORWARD_FILE[side as usize][square.index() as usize]
+}
+
+/// Squares in front of `square` on the adjacent files: everything a pawn there could
+/// ever attack.
+pub fn attack_span(side: Side, square: Square) -> Bitboard {
+    ATTACK_SPAN[side as usize][square.index() as usize]
+}
+
+/// Squares an enemy pawn would have to be on to stop a pawn on `square`.
+pub fn passed_pawn_mask(side: Side, square: Square) -> Bitboard {
+    PASSED_PAWN_MASK[side as usize][square.index() as usize]
+}
+
+/// Rank counted from `side`'s own back rank, 0 to 7.
+pub fn relative_rank(side: Side, square: Square) -> u8 {
+    if side == Side::White {
+        square.rank()
+    } else {
+        7 - square.rank()
+    }
+}
+
+/// King moves between two squares.
+pub fn distance(a: Square, b: Square) -> i32 {
+    let files = (a.file() as i32 - b.file() as i32).abs();
+    let ranks = (a.rank() as i32 - b.rank() as i32).abs();
+
+    files.max(ranks)
+}
+
+/// Every square attacked by one of `pawns`.
+pub fn pawn_attacks(side: Side, pawns: Bitboard) -> Bitboard {
+    let mut attacks = Bitboard::new();
+    for square in pawns {
+        attacks |= move_tables::pawn_attack_board(side, square);
+    }
+
+    attacks
+}
@@ -0,0 +1,321 @@
+pub mod attacks;
+pub mod endgame;
+pub mod king_safety;
+pub mod masks;
+pub mod packed;
+pub mod params;
+pub mod pawns;
+pub mod pieces;
+pub mod psqt;
+pub mod threats;
+pub mod trace;
+
+use crate::chess::board::{Board, Piece};
+use crate::chess::game::{Game, Side, Variant};
+
+use attacks::AttackMaps;
+use endgame::SCALE_NORMAL;
+use packed::PackedScore;
+use params::PIECE_VALUE;
+use pawns::{PawnEntry, PawnTable};
+use trace::{Coefficients, EvalTrace, NoTrace, Term, Tracer};
+
+/// Game phase of the starting position; 0 is a bare pawn endgame.
+pub const MAX_PHASE: i32 = 24;
+/// What each piece fewer than the opponent is worth in Antichess.
+const ANTICHESS_PIECE_VALUE: i32 = 100;
+
+/// Evaluation with its caches. Each search thread owns one.
+pub struct Evaluator {
+    pawns: PawnTable,
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self {
+            pawns: PawnTable::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pawns.clear();
+    }
+
+    /// Same as `evaluate`, reusing pawn structure scores from earlier calls.
+    pub fn evaluate(&mut self, game: &Game) -> i32 {
+        if let Some(score) = evaluate_antichess(game) {
+            return score;
+        }
+        if let Some(score) = evaluate_endgame(game) {
+            return score;
+        }
+        #[cfg(feature = "nnue")]
+        if let Some(score) = crate::nnue::evaluate(game) {
+            return score;
+        }
+
+        let pawns = self.pawns.probe(game.board(), game.pawn_hash());
+        evaluate_with_pawns(game, &pawns)
+    }
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How far the position is from the endgame, from the minor and major pieces left.
+pub fn phase(board: &Board) -> i32 {
+    let mut phase = 0;
+    for side in [Side::White, Side::Black] {
+        let pieces = board.side_pieces(side);
+        phase += (pieces.knights().count() + pieces.bishops().count()) as i32;
+        phase += 2 * pieces.rooks().count() as i32;
+        phase += 4 * pieces.queens().count() as i32;
+    }
+
+    // promotions can push it past the starting material
+    phase.min(MAX_PHASE)
+}
+
+/// Blends the middlegame and endgame halves of `score` by `phase`.
+pub fn taper(score: PackedScore, phase: i32) -> i32 {
+    taper_scaled(score, phase, SCALE_NORMAL)
+}
+
+/// Same as `taper`, with the endgame half scaled by `scale` out of `SCALE_NORMAL`.
+pub fn taper_scaled(score: PackedScore, phase: i32, scale: i32) -> i32 {
+    (score.mg() * phase + score.eg() * scale / SCALE_NORMAL * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+/// The specialized evaluation for the material left, if any, from the side to move's
+/// point of view. These come before the network too, which doesn't know how to mate.
+fn evaluate_endgame(game: &Game) -> Option<i32> {
+    if !game.kind().has_standard_rules() {
+        return None;
+    }
+    let (_, score) = endgame::evaluate(game.board(), game.active())?;
+    Some(if game.active() == Side::White {
+        score
+    } else {
+        -score
+    })
+}
+
+/// Antichess turns material upside down, so neither the terms above nor the network
+/// mean anything there. Having fewer pieces left than the opponent is what counts.
+fn evaluate_antichess(game: &Game) -> Option<i32> {
+    if game.kind() != Variant::Antichess {
+        return None;
+    }
+    let board = game.board();
+    let ours = board.side_pieces(game.active()).occupied();
+    let theirs = board.side_pieces(game.active().opposite()).occupied();
+    Some(ANTICHESS_PIECE_VALUE * (theirs.count() as i32 - ours.count() as i32))
+}
+
+/// Static evaluation in centipawns, from the side to move's point of view. Uses the
+/// game's network when the `nnue` feature is on and one is loaded.
+pub fn evaluate(game: &Game) -> i32 {
+    if let Some(score) = evaluate_antichess(game) {
+        return score;
+    }
+    if let Some(score) = evaluate_endgame(game) {
+        return score;
+    }
+    #[cfg(feature = "nnue")]
+    if let Some(score) = crate::nnue::evaluate(game) {
+        return score;
+    }
+
+    let pawns = pawns::evaluate_pawns(game.board(), game.pawn_hash(), &mut NoTrace);
+    evaluate_with_pawns(game, &pawns)
+}
+
+/// Danger units against `side`'s king, as used by the king safety term. Roughly: 10
+/// is uncomfortable, 30 is a serious attack and 60 or more is usually lost.
+pub fn king_danger(game: &Game, side: Side) -> i32 {
+    let board = game.board();
+    king_safety::king_danger(board, &AttackMaps::new(board), side)
+}
+
+/// Every term except material and PSQT, which the game keeps up to date itself.
+fn positional_terms(
+    board: &Board,
+    pawns: &PawnEntry,
+    attacks: &AttackMaps,
+    tracer: &mut impl Tracer,
+) -> [[PackedScore; 2]; Term::COUNT] {
+    let mut terms = [[PackedScore::ZERO; 2]; Term::COUNT];
+    terms[Term::PassedPawns as usize] = pawns::evaluate_passed(board, pawns, tracer);
+    terms[Term::KingSafety as usize] = king_safety::evaluate(board, attacks, tracer);
+
+    for side in [Side::White, Side::Black] {
+        let index = side as usize;
+        let (mobility, placement) = pieces::evaluate_side(board, side, attacks, tracer);
+        terms[Term::Pawns as usize][index] = pawns.score(side);
+        terms[Term::Mobility as usize][index] = mobility;
+        terms[Term::Pieces as usize][index] = placement;
+        terms[Term::Threats as usize][index] = threats::evaluate_side(board, side, attacks, tracer);
+    }
+
+    terms
+}
+
+fn evaluate_with_pawns(game: &Game, pawns: &PawnEntry) -> i32 {
+    let board = game.board();
+    let attacks = AttackMaps::new(board);
+    let mut score = game.psqt();
+    for [white, black] in positional_terms(board, pawns, &attacks, &mut NoTrace) {
+        score += white - black;
+    }
+    let scale = endgame::scale_factor(board, score, pawns)
+        .filter(|_| game.kind().has_standard_rules())
+        .map_or(SCALE_NORMAL, |(_, scale)| scale);
+    let score = taper_scaled(score, phase(board), scale);
+
+    if game.active() == Side::White {
+        score
+    } else {
+        -score
+    }
+}
+
+/// Breaks the hand-crafted evaluation of `game` down into its terms, and records how
+/// each tunable parameter contributed.
+pub fn trace(game: &Game) -> EvalTrace {
+    let board = game.board();
+    let mut coefficients = Coefficients::new();
+    let pawns = pawns::evaluate_pawns(board, game.pawn_hash(), &mut coefficients);
+    let attacks = AttackMaps::new(board);
+    let mut terms = positional_terms(board, &pawns, &attacks, &mut coefficients);
+
+    for side in [Side::White, Side::Black] {
+        let index = side as usize;
+        let pieces = board.side_pieces(side);
+        for piece in Piece::ALL {
+            for square in pieces.pieces(piece) {
+                let (table, entry) = psqt::table_entry(side, piece, square);
+                terms[Term::Material as usize][index] +=
+                    PIECE_VALUE.apply(&mut coefficients, side, piece as usize, 1);
+                terms[Term::Psqt as usize][index] += table.apply(&mut coefficients, side, entry, 1);
+            }
+            if piece != Piece::King {
+                let in_hand = pieces.pocket(piece) as i32;
+                terms[Term::Material as usize][index] +=
+                    PIECE_VALUE.apply(&mut coefficients, side, piece as usize, in_hand);
+            }
+        }
+    }
+
+    let king_danger =
+        [Side::White, Side::Black].map(|side| king_safety::king_danger(board, &attacks, side));
+
+    let mut trace = EvalTrace::new(terms, phase(board), king_danger, coefficients);
+    if !game.kind().has_standard_rules() {
+        return trace;
+    }
+    if let Some((name, score)) = endgame::evaluate(board, game.active()) {
+        trace.endgame = Some(name);
+        trace.score = score;
+    } else if let Some((name, scale)) = endgame::scale_factor(board, trace.total_score(), &pawns) {
+        trace.endgame = Some(name);
+        trace.scale = scale;
+        trace.score = taper_scaled(trace.total_score(), trace.phase, scale);
+    }
+
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::endgame::SCALE_NORMAL;
+    use super::{evaluate, trace};
+    use crate::chess::game::{Game, Side, Variant};
+
+    fn swap_case(piece: char) -> char {
+        match piece.is_ascii_uppercase() {
+            true => piece.to_ascii_lowercase(),
+            false => piece.to_ascii_uppercase(),
+        }
+    }
+
+    /// The same position with the board turned around and the colors swapped.
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let ranks: Vec<String> = fields[0]
+            .split('/')
+            .rev()
+            .map(|rank| rank.chars().map(swap_case).collect())
+            .collect();
+        let active = if fields[1] == "w" { "b" } else { "w" };
+        let castling: String = fields[2].chars().map(swap_case).collect();
+        let ep_square = match fields[3].as_bytes() {
+            [file, b'3'] => format!("{}6", *file as char),
+            [file, b'6'] => format!("{}3", *file as char),
+            _ => fields[3].to_string(),
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            ranks.join("/"),
+            active,
+            castling,
+            ep_square,
+            fields[4],
+            fields[5]
+        )
+    }
+
+    #[test]
+    fn evaluation_is_color_symmetric() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8",
+            "2r3k1/5ppp/p3p3/1p1pP3/3P4/P4N2/1P3PPP/2R3K1 b - - 0 25",
+            "8/5pk1/6p1/3B4/8/6P1/5PK1/2b5 w - - 0 40",
+            "8/8/4k3/8/2P5/8/4K3/8 w - - 0 1",
+            "4k3/8/8/8/8/8/4P3/R3K3 w Q - 0 1",
+        ] {
+            let game = Game::from_fen(fen.to_string());
+            let mirrored = Game::from_fen(mirror(fen));
+            assert_eq!(evaluate(&game), evaluate(&mirrored), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn trace_matches_evaluate() {
+        let games = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R b KQ - 0 8",
+            // scaled down
+            "4k3/8/3b4/8/3PP3/5B2/8/4K3 w - - 0 1",
+            "4k3/8/3b4/8/3PP3/5B2/8/4K3 b - - 0 1",
+            // specialized
+            "8/8/4k3/8/2P5/8/4K3/8 w - - 0 1",
+            "8/8/8/3k4/8/8/8/KBN5 b - - 0 1",
+        ]
+        .map(|fen| Game::from_fen(fen.to_string()));
+        let crazyhouse = Game::from_fen_variant(
+            "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R[Pn] b KQkq - 0 1".to_string(),
+            Variant::Crazyhouse,
+        );
+
+        // tests never load a network, so `evaluate` is hand-crafted with `nnue` on too
+        for game in games.iter().chain([&crazyhouse]) {
+            let trace = trace(game);
+            let score = match game.active() {
+                Side::White => trace.score,
+                Side::Black => -trace.score,
+            };
+            assert_eq!(score, evaluate(game), "{}", game.to_fen());
+        }
+
+        let traces = games.each_ref().map(trace);
+        assert_eq!(traces[0].endgame, None);
+        assert!(traces[2].endgame.is_some() && traces[2].scale < SCALE_NORMAL);
+        assert!(traces[4].endgame.is_some() && traces[4].scale == SCALE_NORMAL);
+        assert!(traces[5].endgame.is_some());
+    }
+}
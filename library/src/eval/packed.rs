@@ -0,0 +1,77 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+/// A middlegame and an endgame score in one integer, so both can be updated with a
+/// single add. The endgame half lives in the upper 16 bits.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct PackedScore {
+    data: i32,
+}
+
+impl PackedScore {
+    pub const ZERO: PackedScore = PackedScore { data: 0 };
+
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self {
+            data: (eg << 16) + mg,
+        }
+    }
+
+    pub fn mg(&self) -> i32 {
+        self.data as i16 as i32
+    }
+
+    pub fn eg(&self) -> i32 {
+        // round so a negative middlegame half doesn't borrow from the endgame half
+        ((self.data + 0x8000) >> 16) as i16 as i32
+    }
+}
+
+impl Add for PackedScore {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            data: self.data + rhs.data,
+        }
+    }
+}
+
+impl AddAssign for PackedScore {
+    fn add_assign(&mut self, rhs: Self) {
+        self.data += rhs.data;
+    }
+}
+
+impl Sub for PackedScore {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            data: self.data - rhs.data,
+        }
+    }
+}
+
+impl SubAssign for PackedScore {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.data -= rhs.data;
+    }
+}
+
+impl Neg for PackedScore {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self { data: -self.data }
+    }
+}
+
+impl Mul<i32> for PackedScore {
+    type Output = Self;
+
+    fn mul(self, rhs: i32) -> Self {
+        Self {
+            data: self.data * rhs,
+        }
+    }
+}
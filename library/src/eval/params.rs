@@ -0,0 +1,321 @@
+use crate::chess::game::Side;
+
+use super::packed::PackedScore;
+use super::trace::Tracer;
+
+/// A tunable weight, or table of weights, stored in `PARAMS`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Param {
+    offset: usize,
+    size: usize,
+}
+
+impl Param {
+    const fn new(offset: usize, size: usize) -> Self {
+        Self { offset, size }
+    }
+
+    /// Position of the first weight in `PARAMS`.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Number of weights, 1 for a single one.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn get(&self, index: usize) -> PackedScore {
+        PARAMS[self.offset + index]
+    }
+
+    /// The weight at `index`, counted `count` times for `side`.
+    pub fn apply(
+        &self,
+        tracer: &mut impl Tracer,
+        side: Side,
+        index: usize,
+        count: i32,
+    ) -> PackedScore {
+        tracer.record(side, self.offset + index, count);
+        self.get(index) * count
+    }
+}
+
+const fn s(mg: i32, eg: i32) -> PackedScore {
+    PackedScore::new(mg, eg)
+}
+
+/// Declares the tunable parameters: one `Param` constant per name, all the weights in
+/// `PARAMS`, and `PARAM_INFO` so that a tuner can write the list back out.
+macro_rules! eval_params {
+    ($($(#[doc = $doc:literal])* $name:ident = [$($value:expr),* $(,)?];)*) => {
+        eval_params!(@offsets 0; $([$($doc)*] $name [$($value),*])*);
+
+        /// Every tunable weight, back to back.
+        pub static PARAMS: [PackedScore; PARAM_COUNT] = [$($($value,)*)*];
+
+        /// Name, parameter and documentation of everything in `PARAMS`, in order.
+        pub const PARAM_INFO: &[(&str, Param, &str)] = &[
+            $((stringify!($name), $name, concat!($($doc, "\n",)*)),)*
+        ];
+    };
+    (@offsets $offset:expr;) => {
+        pub const PARAM_COUNT: usize = $offset;
+    };
+    (@offsets $offset:expr; [$($doc:literal)*] $name:ident [$($value:expr),*] $($rest:tt)*) => {
+        $(#[doc = $doc])*
+        pub const $name: Param = Param::new($offset, [$($value),*].len());
+        eval_params!(@offsets $offset + [$($value),*].len(); $($rest)*);
+    };
+}
+
+// Evaluation weights. Everything inside `eval_params!` can be rewritten by the `tune`
+// tool; per-rank tables are indexed by the rank relative to the piece's side.
+eval_params! {
+    // Material and placement, which the game adds up incrementally through `psqt`. The
+    // placement tables go from a8 to h1, four squares a line, from white's point of view.
+
+    /// Indexed by `Piece`. The king's is never counted.
+    PIECE_VALUE = [
+        s(82, 94), s(337, 281), s(365, 297), s(477, 512),
+        s(1025, 936), s(0, 0),
+    ];
+    PAWN_PSQT = [
+        s(0, 0), s(0, 0), s(0, 0), s(0, 0),
+        s(0, 0), s(0, 0), s(0, 0), s(0, 0),
+        s(98, 178), s(134, 173), s(61, 158), s(95, 134),
+        s(68, 147), s(126, 132), s(34, 165), s(-11, 187),
+        s(-6, 94), s(7, 100), s(26, 85), s(31, 67),
+        s(65, 56), s(56, 53), s(25, 82), s(-20, 84),
+        s(-14, 32), s(13, 24), s(6, 13), s(21, 5),
+        s(23, -2), s(12, 4), s(17, 17), s(-23, 17),
+        s(-27, 13), s(-2, 9), s(-5, -3), s(12, -7),
+        s(17, -7), s(6, -8), s(10, 3), s(-25, -1),
+        s(-26, 4), s(-4, 7), s(-4, -6), s(-10, 1),
+        s(3, 0), s(3, -5), s(33, -1), s(-12, -8),
+        s(-35, 13), s(-1, 8), s(-20, 8), s(-23, 10),
+        s(-15, 13), s(24, 0), s(38, 2), s(-22, -7),
+        s(0, 0), s(0, 0), s(0, 0), s(0, 0),
+        s(0, 0), s(0, 0), s(0, 0), s(0, 0),
+    ];
+    KNIGHT_PSQT = [
+        s(-167, -58), s(-89, -38), s(-34, -13), s(-49, -28),
+        s(61, -31), s(-97, -27), s(-15, -63), s(-107, -99),
+        s(-73, -25), s(-41, -8), s(72, -25), s(36, -2),
+        s(23, -9), s(62, -25), s(7, -24), s(-17, -52),
+        s(-47, -24), s(60, -20), s(37, 10), s(65, 9),
+        s(84, -1), s(129, -9), s(73, -19), s(44, -41),
+        s(-9, -17), s(17, 3), s(19, 22), s(53, 22),
+        s(37, 22), s(69, 11), s(18, 8), s(22, -18),
+        s(-13, -18), s(4, -6), s(16, 16), s(13, 25),
+        s(28, 16), s(19, 17), s(21, 4), s(-8, -18),
+        s(-23, -23), s(-9, -3), s(12, -1), s(10, 15),
+        s(19, 10), s(17, -3), s(25, -20), s(-16, -22),
+        s(-29, -42), s(-53, -20), s(-12, -10), s(-3, -5),
+        s(-1, -2), s(18, -20), s(-14, -23), s(-19, -44),
+        s(-105, -29), s(-21, -51), s(-58, -23), s(-33, -15),
+        s(-17, -22), s(-28, -18), s(-19, -50), s(-23, -64),
+    ];
+    BISHOP_PSQT = [
+        s(-29, -14), s(4, -21), s(-82, -11), s(-37, -8),
+        s(-25, -7), s(-42, -9), s(7, -17), s(-8, -24),
+        s(-26, -8), s(16, -4), s(-18, 7), s(-13, -12),
+        s(30, -3), s(59, -13), s(18, -4), s(-47, -14),
+        s(-16, 2), s(37, -8), s(43, 0), s(40, -1),
+        s(35, -2), s(50, 6), s(37, 0), s(-2, 4),
+        s(-4, -3), s(5, 9), s(19, 12), s(50, 9),
+        s(37, 14), s(37, 10), s(7, 3), s(-2, 2),
+        s(-6, -6), s(13, 3), s(13, 13), s(26, 19),
+        s(34, 7), s(12, 10), s(10, -3), s(4, -9),
+        s(0, -12), s(15, -3), s(15, 8), s(15, 10),
+        s(14, 13), s(27, 3), s(18, -7), s(10, -15),
+        s(4, -14), s(15, -18), s(16, -7), s(0, -1),
+        s(7, 4), s(21, -9), s(33, -15), s(1, -27),
+        s(-33, -23), s(-3, -9), s(-14, -23), s(-21, -5),
+        s(-13, -9), s(-12, -16), s(-39, -5), s(-21, -17),
+    ];
+    ROOK_PSQT = [
+        s(32, 13), s(42, 10), s(32, 18), s(51, 15),
+        s(63, 12), s(9, 12), s(31, 8), s(43, 5),
+        s(27, 11), s(32, 13), s(58, 13), s(62, 11),
+        s(80, -3), s(67, 3), s(26, 8), s(44, 3),
+        s(-5, 7), s(19, 7), s(26, 7), s(36, 5),
+        s(17, 4), s(45, -3), s(61, -5), s(16, -3),
+        s(-24, 4), s(-11, 3), s(7, 13), s(26, 1),
+        s(24, 2), s(35, 1), s(-8, -1), s(-20, 2),
+        s(-36, 3), s(-26, 5), s(-12, 8), s(-1, 4),
+        s(9, -5), s(-7, -6), s(6, -8), s(-23, -11),
+        s(-45, -4), s(-25, 0), s(-16, -5), s(-17, -1),
+        s(3, -7), s(0, -12), s(-5, -8), s(-33, -16),
+        s(-44, -6), s(-16, -6), s(-20, 0), s(-9, 2),
+        s(-1, -9), s(11, -9), s(-6, -11), s(-71, -3),
+        s(-19, -9), s(-13, 2), s(1, 3), s(17, -1),
+        s(16, -5), s(7, -13), s(-37, 4), s(-26, -20),
+    ];
+    QUEEN_PSQT = [
+        s(-28, -9), s(0, 22), s(29, 22), s(12, 27),
+        s(59, 27), s(44, 19), s(43, 10), s(45, 20),
+        s(-24, -17), s(-39, 20), s(-5, 32), s(1, 41),
+        s(-16, 58), s(57, 25), s(28, 30), s(54, 0),
+        s(-13, -20), s(-17, 6), s(7, 9), s(8, 49),
+        s(29, 47), s(56, 35), s(47, 19), s(57, 9),
+        s(-27, 3), s(-27, 22), s(-16, 24), s(-16, 45),
+        s(-1, 57), s(17, 40), s(-2, 57), s(1, 36),
+        s(-9, -18), s(-26, 28), s(-9, 19), s(-10, 47),
+        s(-2, 31), s(-4, 34), s(3, 39), s(-3, 23),
+        s(-14, -16), s(2, -27), s(-11, 15), s(-2, 6),
+        s(-5, 9), s(2, 17), s(14, 10), s(5, 5),
+        s(-35, -22), s(-8, -23), s(11, -30), s(2, -16),
+        s(8, -16), s(15, -23), s(-3, -36), s(1, -32),
+        s(-1, -33), s(-18, -28), s(-9, -22), s(10, -43),
+        s(-15, -5), s(-25, -32), s(-31, -20), s(-50, -41),
+    ];
+    KING_PSQT = [
+        s(-65, -74), s(23, -35), s(16, -18), s(-15, -18),
+        s(-56, -11), s(-34, 15), s(2, 4), s(13, -17),
+        s(29, -12), s(-1, 17), s(-20, 14), s(-7, 17),
+        s(-8, 17), s(-4, 38), s(-38, 23), s(-29, 11),
+        s(-9, 10), s(24, 17), s(2, 23), s(-16, 15),
+        s(-20, 20), s(6, 45), s(22, 44), s(-22, 13),
+        s(-17, -8), s(-20, 22), s(-12, 24), s(-27, 27),
+        s(-30, 26), s(-25, 33), s(-14, 26), s(-36, 3),
+        s(-49, -18), s(-1, -4), s(-27, 21), s(-39, 24),
+        s(-46, 27), s(-44, 23), s(-33, 9), s(-51, -11),
+        s(-14, -19), s(-14, -3), s(-22, 11), s(-46, 21),
+        s(-44, 23), s(-30, 16), s(-15, 7), s(-27, -9),
+        s(1, -27), s(7, -11), s(-8, 4), s(-64, 13),
+        s(-43, 14), s(-16, 4), s(9, -5), s(8, -17),
+        s(-15, -53), s(36, -34), s(12, -21), s(-54, -11),
+        s(8, -28), s(-28, -14), s(24, -24), s(14, -43),
+    ];
+
+    // Pawn structure.
+
+    /// For each pawn with a friendly pawn in front of it on the same file.
+    DOUBLED_PAWN = [s(-10, -28)];
+    /// No friendly pawns on either adjacent file.
+    ISOLATED_PAWN = [s(-6, -14)];
+    /// Can't be supported by friendly pawns and can't safely advance.
+    BACKWARD_PAWN = [s(-9, -20)];
+    /// Defended by a friendly pawn.
+    SUPPORTED_PAWN = [
+        s(0, 0), s(0, 0), s(8, 4), s(10, 6),
+        s(18, 14), s(35, 30), s(60, 55), s(0, 0),
+    ];
+    /// A friendly pawn beside it on the same rank.
+    PHALANX_PAWN = [
+        s(0, 0), s(4, 2), s(6, 4), s(10, 8),
+        s(20, 16), s(40, 40), s(70, 70), s(0, 0),
+    ];
+    /// No enemy pawn can stop it from promoting.
+    PASSED_PAWN = [
+        s(0, 0), s(2, 8), s(5, 12), s(8, 20),
+        s(22, 42), s(50, 90), s(90, 140), s(0, 0),
+    ];
+    /// Per square of distance between the enemy king and a passed pawn's stop square,
+    /// scaled by how far the pawn has advanced.
+    PASSED_ENEMY_KING_DISTANCE = [s(0, 6)];
+    /// Same for our own king, which wants to be close.
+    PASSED_OWN_KING_DISTANCE = [s(0, -3)];
+    /// Nothing stands between a passed pawn and its promotion square, scaled by rank.
+    PASSED_FREE_PATH = [s(0, 8)];
+
+    // King safety, apart from the danger units below.
+
+    /// For the closest friendly pawn in front of the king on its file and the two next to
+    /// it, by the pawn's relative rank. Index 0 means there is no pawn.
+    PAWN_SHELTER = [
+        s(-30, 0), s(25, 0), s(15, 0), s(0, 0),
+        s(-5, 0), s(-10, 0), s(-10, 0), s(0, 0),
+    ];
+    /// For the closest enemy pawn on the same files, by its rank relative to our side.
+    PAWN_STORM = [
+        s(0, 0), s(0, 0), s(-35, 0), s(-25, 0),
+        s(-12, 0), s(-4, 0), s(0, 0), s(0, 0),
+    ];
+
+    // Piece activity.
+
+    /// By the number of safe squares a knight attacks.
+    KNIGHT_MOBILITY = [
+        s(-38, -50), s(-25, -35), s(-8, -20), s(-2, -8),
+        s(3, 3), s(9, 8), s(15, 12), s(20, 14),
+        s(24, 16),
+    ];
+    BISHOP_MOBILITY = [
+        s(-30, -45), s(-15, -25), s(5, -8), s(10, 2),
+        s(15, 10), s(21, 16), s(25, 22), s(28, 26),
+        s(30, 30), s(35, 33), s(40, 34), s(44, 37),
+        s(46, 40), s(50, 42),
+    ];
+    ROOK_MOBILITY = [
+        s(-40, -60), s(-16, -20), s(-2, 6), s(0, 18),
+        s(1, 30), s(5, 40), s(9, 47), s(12, 52),
+        s(16, 58), s(18, 62), s(20, 66), s(22, 68),
+        s(23, 70), s(25, 72), s(28, 74),
+    ];
+    QUEEN_MOBILITY = [
+        s(-20, -30), s(-10, -18), s(-2, -6), s(0, 4),
+        s(3, 14), s(6, 20), s(8, 26), s(10, 32),
+        s(12, 38), s(14, 44), s(16, 48), s(18, 52),
+        s(20, 56), s(21, 60), s(22, 63), s(23, 66),
+        s(24, 69), s(25, 72), s(26, 74), s(27, 76),
+        s(28, 78), s(29, 80), s(30, 82), s(31, 84),
+        s(32, 86), s(33, 88), s(34, 90), s(35, 92),
+    ];
+    /// A knight or bishop on the 4th to 6th rank, defended by a pawn, that no enemy pawn
+    /// can ever attack.
+    KNIGHT_OUTPOST = [s(25, 12)];
+    BISHOP_OUTPOST = [s(15, 6)];
+    /// No pawns at all on the rook's file.
+    ROOK_OPEN_FILE = [s(22, 8)];
+    /// Only enemy pawns on the rook's file.
+    ROOK_SEMI_OPEN_FILE = [s(10, 5)];
+    /// On the 7th rank with the enemy king behind it or enemy pawns still on it.
+    ROOK_ON_SEVENTH = [s(10, 20)];
+    BISHOP_PAIR = [s(25, 50)];
+    /// A rook boxed in on the back rank by its own uncastled king.
+    TRAPPED_ROOK = [s(-40, -10)];
+    /// A bishop on a7/h7 (a2/h2 for black) shut in by an enemy pawn on b6/g6 (b3/g3).
+    TRAPPED_BISHOP = [s(-60, -60)];
+
+    // Threats.
+
+    /// For each enemy piece attacked by one of our pawns, by the attacked piece.
+    THREAT_BY_PAWN = [
+        s(0, 0), s(45, 30), s(45, 30), s(60, 40),
+        s(60, 40), s(0, 0),
+    ];
+    /// For each enemy piece other than a pawn that we attack and nothing defends.
+    HANGING_PIECE = [s(30, 20)];
+}
+
+// King danger is kept out of `eval_params!` on purpose. Attacks on the king zone are
+// added up in danger units, which are turned into a penalty by `KING_DANGER` so that
+// several attackers hurt much more than one. The tuner only fits weights the evaluation
+// is linear in, and the units stop being linear once they go through the table, so
+// `tune` keeps each position's danger penalty as it is and these are tuned by hand.
+
+/// Danger units per king zone square attacked, by attacking piece.
+pub const KING_ZONE_ATTACK: [i32; 6] = [0, 2, 2, 3, 5, 0];
+/// Danger units per safe checking square, by checking piece.
+pub const SAFE_CHECK: [i32; 6] = [0, 6, 4, 7, 8, 0];
+/// Danger units when the defending side has no queen left to help.
+pub const NO_DEFENDING_QUEEN: i32 = -8;
+/// Middlegame and endgame penalty for each number of danger units.
+#[rustfmt::skip]
+pub const KING_DANGER: [i32; 100] = [
+      0,   0,   1,   2,   3,   5,   7,   9,  12,  15,
+     18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
+     68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
+    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+    494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+];
+/// Share of the danger penalty that still applies in the endgame, in 1/16ths.
+pub const KING_DANGER_ENDGAME: i32 = 2;
@@ -0,0 +1,233 @@
+use crate::chess::board::{Board, Square};
+use crate::chess::game::Side;
+use crate::chess::move_tables;
+use crate::utils::bitboard::Bitboard;
+
+use super::masks;
+use super::packed::PackedScore;
+use super::params::*;
+use super::trace::{NoTrace, Tracer};
+
+/// Entries in a pawn hash table; pawn structures repeat a lot within a search.
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+/// Everything about a pawn structure that doesn't depend on the other pieces.
+#[derive(Clone, Copy, Default)]
+pub struct PawnEntry {
+    key: u64,
+    scores: [PackedScore; 2],
+    passed: [Bitboard; 2],
+}
+
+/// Per-thread cache of pawn structure evaluations, keyed by `Game::pawn_hash`.
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl PawnEntry {
+    /// Structure score for `side`'s pawns, from its own point of view.
+    pub fn score(&self, side: Side) -> PackedScore {
+        self.scores[side as usize]
+    }
+
+    pub fn passed(&self, side: Side) -> Bitboard {
+        self.passed[side as usize]
+    }
+}
+
+impl PawnTable {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![PawnEntry::default(); PAWN_TABLE_SIZE],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(PawnEntry::default());
+    }
+
+    pub fn probe(&mut self, board: &Board, pawn_hash: u64) -> PawnEntry {
+        let entry = &mut self.entries[pawn_hash as usize & (PAWN_TABLE_SIZE - 1)];
+        // key 0 is the empty table, which `evaluate_pawns` gets right anyway
+        if entry.key != pawn_hash || pawn_hash == 0 {
+            *entry = evaluate_pawns(board, pawn_hash, &mut NoTrace);
+        }
+
+        *entry
+    }
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Scores both sides' pawn structure and finds the passed pawns.
+pub fn evaluate_pawns(board: &Board, pawn_hash: u64, tracer: &mut impl Tracer) -> PawnEntry {
+    let mut entry = PawnEntry {
+        key: pawn_hash,
+        ..Default::default()
+    };
+
+    for side in [Side::White, Side::Black] {
+        let (score, passed) = evaluate_side(board, side, tracer);
+        entry.scores[side as usize] = score;
+        entry.passed[side as usize] = passed;
+    }
+
+    entry
+}
+
+fn evaluate_side(board: &Board, side: Side, tracer: &mut impl Tracer) -> (PackedScore, Bitboard) {
+    let own = board.side_pieces(side).pawns();
+    let enemy = board.side_pieces(side.opposite()).pawns();
+    let mut score = PackedScore::ZERO;
+    let mut passed = Bitboard::new();
+
+    for square in own {
+        let rank = masks::relative_rank(side, square) as usize;
+        let adjacent = masks::adjacent_files(square.file());
+
+        let doubled = !(own & masks::forward_file(side, square)).is_empty();
+        let isolated = (own & adjacent).is_empty();
+        let supported = !(own & move_tables::pawn_attack_board(side.opposite(), square)).is_empty();
+        let phalanx = !(own & adjacent & masks::rank(square.rank())).is_empty();
+        let opposed = !(enemy & masks::passed_pawn_mask(side, square)).is_empty();
+
+        if doubled {
+            score += DOUBLED_PAWN.apply(tracer, side, 0, 1);
+        }
+        if isolated {
+            score += ISOLATED_PAWN.apply(tracer, side, 0, 1);
+        }
+        if supported {
+            score += SUPPORTED_PAWN.apply(tracer, side, rank, 1);
+        }
+        if phalanx {
+            score += PHALANX_PAWN.apply(tracer, side, rank, 1);
+        }
+
+        // Nothing beside or behind it can ever come up to defend it, and the square in
+        // front is covered by an enemy pawn.
+        if !isolated && !supported && !phalanx && opposed {
+            let helpers = own & adjacent & !masks::attack_span(side, square);
+            let stop = stop_square(side, square);
+            let stop_attacked = !(enemy & move_tables::pawn_attack_board(side, stop)).is_empty();
+            if helpers.is_empty() && stop_attacked {
+                score += BACKWARD_PAWN.apply(tracer, side, 0, 1);
+            }
+        }
+
+        // the rear pawn of a doubled pair counts through the front one
+        if !opposed && !doubled {
+            score += PASSED_PAWN.apply(tracer, side, rank, 1);
+            passed.set(square);
+        }
+    }
+
+    (score, passed)
+}
+
+fn stop_square(side: Side, square: Square) -> Square {
+    if side == Side::White {
+        Square::new(square.index() + 8)
+    } else {
+        Square::new(square.index() - 8)
+    }
+}
+
+/// Passed pawn terms that depend on the kings and the other pieces, so they can't be
+/// cached with the structure. Indexed by side, each from its own point of view.
+pub fn evaluate_passed(
+    board: &Board,
+    entry: &PawnEntry,
+    tracer: &mut impl Tracer,
+) -> [PackedScore; 2] {
+    let occupied = board.occupied();
+    let mut scores = [PackedScore::ZERO; 2];
+
+    for side in [Side::White, Side::Black] {
+        let (Some(own_king), Some(enemy_king)) = (
+            board.side_pieces(side).king_square(),
+            board.side_pieces(side.opposite()).king_square(),
+        ) else {
+            continue;
+        };
+
+        let side_score = &mut scores[side as usize];
+        for square in entry.passed(side) {
+            let rank = masks::relative_rank(side, square) as i32;
+            // only pawns past the middle of the board are worth escorting
+            let weight = (rank - 2).max(0);
+            if weight == 0 {
+                continue;
+            }
+
+            let stop = stop_square(side, square);
+            let enemy_distance = masks::distance(enemy_king, stop).min(5) * weight;
+            let own_distance = masks::distance(own_king, stop) * weight;
+            *side_score += PASSED_ENEMY_KING_DISTANCE.apply(tracer, side, 0, enemy_distance);
+            *side_score += PASSED_OWN_KING_DISTANCE.apply(tracer, side, 0, own_distance);
+            if (occupied & masks::forward_file(side, square)).is_empty() {
+                *side_score += PASSED_FREE_PATH.apply(tracer, side, 0, weight);
+            }
+        }
+    }
+
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate_pawns, PawnTable};
+    use crate::chess::game::{Game, Side};
+    use crate::chess::moves::parse_uci_move;
+    use crate::eval::params::*;
+    use crate::eval::trace::NoTrace;
+
+    fn entry(fen: &str) -> super::PawnEntry {
+        let game = Game::from_fen(fen.to_string());
+        evaluate_pawns(game.board(), game.pawn_hash(), &mut NoTrace)
+    }
+
+    #[test]
+    fn structure_terms() {
+        // a2 is the rear of a doubled pair and a3 is passed, both are isolated
+        let white = entry("4k3/8/8/8/8/P7/P7/4K3 w - - 0 1").score(Side::White);
+        let expected = DOUBLED_PAWN.get(0) + ISOLATED_PAWN.get(0) * 2 + PASSED_PAWN.get(2);
+        assert_eq!(white, expected);
+
+        // a2 and h5 are free, d3 holds up both black pawns and is held up itself
+        let entry = entry("4k3/2p5/8/3p3P/8/3P4/P7/4K3 w - - 0 1");
+        assert_eq!(entry.passed(Side::White).data(), (1 << 8) | (1 << 39));
+        assert!(entry.passed(Side::Black).is_empty());
+    }
+
+    #[test]
+    fn probes_match_a_fresh_evaluation() {
+        let mut table = PawnTable::new();
+        let mut results = Vec::new();
+        // the same structure through three move orders, checked after every move
+        for moves in [
+            ["e2e4", "g8f6", "d2d4", "d7d5", "e4d5"],
+            ["d2d4", "g8f6", "e2e4", "d7d5", "e4d5"],
+            ["d2d4", "d7d5", "e2e4", "g8f6", "e4d5"],
+        ] {
+            let mut game = Game::new_classical();
+            for uci in moves {
+                let mv = parse_uci_move(&mut game, uci).unwrap();
+                game.make_move(mv);
+
+                let cached = table.probe(game.board(), game.pawn_hash());
+                let fresh = evaluate_pawns(game.board(), game.pawn_hash(), &mut NoTrace);
+                for side in [Side::White, Side::Black] {
+                    assert_eq!(cached.score(side), fresh.score(side), "{}", game.to_fen());
+                    assert_eq!(cached.passed(side).data(), fresh.passed(side).data());
+                }
+            }
+            results.push(game.pawn_hash());
+        }
+        assert!(results.iter().all(|hash| *hash == results[0]));
+    }
+}
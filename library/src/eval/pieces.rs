@@ -0,0 +1,198 @@
+use crate::chess::board::{Board, Piece, Square};
+use crate::chess::game::Side;
+
+use super::attacks::{piece_attacks, AttackMaps};
+use super::masks;
+use super::packed::PackedScore;
+use super::params::*;
+use super::trace::Tracer;
+
+fn mobility_param(piece: Piece) -> Param {
+    match piece {
+        Piece::Knight => KNIGHT_MOBILITY,
+        Piece::Bishop => BISHOP_MOBILITY,
+        Piece::Rook => ROOK_MOBILITY,
+        Piece::Queen => QUEEN_MOBILITY,
+        _ => panic!("mobility_param: no mobility table for {:?}", piece),
+    }
+}
+
+/// A minor piece that pawns defend and can never chase away.
+fn is_outpost(board: &Board, side: Side, square: Square, attacks: &AttackMaps) -> bool {
+    let rank = masks::relative_rank(side, square);
+    let enemy_pawns = board.side_pieces(side.opposite()).pawns();
+
+    (3..=5).contains(&rank)
+        && attacks.by_piece(side, Piece::Pawn).is_set(square)
+        && (enemy_pawns & masks::attack_span(side, square)).is_empty()
+}
+
+fn is_trapped_rook(board: &Board, side: Side, square: Square, mobility: u32) -> bool {
+    let Some(king_square) = board.side_pieces(side).king_square() else {
+        return false;
+    };
+    if mobility > 3
+        || masks::relative_rank(side, square) != 0
+        || masks::relative_rank(side, king_square) != 0
+    {
+        return false;
+    }
+
+    // The rook is in the corner the king blocks off. A king still on e1 can usually
+    // castle its way out of it.
+    let king_file = king_square.file();
+    (king_file > 4 && square.file() > king_file) || (king_file < 4 && square.file() < king_file)
+}
+
+fn is_trapped_bishop(board: &Board, side: Side, square: Square) -> bool {
+    let enemy_pawns = board.side_pieces(side.opposite()).pawns();
+    let rank = masks::relative_rank(side, square);
+    if rank != 6 {
+        return false;
+    }
+
+    let blocker_rank = if side == Side::White { 5 } else { 2 };
+    match square.file() {
+        0 => enemy_pawns.is_set(Square::new_file_rank(1, blocker_rank)),
+        7 => enemy_pawns.is_set(Square::new_file_rank(6, blocker_rank)),
+        _ => false,
+    }
+}
+
+/// (mobility, placement) for `side`'s pieces, from its own point of view.
+pub fn evaluate_side(
+    board: &Board,
+    side: Side,
+    attacks: &AttackMaps,
+    tracer: &mut impl Tracer,
+) -> (PackedScore, PackedScore) {
+    let pieces = board.side_pieces(side);
+    let enemy = board.side_pieces(side.opposite());
+    let occupied = board.occupied();
+    let all_pawns = pieces.pawns() | enemy.pawns();
+    // squares attacked by enemy pawns, or blocked by our own pawns and king, don't count
+    let mobility_area =
+        !(pieces.pawns() | pieces.kings() | attacks.by_piece(side.opposite(), Piece::Pawn));
+    let mut mobility_score = PackedScore::ZERO;
+    let mut score = PackedScore::ZERO;
+
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        for square in pieces.pieces(piece) {
+            let squares = (piece_attacks(piece, occupied, square) & mobility_area).count() as u32;
+            mobility_score += mobility_param(piece).apply(tracer, side, squares as usize, 1);
+
+            match piece {
+                Piece::Knight if is_outpost(board, side, square, attacks) => {
+                    score += KNIGHT_OUTPOST.apply(tracer, side, 0, 1);
+                }
+                Piece::Bishop => {
+                    if is_outpost(board, side, square, attacks) {
+                        score += BISHOP_OUTPOST.apply(tracer, side, 0, 1);
+                    }
+                    if is_trapped_bishop(board, side, square) {
+                        score += TRAPPED_BISHOP.apply(tracer, side, 0, 1);
+                    }
+                }
+                Piece::Rook => {
+                    let file = masks::file(square.file());
+                    if (file & all_pawns).is_empty() {
+                        score += ROOK_OPEN_FILE.apply(tracer, side, 0, 1);
+                    } else if (file & pieces.pawns()).is_empty() {
+                        score += ROOK_SEMI_OPEN_FILE.apply(tracer, side, 0, 1);
+                    }
+
+                    if masks::relative_rank(side, square) == 6 {
+                        let seventh = masks::rank(square.rank());
+                        let eighth = masks::rank(if side == Side::White { 7 } else { 0 });
+                        if !(enemy.pawns() & seventh).is_empty()
+                            || !(enemy.kings() & eighth).is_empty()
+                        {
+                            score += ROOK_ON_SEVENTH.apply(tracer, side, 0, 1);
+                        }
+                    }
+
+                    if is_trapped_rook(board, side, square, squares) {
+                        score += TRAPPED_ROOK.apply(tracer, side, 0, 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if pieces.bishops().count() >= 2 {
+        score += BISHOP_PAIR.apply(tracer, side, 0, 1);
+    }
+
+    (mobility_score, score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::evaluate_side;
+    use crate::chess::game::{Game, Side};
+    use crate::eval::attacks::AttackMaps;
+    use crate::eval::packed::PackedScore;
+    use crate::eval::params::*;
+    use crate::eval::trace::NoTrace;
+
+    /// White's (mobility, placement).
+    fn white(fen: &str) -> (PackedScore, PackedScore) {
+        let game = Game::from_fen(fen.to_string());
+        let board = game.board();
+        evaluate_side(board, Side::White, &AttackMaps::new(board), &mut NoTrace)
+    }
+
+    #[test]
+    fn mobility_skips_squares_enemy_pawns_attack() {
+        let (mobility, _) = white("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1");
+        assert_eq!(mobility, KNIGHT_MOBILITY.get(8));
+        // e6 takes away f5, but the pawn itself can be taken
+        let (mobility, _) = white("4k3/8/4p3/8/3N4/8/8/4K3 w - - 0 1");
+        assert_eq!(mobility, KNIGHT_MOBILITY.get(7));
+    }
+
+    #[test]
+    fn placement_terms() {
+        let placement = |fen: &str| white(fen).1;
+        assert_eq!(
+            placement("4k3/8/8/3N4/4P3/8/8/4K3 w - - 0 1"),
+            KNIGHT_OUTPOST.get(0)
+        );
+        // c7 can still come down to chase it
+        assert_eq!(
+            placement("4k3/2p5/8/3N4/4P3/8/8/4K3 w - - 0 1"),
+            PackedScore::ZERO
+        );
+
+        assert_eq!(
+            placement("4k3/8/8/8/8/8/8/3RK3 w - - 0 1"),
+            ROOK_OPEN_FILE.get(0)
+        );
+        assert_eq!(
+            placement("4k3/8/3p4/8/8/8/8/3RK3 w - - 0 1"),
+            ROOK_SEMI_OPEN_FILE.get(0)
+        );
+        assert_eq!(
+            placement("4k3/8/8/8/8/8/3P4/3RK3 w - - 0 1"),
+            PackedScore::ZERO
+        );
+        assert_eq!(
+            placement("4k3/R7/8/8/8/8/8/4K3 w - - 0 1"),
+            ROOK_OPEN_FILE.get(0) + ROOK_ON_SEVENTH.get(0)
+        );
+
+        assert_eq!(
+            placement("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"),
+            BISHOP_PAIR.get(0)
+        );
+        assert_eq!(
+            placement("4k3/8/8/8/8/8/6PP/5K1R w - - 0 1"),
+            TRAPPED_ROOK.get(0)
+        );
+        assert_eq!(
+            placement("4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1"),
+            TRAPPED_BISHOP.get(0)
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use crate::chess::board::{Piece, Square};
+use crate::chess::game::Side;
+
+use super::packed::PackedScore;
+use super::params::{
+    Param, BISHOP_PSQT, KING_PSQT, KNIGHT_PSQT, PAWN_PSQT, PIECE_VALUE, QUEEN_PSQT, ROOK_PSQT,
+};
+
+/// Placement tables indexed by `Piece`.
+const TABLES: [Param; 6] = [
+    PAWN_PSQT,
+    KNIGHT_PSQT,
+    BISHOP_PSQT,
+    ROOK_PSQT,
+    QUEEN_PSQT,
+    KING_PSQT,
+];
+
+/// The placement table of `piece` and where `side`'s piece on `square` is in it. The
+/// tables are laid out the way a board is printed, from a8 to h1, from white's point of
+/// view, so black looks them up mirrored.
+pub fn table_entry(side: Side, piece: Piece, square: Square) -> (Param, usize) {
+    let index = square.index() as usize;
+    // a1 is 0 on our board but the last row of the tables
+    let index = if side == Side::White {
+        index ^ 56
+    } else {
+        index
+    };
+    (TABLES[piece as usize], index)
+}
+
+lazy_static! {
+    /// Material plus placement for every piece on every square, from white's point of
+    /// view: black entries are negated so a position's score is just the sum.
+    static ref PSQT: [[[PackedScore; 64]; 6]; 2] = {
+        let mut psqt = [[[PackedScore::ZERO; 64]; 6]; 2];
+        for piece in Piece::ALL {
+            let value = PIECE_VALUE.get(piece as usize);
+            for index in 0..64 {
+                let square = Square::new(index);
+                for side in [Side::White, Side::Black] {
+                    let (table, entry) = table_entry(side, piece, square);
+                    let score = value + table.get(entry);
+                    psqt[side as usize][piece as usize][index as usize] =
+                        if side == Side::White { score } else { -score };
+                }
+            }
+        }
+
+        psqt
+    };
+}
+
+pub fn psqt(side: Side, piece: Piece, square: Square) -> PackedScore {
+    PSQT[side as usize][piece as usize][square.index() as usize]
+}
+
+/// A Crazyhouse piece in hand, worth its material alone and signed like `psqt`.
+pub fn pocket(side: Side, piece: Piece) -> PackedScore {
+    let value = PIECE_VALUE.get(piece as usize);
+    if side == Side::White {
+        value
+    } else {
+        -value
+    }
+}
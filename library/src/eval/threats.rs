@@ -0,0 +1,31 @@
+use crate::chess::board::{Board, Piece};
+use crate::chess::game::Side;
+
+use super::attacks::AttackMaps;
+use super::packed::PackedScore;
+use super::params::*;
+use super::trace::Tracer;
+
+/// Enemy pieces `side` is threatening to win, from `side`'s point of view.
+pub fn evaluate_side(
+    board: &Board,
+    side: Side,
+    attacks: &AttackMaps,
+    tracer: &mut impl Tracer,
+) -> PackedScore {
+    let enemy = side.opposite();
+    let enemy_pieces = board.side_pieces(enemy);
+    let pawn_attacks = attacks.by_piece(side, Piece::Pawn);
+    let mut score = PackedScore::ZERO;
+
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        let targets = enemy_pieces.pieces(piece);
+        let threatened = (targets & pawn_attacks).count() as i32;
+        score += THREAT_BY_PAWN.apply(tracer, side, piece as usize, threatened);
+
+        let hanging = targets & attacks.all(side) & !attacks.all(enemy);
+        score += HANGING_PIECE.apply(tracer, side, 0, hanging.count() as i32);
+    }
+
+    score
+}
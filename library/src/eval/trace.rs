@@ -0,0 +1,213 @@
+use std::fmt;
+
+use crate::chess::game::Side;
+
+use super::endgame::SCALE_NORMAL;
+use super::packed::PackedScore;
+use super::params::PARAM_COUNT;
+use super::{taper, MAX_PHASE};
+
+/// Receives every use of a tunable parameter during evaluation.
+pub trait Tracer {
+    /// Entry `index` of `PARAMS` counted `count` times for `side`.
+    fn record(&mut self, side: Side, index: usize, count: i32);
+}
+
+/// For the normal evaluation, which doesn't need to know.
+pub struct NoTrace;
+
+/// How many times each entry of `PARAMS` counts for white minus for black. The
+/// tunable part of the evaluation is the dot product of these with `PARAMS`.
+#[derive(Clone, Debug)]
+pub struct Coefficients {
+    values: Vec<i32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Term {
+    Material = 0,
+    Psqt = 1,
+    Pawns = 2,
+    PassedPawns = 3,
+    KingSafety = 4,
+    Mobility = 5,
+    Pieces = 6,
+    Threats = 7,
+}
+
+/// Every evaluation term, split by side and by phase, for explaining a score.
+#[derive(Clone, Debug)]
+pub struct EvalTrace {
+    pub coefficients: Coefficients,
+    /// Indexed by `Term` then `Side`, each side from its own point of view.
+    pub terms: [[PackedScore; 2]; Term::COUNT],
+    pub phase: i32,
+    /// Danger units against each side's king, see `eval::king_danger`.
+    pub king_danger: [i32; 2],
+    /// The specialized evaluation or scaling rule used for the material, if any.
+    pub endgame: Option<&'static str>,
+    /// Scale factor of the endgame half, out of `SCALE_NORMAL`.
+    pub scale: i32,
+    /// Final tapered score from white's point of view.
+    pub score: i32,
+}
+
+impl Tracer for NoTrace {
+    #[inline(always)]
+    fn record(&mut self, _side: Side, _index: usize, _count: i32) {}
+}
+
+impl Coefficients {
+    pub fn new() -> Self {
+        Self {
+            values: vec![0; PARAM_COUNT],
+        }
+    }
+
+    pub fn values(&self) -> &[i32] {
+        &self.values
+    }
+}
+
+impl Default for Coefficients {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tracer for Coefficients {
+    fn record(&mut self, side: Side, index: usize, count: i32) {
+        if side == Side::White {
+            self.values[index] += count;
+        } else {
+            self.values[index] -= count;
+        }
+    }
+}
+
+impl Term {
+    pub const COUNT: usize = 8;
+
+    pub const ALL: [Term; Term::COUNT] = [
+        Term::Material,
+        Term::Psqt,
+        Term::Pawns,
+        Term::PassedPawns,
+        Term::KingSafety,
+        Term::Mobility,
+        Term::Pieces,
+        Term::Threats,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::Psqt => "PSQT",
+            Term::Pawns => "Pawns",
+            Term::PassedPawns => "Passed pawns",
+            Term::KingSafety => "King safety",
+            Term::Mobility => "Mobility",
+            Term::Pieces => "Pieces",
+            Term::Threats => "Threats",
+        }
+    }
+}
+
+impl EvalTrace {
+    pub fn new(
+        terms: [[PackedScore; 2]; Term::COUNT],
+        phase: i32,
+        king_danger: [i32; 2],
+        coefficients: Coefficients,
+    ) -> Self {
+        let mut trace = Self {
+            coefficients,
+            terms,
+            phase,
+            king_danger,
+            endgame: None,
+            scale: SCALE_NORMAL,
+            score: 0,
+        };
+        trace.score = taper(trace.total_score(), phase);
+
+        trace
+    }
+
+    pub fn term(&self, term: Term, side: Side) -> PackedScore {
+        self.terms[term as usize][side as usize]
+    }
+
+    /// White's share of `term` minus black's.
+    pub fn total(&self, term: Term) -> PackedScore {
+        self.term(term, Side::White) - self.term(term, Side::Black)
+    }
+
+    /// Sum of all terms from white's point of view, before tapering.
+    pub fn total_score(&self) -> PackedScore {
+        Term::ALL
+            .into_iter()
+            .fold(PackedScore::ZERO, |sum, term| sum + self.total(term))
+    }
+}
+
+/// Centipawns as pawns with two decimals.
+fn pawns(centipawns: i32) -> String {
+    format!("{:>6.2}", centipawns as f64 / 100.0)
+}
+
+fn phases(score: PackedScore) -> String {
+    format!("{} {}", pawns(score.mg()), pawns(score.eg()))
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = "--------------+---------------+---------------+--------------";
+        writeln!(
+            f,
+            "         Term |     White     |     Black     |     Total"
+        )?;
+        writeln!(
+            f,
+            "              |   MG     EG   |   MG     EG   |   MG     EG"
+        )?;
+        writeln!(f, "{}", separator)?;
+        for term in Term::ALL {
+            writeln!(
+                f,
+                " {:>12} | {} | {} | {}",
+                term.name(),
+                phases(self.term(term, Side::White)),
+                phases(self.term(term, Side::Black)),
+                phases(self.total(term))
+            )?;
+        }
+        writeln!(f, "{}", separator)?;
+        writeln!(
+            f,
+            " {:>12} |               |               | {}",
+            "Total",
+            phases(self.total_score())
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/{}", self.phase, MAX_PHASE)?;
+        writeln!(
+            f,
+            "King danger: white {} black {}",
+            self.king_danger[Side::White as usize],
+            self.king_danger[Side::Black as usize]
+        )?;
+        if let Some(endgame) = self.endgame {
+            write!(f, "Endgame: {}", endgame)?;
+            if self.scale != SCALE_NORMAL {
+                write!(f, ", scale factor {}/{}", self.scale, SCALE_NORMAL)?;
+            }
+            writeln!(f)?;
+        }
+        write!(
+            f,
+            "Final evaluation: {} (white side)",
+            pawns(self.score).trim_start()
+        )
+    }
+}
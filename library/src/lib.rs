@@ -1,5 +1,12 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod book;
 pub mod chess;
-pub mod utils;
\ No newline at end of file
+pub mod eval;
+#[cfg(feature = "nnue")]
+pub mod nnue;
+pub mod search;
+pub mod syzygy;
+pub mod tablebase;
+pub mod utils;
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use crate::chess::board::{Board, Piece, Square};
+use crate::chess::game::Side;
+
+use super::network::{feature_index, Network};
+
+/// Feature transformer outputs for both perspectives, one entry per ply so that
+/// unmaking a move is just a pop. Empty and free when no network is loaded.
+#[derive(Clone, Default)]
+pub struct Accumulators {
+    network: Option<Arc<Network>>,
+    /// White's then black's accumulator for each ply, back to back.
+    values: Vec<i16>,
+    /// Perspectives whose king moved in the move being made; rebuilt in `finish`.
+    dirty: [bool; 2],
+}
+
+impl Accumulators {
+    pub fn new(network: Option<Arc<Network>>, board: &Board) -> Self {
+        let mut accumulators = Self {
+            network,
+            values: Vec::new(),
+            dirty: [false; 2],
+        };
+        accumulators.reset(board);
+
+        accumulators
+    }
+
+    pub fn network(&self) -> Option<&Arc<Network>> {
+        self.network.as_ref()
+    }
+
+    fn stride(&self) -> usize {
+        self.network
+            .as_ref()
+            .map_or(0, |network| 2 * network.accumulator_size())
+    }
+
+    /// The current ply's accumulators, white's then black's.
+    pub fn current(&self) -> &[i16] {
+        &self.values[self.values.len() - self.stride()..]
+    }
+
+    /// `perspective`'s accumulator for the current ply.
+    fn perspective_mut(values: &mut [i16], size: usize, perspective: Side) -> &mut [i16] {
+        let start = values.len() - 2 * size + perspective as usize * size;
+        &mut values[start..start + size]
+    }
+
+    /// Throws away the history and computes the current position from scratch.
+    pub fn reset(&mut self, board: &Board) {
+        self.values.clear();
+        self.values.resize(self.stride(), 0);
+        for perspective in [Side::White, Side::Black] {
+            self.refresh(board, perspective);
+        }
+    }
+
+    fn refresh(&mut self, board: &Board, perspective: Side) {
+        let Some(network) = self.network.as_deref() else {
+            return;
+        };
+        let size = network.accumulator_size();
+        let accumulator = Self::perspective_mut(&mut self.values, size, perspective);
+        network.refresh(board, perspective, accumulator);
+    }
+
+    /// Starts a new ply as a copy of the current one.
+    pub fn push(&mut self) {
+        let stride = self.stride();
+        if stride > 0 {
+            self.values.extend_from_within(self.values.len() - stride..);
+        }
+    }
+
+    pub fn pop(&mut self) {
+        let stride = self.stride();
+        self.values.truncate(self.values.len() - stride);
+    }
+
+    /// Applies a piece appearing on (`add`) or leaving `square`. The board must
+    /// already show both kings where they are for the perspectives being updated.
+    pub fn update(&mut self, board: &Board, side: Side, piece: Piece, square: Square, add: bool) {
+        let Some(network) = self.network.as_deref() else {
+            return;
+        };
+
+        // HalfKP is relative to the king, so a king move invalidates its whole side
+        if piece == Piece::King {
+            self.dirty[side as usize] = true;
+            return;
+        }
+
+        for perspective in [Side::White, Side::Black] {
+            if self.dirty[perspective as usize] {
+                continue;
+            }
+            let Some(king_square) = board.side_pieces(perspective).king_square() else {
+                continue;
+            };
+            let feature = feature_index(perspective, king_square, side, piece, square);
+            let size = network.accumulator_size();
+            let accumulator = Self::perspective_mut(&mut self.values, size, perspective);
+            network.update(accumulator, feature, add);
+        }
+    }
+
+    /// Rebuilds whatever a king move invalidated.
+    pub fn finish(&mut self, board: &Board) {
+        for perspective in [Side::White, Side::Black] {
+            if self.dirty[perspective as usize] {
+                self.refresh(board, perspective);
+                self.dirty[perspective as usize] = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+pub mod accumulator;
+pub mod network;
+
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::chess::game::Game;
+
+use network::Network;
+
+lazy_static! {
+    /// The network new games pick up. Games keep their own reference, so replacing it
+    /// doesn't disturb a running search.
+    static ref NETWORK: RwLock<Option<Arc<Network>>> = RwLock::new(None);
+}
+
+pub fn current_network() -> Option<Arc<Network>> {
+    NETWORK.read().expect("network lock poisoned").clone()
+}
+
+pub fn set_network(network: Option<Arc<Network>>) {
+    *NETWORK.write().expect("network lock poisoned") = network;
+}
+
+/// Loads a network file and makes it the one new games use.
+pub fn load(path: impl AsRef<Path>) -> io::Result<()> {
+    let network = Network::load(path)?;
+    set_network(Some(Arc::new(network)));
+
+    Ok(())
+}
+
+/// Network evaluation from the side to move's point of view, or `None` when the game
+/// has no network and the hand-crafted evaluation should be used.
+pub fn evaluate(game: &Game) -> Option<i32> {
+    let accumulators = game.accumulators();
+    let network = accumulators.network()?;
+
+    Some(network.evaluate(accumulators.current(), game.active()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::accumulator::Accumulators;
+    use super::network::{Network, HALFKP_INPUTS};
+    use crate::chess::game::Game;
+    use crate::chess::moves::generate_legal_moves;
+    use crate::utils::random::Random;
+
+    /// A tiny network with random weights, written out in the file format.
+    fn tiny_network_bytes() -> Vec<u8> {
+        let (l1, l2, l3) = (8_usize, 4_usize, 4_usize);
+        let mut random = Random::new(0x5eed);
+        let mut small = |bound: u64| random.next_below(2 * bound) as i64 - bound as i64;
+
+        let mut bytes = b"NNUE".to_vec();
+        for value in [1, 0, l1, l2, l3] {
+            bytes.extend((value as u32).to_le_bytes());
+        }
+        for _ in 0..l1 {
+            bytes.extend((small(32) as i16).to_le_bytes());
+        }
+        for _ in 0..HALFKP_INPUTS * l1 {
+            bytes.extend((small(16) as i16).to_le_bytes());
+        }
+        for (inputs, outputs) in [(2 * l1, l2), (l2, l3), (l3, 1)] {
+            for _ in 0..outputs {
+                bytes.extend((small(2000) as i32).to_le_bytes());
+            }
+            for _ in 0..inputs * outputs {
+                bytes.push(small(64) as i8 as u8);
+            }
+        }
+
+        bytes
+    }
+
+    fn tiny_network() -> Arc<Network> {
+        Arc::new(Network::from_bytes(&tiny_network_bytes()).expect("tiny network loads"))
+    }
+
+    fn assert_matches_refresh(game: &Game, network: &Arc<Network>) {
+        let fresh = Accumulators::new(Some(network.clone()), game.board());
+        assert_eq!(
+            game.accumulators().current(),
+            fresh.current(),
+            "{}",
+            game.to_fen()
+        );
+        assert_eq!(
+            super::evaluate(game),
+            Some(network.evaluate(fresh.current(), game.active()))
+        );
+    }
+
+    #[test]
+    fn incremental_updates_match_full_refresh() {
+        let network = tiny_network();
+        let mut random = Random::new(42);
+        let mut game = Game::from_fen(String::from(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ));
+        game.set_network(Some(network.clone()));
+        assert_matches_refresh(&game, &network);
+
+        for _ in 0..400 {
+            let moves = generate_legal_moves(&mut game);
+            // back up every so often, and whenever the game is over
+            if moves.is_empty() || (game.ply() > 0 && random.next_below(4) == 0) {
+                game.unmake_move();
+            } else {
+                game.make_move(moves[random.next_below(moves.len() as u64) as usize]);
+            }
+            assert_matches_refresh(&game, &network);
+        }
+    }
+
+    #[test]
+    fn truncated_network_is_rejected() {
+        let bytes = tiny_network_bytes();
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::from_bytes(b"not a network").is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::chess::board::{Board, Piece, Square};
+use crate::chess::game::Side;
+
+/// HalfKP inputs: for each of our king's squares, every non-king piece of either colour
+/// on every square, plus one unused slot per king square as in the original layout.
+pub const HALFKP_INPUTS: usize = 64 * PIECE_SQUARES;
+const PIECE_SQUARES: usize = 10 * 64 + 1;
+
+const MAGIC: &[u8; 4] = b"NNUE";
+const VERSION: u32 = 1;
+const FEATURE_SET_HALFKP: u32 = 0;
+
+/// Largest layer sizes we accept, so the forward pass can work on the stack.
+const MAX_L1: usize = 2048;
+const MAX_HIDDEN: usize = 256;
+
+/// Hidden layer sums are shifted down by this many bits before clipping.
+const WEIGHT_SHIFT: u32 = 6;
+/// Network output units per centipawn.
+const OUTPUT_SCALE: i32 = 16;
+
+/// A quantized HalfKP network: feature transformer (int16) into two clipped ReLU
+/// hidden layers and a single output (int8 weights, int32 biases).
+///
+/// File layout, all little-endian: the magic `NNUE`, then u32 version, feature set
+/// (0 = HalfKP), and the three layer sizes `l1`, `l2`, `l3`. After that come the
+/// feature transformer biases (i16 × l1) and weights (i16 × inputs × l1, grouped by
+/// feature), the first hidden layer biases (i32 × l2) and weights (i8 × l2 × 2·l1,
+/// grouped by output), the second hidden layer (i32 × l3, i8 × l3 × l2) and finally
+/// the output bias (i32) and weights (i8 × l3).
+pub struct Network {
+    l1: usize,
+    l2: usize,
+    l3: usize,
+    ft_biases: Vec<i16>,
+    ft_weights: Vec<i16>,
+    hidden1_biases: Vec<i32>,
+    hidden1_weights: Vec<i8>,
+    hidden2_biases: Vec<i32>,
+    hidden2_weights: Vec<i8>,
+    output_bias: i32,
+    output_weights: Vec<i8>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "network file is truncated",
+            ));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32s(&mut self, count: usize) -> io::Result<Vec<i32>> {
+        let bytes = self.take(count * 4)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect())
+    }
+
+    fn i16s(&mut self, count: usize) -> io::Result<Vec<i16>> {
+        let bytes = self.take(count * 2)?;
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect())
+    }
+
+    fn i8s(&mut self, count: usize) -> io::Result<Vec<i8>> {
+        Ok(self.take(count)?.iter().map(|byte| *byte as i8).collect())
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Index of the input feature for `piece` of `side` on `square`, seen from
+/// `perspective` with its king on `king_square`. Black sees the board rotated.
+pub fn feature_index(
+    perspective: Side,
+    king_square: Square,
+    side: Side,
+    piece: Piece,
+    square: Square,
+) -> usize {
+    let orient = |square: Square| {
+        if perspective == Side::White {
+            square.index() as usize
+        } else {
+            square.index() as usize ^ 63
+        }
+    };
+    let piece_index = piece as usize * 2 + usize::from(side != perspective);
+
+    orient(king_square) * PIECE_SQUARES + 1 + piece_index * 64 + orient(square)
+}
+
+fn clipped_relu(value: i32) -> u8 {
+    value.clamp(0, 127) as u8
+}
+
+/// Dot product of int8 weights and uint8 activations. Written over fixed-size chunks
+/// so the compiler can vectorise it without target-specific code.
+fn dot(weights: &[i8], inputs: &[u8]) -> i32 {
+    const LANES: usize = 16;
+    let mut lanes = [0_i32; LANES];
+    let weight_chunks = weights.chunks_exact(LANES);
+    let input_chunks = inputs.chunks_exact(LANES);
+    let (weight_rest, input_rest) = (weight_chunks.remainder(), input_chunks.remainder());
+    for (weights, inputs) in weight_chunks.zip(input_chunks) {
+        for lane in 0..LANES {
+            lanes[lane] += weights[lane] as i32 * inputs[lane] as i32;
+        }
+    }
+
+    let rest: i32 = weight_rest
+        .iter()
+        .zip(input_rest)
+        .map(|(weight, input)| *weight as i32 * *input as i32)
+        .sum();
+    lanes.iter().sum::<i32>() + rest
+}
+
+/// One fully connected layer followed by a clipped ReLU.
+fn hidden_layer(biases: &[i32], weights: &[i8], inputs: &[u8], outputs: &mut [u8]) {
+    let rows = weights.chunks_exact(inputs.len());
+    for ((output, bias), row) in outputs.iter_mut().zip(biases).zip(rows) {
+        *output = clipped_relu((bias + dot(row, inputs)) >> WEIGHT_SHIFT);
+    }
+}
+
+impl Network {
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC {
+            return Err(invalid("not a network file"));
+        }
+        if reader.u32()? != VERSION {
+            return Err(invalid("unsupported network version"));
+        }
+        if reader.u32()? != FEATURE_SET_HALFKP {
+            return Err(invalid("unsupported feature set"));
+        }
+
+        let l1 = reader.u32()? as usize;
+        let l2 = reader.u32()? as usize;
+        let l3 = reader.u32()? as usize;
+        if !(1..=MAX_L1).contains(&l1)
+            || !(1..=MAX_HIDDEN).contains(&l2)
+            || !(1..=MAX_HIDDEN).contains(&l3)
+        {
+            return Err(invalid("bad layer sizes"));
+        }
+
+        let network = Self {
+            l1,
+            l2,
+            l3,
+            ft_biases: reader.i16s(l1)?,
+            ft_weights: reader.i16s(HALFKP_INPUTS * l1)?,
+            hidden1_biases: reader.i32s(l2)?,
+            hidden1_weights: reader.i8s(l2 * 2 * l1)?,
+            hidden2_biases: reader.i32s(l3)?,
+            hidden2_weights: reader.i8s(l3 * l2)?,
+            output_bias: reader.i32s(1)?[0],
+            output_weights: reader.i8s(l3)?,
+        };
+        if !reader.bytes.is_empty() {
+            return Err(invalid("trailing data after network"));
+        }
+
+        Ok(network)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Feature transformer width, i.e. accumulator length per perspective.
+    pub fn accumulator_size(&self) -> usize {
+        self.l1
+    }
+
+    fn feature_weights(&self, feature: usize) -> &[i16] {
+        &self.ft_weights[feature * self.l1..(feature + 1) * self.l1]
+    }
+
+    /// Adds (or subtracts) one feature's weights to an accumulator.
+    pub fn update(&self, accumulator: &mut [i16], feature: usize, add: bool) {
+        let weights = self.feature_weights(feature);
+        if add {
+            for (value, weight) in accumulator.iter_mut().zip(weights) {
+                *value = value.wrapping_add(*weight);
+            }
+        } else {
+            for (value, weight) in accumulator.iter_mut().zip(weights) {
+                *value = value.wrapping_sub(*weight);
+            }
+        }
+    }
+
+    /// Recomputes `perspective`'s accumulator from scratch.
+    pub fn refresh(&self, board: &Board, perspective: Side, accumulator: &mut [i16]) {
+        accumulator.copy_from_slice(&self.ft_biases);
+        let Some(king_square) = board.side_pieces(perspective).king_square() else {
+            return;
+        };
+
+        for side in [Side::White, Side::Black] {
+            for piece in [
+                Piece::Pawn,
+                Piece::Knight,
+                Piece::Bishop,
+                Piece::Rook,
+                Piece::Queen,
+            ] {
+                for square in board.side_pieces(side).pieces(piece) {
+                    let feature = feature_index(perspective, king_square, side, piece, square);
+                    self.update(accumulator, feature, true);
+                }
+            }
+        }
+    }
+
+    /// Runs the layers on top of the accumulators (white's then black's, `l1` each) and
+    /// returns centipawns from `active`'s point of view.
+    pub fn evaluate(&self, accumulators: &[i16], active: Side) -> i32 {
+        let (white, black) = accumulators.split_at(self.l1);
+        let (own, other) = if active == Side::White {
+            (white, black)
+        } else {
+            (black, white)
+        };
+
+        let mut transformed = [0_u8; 2 * MAX_L1];
+        let transformed = &mut transformed[..2 * self.l1];
+        for (output, value) in transformed.iter_mut().zip(own.iter().chain(other)) {
+            *output = clipped_relu(*value as i32);
+        }
+
+        let mut hidden1 = [0_u8; MAX_HIDDEN];
+        let hidden1 = &mut hidden1[..self.l2];
+        hidden_layer(
+            &self.hidden1_biases,
+            &self.hidden1_weights,
+            transformed,
+            hidden1,
+        );
+        let mut hidden2 = [0_u8; MAX_HIDDEN];
+        let hidden2 = &mut hidden2[..self.l3];
+        hidden_layer(
+            &self.hidden2_biases,
+            &self.hidden2_weights,
+            hidden1,
+            hidden2,
+        );
+
+        (self.output_bias + dot(&self.output_weights, hidden2)) / OUTPUT_SCALE
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use super::transposition::Bound;
+use crate::chess::moves::Move;
+
+/// Progress report for one (re-)search of the iterative deepening loop.
+pub struct SearchInfo {
+    pub depth: u8,
+    pub seldepth: u8,
+    /// 1-based index of the line this report is about.
+    pub multipv: usize,
+    pub score: i32,
+    pub bound: Bound,
+    pub nodes: u64,
+    pub time: Duration,
+    pub hashfull: u32,
+    /// Tablebase probes made by the main thread.
+    pub tb_hits: u64,
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    pub fn nps(&self) -> u64 {
+        let seconds = self.time.as_secs_f64();
+        if seconds > 0.0 {
+            (self.nodes as f64 / seconds) as u64
+        } else {
+            0
+        }
+    }
+}
+
+/// How often each search extension fired, for tuning.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SearchStats {
+    pub check_extensions: u64,
+    pub singular_extensions: u64,
+    pub recapture_extensions: u64,
+    pub pawn_push_extensions: u64,
+}
+
+/// One of the lines of a MultiPV search.
+#[derive(Clone, Debug)]
+pub struct PvLine {
+    pub score: i32,
+    pub depth: u8,
+    pub pv: Vec<Move>,
+}
+
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u8,
+    pub pv: Vec<Move>,
+    /// Best first; a single line unless MultiPV was requested.
+    pub lines: Vec<PvLine>,
+    /// The reply we expect, to think about on the opponent's time.
+    pub ponder_move: Option<Move>,
+    pub stats: SearchStats,
+}
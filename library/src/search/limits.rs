@@ -0,0 +1,51 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::time::TimeManager;
+use crate::chess::moves::Move;
+use crate::syzygy::ProbeSettings;
+
+#[derive(Clone, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    /// Stop after exactly this many nodes, for reproducible runs. Helper threads are
+    /// not started for node-limited searches.
+    pub nodes: Option<u64>,
+    /// Stop once a mate in at most this many moves has been proven.
+    pub mate: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub time_manager: Option<TimeManager>,
+    pub infinite: bool,
+    /// Only consider these root moves; empty means all of them.
+    pub searchmoves: Vec<Move>,
+    /// Set while pondering. Time limits only apply once it's cleared (ponderhit).
+    pub ponder: Option<Arc<AtomicBool>>,
+    /// Number of root moves to report lines for; 0 and 1 both mean just the best.
+    pub multipv: usize,
+    /// How to use the Syzygy tablebases, if any are loaded.
+    pub syzygy: ProbeSettings,
+}
+
+impl SearchLimits {
+    pub fn new_depth(depth: u8) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    pub fn new_nodes(nodes: u64) -> Self {
+        Self {
+            nodes: Some(nodes),
+            ..Default::default()
+        }
+    }
+
+    pub fn new_movetime(movetime: Duration) -> Self {
+        Self {
+            movetime: Some(movetime),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,8 @@
+pub mod info;
+pub mod limits;
+pub mod ordering;
+pub mod score;
+pub mod searcher;
+pub mod threads;
+pub mod time;
+pub mod transposition;
@@ -0,0 +1,124 @@
+use super::score::MAX_PLY;
+use super::transposition::packed_move_matches;
+use crate::chess::board::Piece;
+use crate::chess::game::Game;
+use crate::chess::moves::Move;
+
+const TT_MOVE_SCORE: i32 = 1_000_000;
+const CAPTURE_SCORE: i32 = 100_000;
+const KILLER_SCORES: [i32; 2] = [90_000, 80_000];
+const HISTORY_MAX: i32 = 16_384;
+
+pub fn piece_order_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight => 3,
+        Piece::Bishop => 3,
+        Piece::Rook => 5,
+        Piece::Queen => 9,
+        Piece::King => 10,
+    }
+}
+
+/// Killer moves and butterfly history, owned by a single search thread.
+pub struct MoveOrdering {
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    history: [[[i32; 64]; 64]; 2],
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MoveOrdering {
+    pub fn new() -> Self {
+        Self {
+            killers: [[None; 2]; MAX_PLY],
+            history: [[[0; 64]; 64]; 2],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.killers = [[None; 2]; MAX_PLY];
+        self.history = [[[0; 64]; 64]; 2];
+    }
+
+    /// Most valuable victim, least valuable attacker.
+    pub fn mvv_lva(game: &Game, mv: &Move) -> i32 {
+        let board = game.board();
+        let victim = if mv.is_en_passant() {
+            Piece::Pawn
+        } else {
+            match board.piece_at(mv.to()) {
+                Some((_, piece)) => piece,
+                None => Piece::Pawn,
+            }
+        };
+        let attacker = match board.piece_at(mv.from()) {
+            Some((_, piece)) => piece,
+            None => Piece::Pawn,
+        };
+
+        piece_order_value(victim) * 16 - piece_order_value(attacker)
+    }
+
+    fn score_move(&self, game: &Game, mv: &Move, tt_move: u16, ply: usize) -> i32 {
+        if packed_move_matches(tt_move, mv) {
+            return TT_MOVE_SCORE;
+        }
+
+        let promotion_bonus = match mv.promotion() {
+            Some(Piece::Queen) => CAPTURE_SCORE / 2,
+            Some(_) => -CAPTURE_SCORE,
+            None => 0,
+        };
+        if mv.is_capture() {
+            return CAPTURE_SCORE + Self::mvv_lva(game, mv) + promotion_bonus;
+        }
+        if promotion_bonus != 0 {
+            return CAPTURE_SCORE + promotion_bonus;
+        }
+
+        for (slot, killer) in self.killers[ply].iter().enumerate() {
+            if *killer == Some(*mv) {
+                return KILLER_SCORES[slot];
+            }
+        }
+
+        self.history_score(game, mv)
+    }
+
+    pub fn history_score(&self, game: &Game, mv: &Move) -> i32 {
+        self.history[game.active() as usize][mv.from().index() as usize][mv.to().index() as usize]
+    }
+
+    /// Sorts `moves` best first: TT move, captures, killers, then history.
+    pub fn order_moves(&self, game: &Game, moves: &mut [Move], tt_move: u16, ply: usize) {
+        moves.sort_by_cached_key(|mv| -self.score_move(game, mv, tt_move, ply));
+    }
+
+    /// Records a quiet move that caused a beta cutoff, penalising the quiets tried before it.
+    pub fn update_quiet(&mut self, game: &Game, mv: Move, tried: &[Move], depth: i32, ply: usize) {
+        if self.killers[ply][0] != Some(mv) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(mv);
+        }
+
+        let bonus = (depth * depth).min(400);
+        let side = game.active() as usize;
+        self.apply_history(side, &mv, bonus);
+        for other in tried {
+            if *other != mv && !other.is_tactical() {
+                self.apply_history(side, other, -bonus);
+            }
+        }
+    }
+
+    fn apply_history(&mut self, side: usize, mv: &Move, bonus: i32) {
+        let entry = &mut self.history[side][mv.from().index() as usize][mv.to().index() as usize];
+        // gravity keeps entries bounded by HISTORY_MAX
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+}
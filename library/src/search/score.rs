@@ -0,0 +1,39 @@
+pub const INFINITY: i32 = 32001;
+pub const MATE: i32 = 32000;
+pub const MAX_PLY: usize = 128;
+
+/// Scores beyond this are mates found within `MAX_PLY`.
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+/// A tablebase win at the root, just below the mate scores. Like mates, wins further
+/// from the root score a little lower.
+pub const TB_WIN: i32 = MATE_BOUND - 1;
+/// Scores beyond this are tablebase wins or mates.
+pub const TB_WIN_BOUND: i32 = TB_WIN - MAX_PLY as i32;
+
+pub fn mated_in(ply: usize) -> i32 {
+    -MATE + ply as i32
+}
+
+pub fn mate_in(ply: usize) -> i32 {
+    MATE - ply as i32
+}
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_BOUND
+}
+
+/// Full moves until mate, negative when we're the side getting mated.
+pub fn mate_distance(score: i32) -> Option<i32> {
+    if !is_mate_score(score) {
+        None
+    } else if score > 0 {
+        Some((MATE - score + 1) / 2)
+    } else {
+        Some(-(MATE + score) / 2)
+    }
+}
+
+pub fn tb_win_in(ply: usize) -> i32 {
+    TB_WIN - ply as i32
+}
@@ -0,0 +1,962 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::info::{PvLine, SearchInfo, SearchResult, SearchStats};
+use super::limits::SearchLimits;
+use super::ordering::MoveOrdering;
+use super::score::{is_mate_score, mate_distance, mated_in, tb_win_in, INFINITY, MAX_PLY};
+use super::time::TimeManager;
+use super::transposition::{
+    packed_move_matches, score_from_tt, score_to_tt, Bound, TranspositionTable, TtEntry,
+};
+use crate::chess::board::Piece;
+use crate::chess::game::{Game, Side};
+use crate::chess::moves::{generate_legal_moves, Move};
+use crate::eval::Evaluator;
+use crate::syzygy::table::Kind;
+use crate::syzygy::{self, ProbeSettings, Tablebases};
+
+const ASPIRATION_MIN_DEPTH: u8 = 4;
+const ASPIRATION_WINDOW: i32 = 25;
+const SINGULAR_MIN_DEPTH: i32 = 8;
+const SINGULAR_MARGIN: i32 = 2;
+
+// Helper threads skip some iterations so they spread over different depths.
+const SKIP_SIZE: [u8; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u8; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+pub struct Searcher {
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    thread_index: usize,
+    helper_nodes: Arc<AtomicU64>,
+    published_nodes: u64,
+    ordering: MoveOrdering,
+    evaluator: Evaluator,
+    start: Instant,
+    deadline: Option<Instant>,
+    movetime: Option<Duration>,
+    time_manager: Option<TimeManager>,
+    node_limit: Option<u64>,
+    ponder: Option<Arc<AtomicBool>>,
+    pondering: bool,
+    nodes: u64,
+    seldepth: usize,
+    stopped: bool,
+    stats: SearchStats,
+    root_depth: i32,
+    pv: Vec<Vec<Move>>,
+    excluded: [Option<Move>; MAX_PLY],
+    root_moves: Vec<Move>,
+    excluded_root: Vec<Move>,
+    pv_index: usize,
+    tablebases: Option<Arc<Tablebases>>,
+    tb_settings: ProbeSettings,
+    /// Off once DTZ tables have ranked the root moves, they already keep the win.
+    tb_in_search: bool,
+    /// Score of each root move according to the tablebases, when they ranked them.
+    tb_root_scores: Vec<(Move, i32)>,
+    tb_hits: u64,
+}
+
+impl Searcher {
+    pub fn new(tt: Arc<TranspositionTable>, stop: Arc<AtomicBool>) -> Self {
+        Self::new_thread(tt, stop, 0, Arc::new(AtomicU64::new(0)))
+    }
+
+    /// One thread of a Lazy SMP search. Thread 0 is the main thread; helpers add the
+    /// nodes they search to `helper_nodes` so the main thread can report the total.
+    pub fn new_thread(
+        tt: Arc<TranspositionTable>,
+        stop: Arc<AtomicBool>,
+        thread_index: usize,
+        helper_nodes: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            tt,
+            stop,
+            thread_index,
+            helper_nodes,
+            published_nodes: 0,
+            ordering: MoveOrdering::new(),
+            evaluator: Evaluator::new(),
+            start: Instant::now(),
+            deadline: None,
+            movetime: None,
+            time_manager: None,
+            node_limit: None,
+            ponder: None,
+            pondering: false,
+            nodes: 0,
+            seldepth: 0,
+            stopped: false,
+            stats: SearchStats::default(),
+            root_depth: 0,
+            pv: vec![Vec::new(); MAX_PLY + 1],
+            excluded: [None; MAX_PLY],
+            root_moves: Vec::new(),
+            excluded_root: Vec::new(),
+            pv_index: 0,
+            tablebases: None,
+            tb_settings: ProbeSettings::default(),
+            tb_in_search: false,
+            tb_root_scores: Vec::new(),
+            tb_hits: 0,
+        }
+    }
+
+    /// Forget everything learned from previous games.
+    pub fn clear(&mut self) {
+        self.ordering.clear();
+        self.evaluator.clear();
+        if self.is_main_thread() {
+            self.tt.clear();
+        }
+    }
+
+    pub fn is_main_thread(&self) -> bool {
+        self.thread_index == 0
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    /// Iterative deepening driver. Calls `report` after every completed or failed iteration.
+    pub fn search(
+        &mut self,
+        game: &mut Game,
+        limits: &SearchLimits,
+        report: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchResult {
+        self.start = Instant::now();
+        self.time_manager = limits.time_manager.clone();
+        self.movetime = limits.movetime;
+        self.ponder = limits.ponder.clone();
+        self.pondering = self.ponder.is_some();
+        self.node_limit = limits.nodes;
+        self.update_deadline();
+        self.nodes = 0;
+        self.published_nodes = 0;
+        self.seldepth = 0;
+        self.stopped = false;
+        self.stats = SearchStats::default();
+        if self.is_main_thread() {
+            self.tt.new_search();
+        }
+        self.tablebases = syzygy::current_tablebases().filter(|_| game.kind().has_standard_rules());
+        self.tb_settings = limits.syzygy;
+        self.tb_in_search = self.tablebases.is_some();
+        self.tb_root_scores.clear();
+        self.tb_hits = 0;
+
+        self.root_moves = generate_legal_moves(game);
+        if !limits.searchmoves.is_empty() {
+            self.root_moves.retain(|mv| limits.searchmoves.contains(mv));
+        }
+        self.filter_root_moves(game);
+        let mut result = SearchResult {
+            best_move: self.root_moves.first().copied(),
+            score: 0,
+            depth: 0,
+            pv: Vec::new(),
+            lines: Vec::new(),
+            ponder_move: None,
+            stats: SearchStats::default(),
+        };
+        if self.root_moves.is_empty() {
+            result.score = if game.is_in_check() || game.is_variant_loss() {
+                mated_in(0)
+            } else if game.kind().stalemate_wins() {
+                -mated_in(0)
+            } else {
+                0
+            };
+            return result;
+        }
+        let tt_move = self
+            .tt
+            .probe(game.hash())
+            .map_or(0, |entry| entry.packed_move);
+        self.ordering
+            .order_moves(game, &mut self.root_moves, tt_move, 0);
+
+        let line_count = limits.multipv.clamp(1, self.root_moves.len());
+        let mut lines: Vec<PvLine> = Vec::new();
+        let max_depth = limits
+            .depth
+            .unwrap_or(MAX_PLY as u8 - 1)
+            .min(MAX_PLY as u8 - 1);
+        for depth in 1..=max_depth {
+            if self.skips_depth(depth) {
+                continue;
+            }
+
+            // Each further line is searched with the best moves of the earlier ones excluded.
+            let mut iteration_lines: Vec<PvLine> = Vec::new();
+            self.excluded_root.clear();
+            for pv_index in 0..line_count {
+                self.pv_index = pv_index;
+                let previous_score = lines.get(pv_index).map_or(0, |line| line.score);
+                let score = self.aspiration_search(game, depth, previous_score, report);
+                if self.stopped {
+                    break;
+                }
+                let Some(best_move) = self.pv[0].first().copied() else {
+                    break;
+                };
+
+                report(&self.info(depth, score, Bound::Exact));
+                iteration_lines.push(PvLine {
+                    score,
+                    depth,
+                    pv: self.pv[0].clone(),
+                });
+                self.excluded_root.push(best_move);
+            }
+            if self.stopped {
+                break;
+            }
+
+            iteration_lines.sort_by_key(|line| -line.score);
+            lines = iteration_lines;
+            result.best_move = lines[0].pv.first().copied();
+            result.score = lines[0].score;
+            result.depth = depth;
+            result.pv = lines[0].pv.clone();
+
+            // next iteration starts with the moves we currently think are best
+            for line in lines.iter().rev() {
+                let index = self
+                    .root_moves
+                    .iter()
+                    .position(|mv| Some(mv) == line.pv.first());
+                if let Some(index) = index {
+                    self.root_moves[..=index].rotate_right(1);
+                }
+            }
+            if let (Some(mate), Some(distance)) = (limits.mate, mate_distance(result.score)) {
+                if distance > 0 && distance <= mate as i32 {
+                    break;
+                }
+            }
+            self.check_ponderhit();
+            if self.pondering {
+                continue;
+            }
+            if self.deadline_passed() {
+                break;
+            }
+            let root_move_count = self.root_moves.len();
+            let score = result.score;
+            if let Some(time_manager) = self.time_manager.as_mut() {
+                if time_manager.iteration_complete(result.best_move, score, root_move_count) {
+                    break;
+                }
+            }
+        }
+
+        result.score = self.tb_root_score(result.best_move, result.score);
+        result.lines = lines;
+        result.ponder_move = self.ponder_move(game, &result.pv);
+        self.publish_nodes();
+        result.stats = self.stats;
+        result
+    }
+
+    /// The best `lines` root moves, each with its own score and principal variation.
+    pub fn search_multipv(
+        &mut self,
+        game: &mut Game,
+        limits: &SearchLimits,
+        lines: usize,
+    ) -> Vec<PvLine> {
+        let limits = SearchLimits {
+            multipv: lines,
+            ..limits.clone()
+        };
+
+        self.search(game, &limits, &mut |_| {}).lines
+    }
+
+    fn skips_depth(&self, depth: u8) -> bool {
+        if self.is_main_thread() {
+            return false;
+        }
+
+        let index = (self.thread_index - 1) % SKIP_SIZE.len();
+        ((depth + SKIP_PHASE[index]) / SKIP_SIZE[index]) & 1 == 1
+    }
+
+    fn publish_nodes(&mut self) {
+        if !self.is_main_thread() {
+            self.helper_nodes
+                .fetch_add(self.nodes - self.published_nodes, Ordering::Relaxed);
+            self.published_nodes = self.nodes;
+        }
+    }
+
+    /// Searches the root with a window around the previous iteration's score, widening
+    /// it on whichever side fails until the score lands inside.
+    fn aspiration_search(
+        &mut self,
+        game: &mut Game,
+        depth: u8,
+        previous_score: i32,
+        report: &mut dyn FnMut(&SearchInfo),
+    ) -> i32 {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) =
+            if depth >= ASPIRATION_MIN_DEPTH && !is_mate_score(previous_score) {
+                (
+                    (previous_score - delta).max(-INFINITY),
+                    (previous_score + delta).min(INFINITY),
+                )
+            } else {
+                (-INFINITY, INFINITY)
+            };
+
+        loop {
+            let score = self.search_root(game, depth, alpha, beta);
+            if self.stopped {
+                return score;
+            }
+
+            if score <= alpha {
+                report(&self.info(depth, score, Bound::Upper));
+                beta = (alpha + beta) / 2;
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta {
+                report(&self.info(depth, score, Bound::Lower));
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+
+            delta += delta / 2;
+        }
+    }
+
+    fn search_root(&mut self, game: &mut Game, depth: u8, mut alpha: i32, beta: i32) -> i32 {
+        let depth = depth as i32;
+        self.root_depth = depth;
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        self.pv[0].clear();
+
+        let root_moves: Vec<Move> = self
+            .root_moves
+            .iter()
+            .filter(|mv| !self.excluded_root.contains(mv))
+            .copied()
+            .collect();
+        for (index, mv) in root_moves.into_iter().enumerate() {
+            game.make_move(mv);
+            let score = if index == 0 {
+                -self.negamax(game, depth - 1, 1, -beta, -alpha, 0)
+            } else {
+                let score = -self.negamax(game, depth - 1, 1, -alpha - 1, -alpha, 0);
+                if score > alpha && score < beta {
+                    -self.negamax(game, depth - 1, 1, -beta, -alpha, 0)
+                } else {
+                    score
+                }
+            };
+            game.unmake_move();
+
+            if self.stopped {
+                return best_score;
+            }
+
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(mv);
+                    self.update_pv(0, mv);
+                }
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        // with root moves excluded the score isn't the position's score
+        if !self.excluded_root.is_empty() {
+            return best_score;
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(
+            game.hash(),
+            best_move,
+            score_to_tt(best_score, 0),
+            depth as u8,
+            bound,
+        );
+
+        best_score
+    }
+
+    fn negamax(
+        &mut self,
+        game: &mut Game,
+        mut depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        mut extensions: i32,
+    ) -> i32 {
+        self.pv[ply].clear();
+        if self.should_stop() {
+            return 0;
+        }
+
+        let in_check = game.is_in_check();
+        // the singular verification search revisits a node that was already extended
+        if in_check && self.excluded[ply].is_none() && self.can_extend(extensions, ply) {
+            depth += 1;
+            extensions += 1;
+            self.stats.check_extensions += 1;
+        }
+        if depth <= 0 {
+            return self.quiescence(game, ply, alpha, beta);
+        }
+
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        if game.is_variant_loss() {
+            return mated_in(ply);
+        }
+        if game.is_draw() {
+            return 0;
+        }
+        if ply >= MAX_PLY - 1 {
+            return self.evaluator.evaluate(game);
+        }
+
+        let is_pv = beta - alpha > 1;
+        let excluded = self.excluded[ply];
+        let tt_entry = self.tt.probe(game.hash());
+        let mut tt_move = 0;
+        if let Some(entry) = tt_entry {
+            tt_move = entry.packed_move;
+            let score = score_from_tt(entry.score, ply);
+            if !is_pv && excluded.is_none() && entry.depth as i32 >= depth {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        if excluded.is_none() {
+            if let Some(score) = self.probe_tablebases(game, depth, ply, alpha, beta) {
+                return score;
+            }
+        }
+
+        let mut moves = generate_legal_moves(game);
+        if let Some(excluded) = excluded {
+            moves.retain(|mv| *mv != excluded);
+            if moves.is_empty() {
+                // the excluded move was the only one, which makes it singular
+                return alpha;
+            }
+        } else if moves.is_empty() {
+            return if in_check {
+                mated_in(ply)
+            } else if game.kind().stalemate_wins() {
+                -mated_in(ply)
+            } else {
+                0
+            };
+        }
+        self.ordering.order_moves(game, &mut moves, tt_move, ply);
+
+        let singular_move = match tt_entry {
+            Some(entry) if excluded.is_none() && self.can_extend(extensions, ply) => {
+                self.singular_move(game, &moves, entry, depth, ply, extensions)
+            }
+            _ => None,
+        };
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        for (index, mv) in moves.iter().enumerate() {
+            let mv = *mv;
+            let extension = if self.can_extend(extensions, ply) {
+                self.move_extension(game, mv, singular_move)
+            } else {
+                0
+            };
+            let new_depth = depth - 1 + extension;
+            let child_extensions = extensions + extension;
+
+            game.make_move(mv);
+            let score = if index == 0 {
+                -self.negamax(game, new_depth, ply + 1, -beta, -alpha, child_extensions)
+            } else {
+                // null window first, full window only if it might raise alpha
+                let score = -self.negamax(
+                    game,
+                    new_depth,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    child_extensions,
+                );
+                if score > alpha && score < beta {
+                    -self.negamax(game, new_depth, ply + 1, -beta, -alpha, child_extensions)
+                } else {
+                    score
+                }
+            };
+            game.unmake_move();
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(mv);
+                    self.update_pv(ply, mv);
+                }
+            }
+            if alpha >= beta {
+                if !mv.is_tactical() {
+                    self.ordering
+                        .update_quiet(game, mv, &moves[..index], depth, ply);
+                }
+                break;
+            }
+        }
+
+        // A search with a move excluded says nothing about the position itself.
+        if excluded.is_some() {
+            return best_score;
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(
+            game.hash(),
+            best_move,
+            score_to_tt(best_score, ply),
+            depth as u8,
+            bound,
+        );
+
+        best_score
+    }
+
+    fn quiescence(&mut self, game: &mut Game, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv[ply].clear();
+        if self.should_stop() {
+            return 0;
+        }
+
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        if game.is_variant_loss() {
+            return mated_in(ply);
+        }
+        if game.is_draw() {
+            return 0;
+        }
+
+        let in_check = game.is_in_check();
+        if ply >= MAX_PLY - 1 {
+            return self.evaluator.evaluate(game);
+        }
+
+        // Where captures are compulsory the moves are needed up front: a side that has
+        // to capture can't stand pat, and one that can't move at all has won.
+        let forced_moves = game
+            .kind()
+            .captures_are_forced()
+            .then(|| generate_legal_moves(game));
+        if game.kind().stalemate_wins() && forced_moves.as_ref().is_some_and(Vec::is_empty) {
+            return -mated_in(ply);
+        }
+        let forced = in_check
+            || forced_moves
+                .as_ref()
+                .is_some_and(|moves| moves.iter().any(|mv| mv.is_capture()));
+
+        // When in check every evasion is searched and standing pat isn't allowed.
+        let mut best_score = -INFINITY;
+        if !forced {
+            best_score = self.evaluator.evaluate(game);
+            if best_score >= beta {
+                return best_score;
+            }
+            alpha = alpha.max(best_score);
+        }
+
+        let mut moves = forced_moves.unwrap_or_else(|| generate_legal_moves(game));
+        if moves.is_empty() && in_check {
+            return mated_in(ply);
+        }
+        if !forced {
+            moves.retain(|mv| mv.is_tactical());
+        }
+        self.ordering.order_moves(game, &mut moves, 0, ply);
+
+        for mv in moves {
+            game.make_move(mv);
+            let score = -self.quiescence(game, ply + 1, -beta, -alpha);
+            game.unmake_move();
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mv);
+                }
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        best_score
+    }
+
+    /// Extensions along the current line are capped by the root depth, so a long
+    /// forcing sequence can at most double the nominal depth.
+    fn can_extend(&self, extensions: i32, ply: usize) -> bool {
+        extensions < self.root_depth && ply < MAX_PLY / 2
+    }
+
+    /// At most one ply per move; check extensions are applied at the child instead.
+    fn move_extension(&mut self, game: &Game, mv: Move, singular_move: Option<Move>) -> i32 {
+        if singular_move == Some(mv) {
+            self.stats.singular_extensions += 1;
+            return 1;
+        }
+
+        // where captures are compulsory recaptures are the norm, not a sign of tactics
+        if let Some(last_move) = game
+            .last_move()
+            .filter(|_| !game.kind().captures_are_forced())
+        {
+            if last_move.is_capture() && mv.is_capture() && last_move.to() == mv.to() {
+                self.stats.recapture_extensions += 1;
+                return 1;
+            }
+        }
+
+        let seventh_rank = if game.active() == Side::White { 6 } else { 1 };
+        let moved = game.board().side_pieces(game.active()).piece_at(mv.from());
+        if moved == Some(Piece::Pawn) && mv.to().rank() == seventh_rank {
+            self.stats.pawn_push_extensions += 1;
+            return 1;
+        }
+
+        0
+    }
+
+    /// The TT move is singular if every other move fails low against a margin below
+    /// its stored score, checked with a reduced-depth search that excludes it.
+    fn singular_move(
+        &mut self,
+        game: &mut Game,
+        moves: &[Move],
+        entry: TtEntry,
+        depth: i32,
+        ply: usize,
+        extensions: i32,
+    ) -> Option<Move> {
+        let tt_score = score_from_tt(entry.score, ply);
+        if depth < SINGULAR_MIN_DEPTH
+            || entry.bound == Bound::Upper
+            || (entry.depth as i32) < depth - 3
+            || is_mate_score(tt_score)
+        {
+            return None;
+        }
+        let tt_move = *moves
+            .iter()
+            .find(|mv| packed_move_matches(entry.packed_move, mv))?;
+
+        let singular_beta = tt_score - SINGULAR_MARGIN * depth;
+        self.excluded[ply] = Some(tt_move);
+        let score = self.negamax(
+            game,
+            (depth - 1) / 2,
+            ply,
+            singular_beta - 1,
+            singular_beta,
+            extensions,
+        );
+        self.excluded[ply] = None;
+        self.pv[ply].clear();
+
+        if !self.stopped && score < singular_beta {
+            Some(tt_move)
+        } else {
+            None
+        }
+    }
+
+    fn update_pv(&mut self, ply: usize, mv: Move) {
+        let (head, tail) = self.pv.split_at_mut(ply + 1);
+        let line = &mut head[ply];
+        line.clear();
+        line.push(mv);
+        line.extend_from_slice(&tail[0]);
+    }
+
+    /// Second move of the PV, or the TT move after our best move when the PV is too short.
+    fn ponder_move(&self, game: &mut Game, pv: &[Move]) -> Option<Move> {
+        if pv.len() >= 2 {
+            return Some(pv[1]);
+        }
+
+        let best_move = *pv.first()?;
+        game.make_move(best_move);
+        let packed_move = self
+            .tt
+            .probe(game.hash())
+            .map_or(0, |entry| entry.packed_move);
+        let ponder_move = generate_legal_moves(game)
+            .into_iter()
+            .find(|mv| packed_move_matches(packed_move, mv));
+        game.unmake_move();
+
+        ponder_move
+    }
+
+    fn update_deadline(&mut self) {
+        let movetime_deadline = self.movetime.map(|movetime| self.start + movetime);
+        let clock_deadline = self
+            .time_manager
+            .as_ref()
+            .map(|time_manager| time_manager.hard_deadline());
+        self.deadline = match (movetime_deadline, clock_deadline) {
+            (Some(movetime), Some(clock)) => Some(movetime.min(clock)),
+            (movetime, clock) => movetime.or(clock),
+        };
+    }
+
+    /// The clock only starts running once the opponent plays the move we pondered on.
+    fn check_ponderhit(&mut self) {
+        let still_pondering = match self.ponder.as_ref() {
+            Some(ponder) => ponder.load(Ordering::Relaxed),
+            None => false,
+        };
+        if self.pondering && !still_pondering {
+            self.pondering = false;
+            self.start = Instant::now();
+            if let Some(time_manager) = self.time_manager.as_mut() {
+                time_manager.restart();
+            }
+            self.update_deadline();
+        }
+    }
+
+    fn deadline_passed(&self) -> bool {
+        if self.pondering {
+            return false;
+        }
+
+        match self.deadline {
+            Some(deadline) => Instant::now() >= deadline,
+            None => false,
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+
+        if self.nodes & 1023 == 0 {
+            self.publish_nodes();
+            self.check_ponderhit();
+        }
+        if let Some(node_limit) = self.node_limit {
+            if self.nodes >= node_limit {
+                self.stopped = true;
+                return true;
+            }
+        }
+        if self.stop.load(Ordering::Relaxed) || (self.nodes & 2047 == 0 && self.deadline_passed()) {
+            self.stopped = true;
+        }
+
+        self.stopped
+    }
+
+    fn info(&self, depth: u8, score: i32, bound: Bound) -> SearchInfo {
+        SearchInfo {
+            depth,
+            seldepth: self.seldepth as u8,
+            multipv: self.pv_index + 1,
+            score: self.tb_root_score(self.pv[0].first().copied(), score),
+            bound,
+            nodes: self.nodes + self.helper_nodes.load(Ordering::Relaxed),
+            time: self.start.elapsed(),
+            hashfull: self.tt.hashfull(),
+            tb_hits: self.tb_hits,
+            pv: self.pv[0].clone(),
+        }
+    }
+
+    /// Keeps only the root moves the tablebases rank best, e.g. the ones that keep a
+    /// win in time for the fifty move rule.
+    fn filter_root_moves(&mut self, game: &mut Game) {
+        let Some(tablebases) = self.tablebases.clone() else {
+            return;
+        };
+        let Some((ranked, kind)) =
+            tablebases.rank_root_moves(game, &self.root_moves, self.tb_settings.rule50)
+        else {
+            return;
+        };
+        let Some(best_rank) = ranked.iter().map(|root_move| root_move.rank).max() else {
+            return;
+        };
+
+        self.tb_hits += ranked.len() as u64;
+        self.root_moves.retain(|mv| {
+            ranked
+                .iter()
+                .any(|root_move| root_move.mv == *mv && root_move.rank == best_rank)
+        });
+        self.tb_root_scores = ranked
+            .iter()
+            .map(|root_move| (root_move.mv, root_move.score))
+            .collect();
+        if kind == Kind::Dtz {
+            self.tb_in_search = false;
+        }
+    }
+
+    /// The tablebase score of a root move, unless the search found a mate.
+    fn tb_root_score(&self, mv: Option<Move>, score: i32) -> i32 {
+        if is_mate_score(score) {
+            return score;
+        }
+        self.tb_root_scores
+            .iter()
+            .find(|(root_move, _)| Some(*root_move) == mv)
+            .map_or(score, |(_, tb_score)| *tb_score)
+    }
+
+    /// WDL probe right after a capture or pawn move, when the fifty move counter can't
+    /// spoil the result yet. Returns the score if it settles the node.
+    fn probe_tablebases(
+        &mut self,
+        game: &mut Game,
+        depth: i32,
+        ply: usize,
+        alpha: i32,
+        beta: i32,
+    ) -> Option<i32> {
+        if !self.tb_in_search || game.half_move() != 0 {
+            return None;
+        }
+        let tablebases = self.tablebases.clone()?;
+        let pieces = game.board().occupied().count();
+        if pieces > tablebases.max_pieces()
+            || (pieces == tablebases.max_pieces() && depth < self.tb_settings.probe_depth)
+        {
+            return None;
+        }
+
+        let wdl = tablebases.probe_wdl(game)? as i32;
+        self.tb_hits += 1;
+
+        // with the fifty move rule cursed wins and blessed losses are nearly draws
+        let draw = self.tb_settings.rule50 as i32;
+        let (score, bound) = if wdl < -draw {
+            (-tb_win_in(ply), Bound::Upper)
+        } else if wdl > draw {
+            (tb_win_in(ply), Bound::Lower)
+        } else {
+            (2 * wdl * draw, Bound::Exact)
+        };
+        let settled = match bound {
+            Bound::Exact => true,
+            Bound::Lower => score >= beta,
+            Bound::Upper => score <= alpha,
+        };
+        if !settled {
+            return None;
+        }
+
+        self.tt.store(
+            game.hash(),
+            None,
+            score_to_tt(score, ply),
+            (depth + 6).min(MAX_PLY as i32 - 1) as u8,
+            bound,
+        );
+        Some(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use super::Searcher;
+    use crate::chess::game::Game;
+    use crate::search::limits::SearchLimits;
+    use crate::search::transposition::TranspositionTable;
+
+    #[test]
+    fn multipv_lines() {
+        let tt = Arc::new(TranspositionTable::new(1));
+        let mut searcher = Searcher::new(tt, Arc::new(AtomicBool::new(false)));
+        let mut game = Game::from_fen(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3".to_string(),
+        );
+        let lines = searcher.search_multipv(&mut game, &SearchLimits::new_depth(4), 4);
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|line| !line.pv.is_empty()));
+        // each line starts with a different root move
+        let mut first_moves: Vec<_> = lines.iter().map(|line| line.pv[0]).collect();
+        first_moves.sort_by_key(|mv| mv.to_string());
+        first_moves.dedup();
+        assert_eq!(first_moves.len(), 4);
+        assert!(lines.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert!(lines.iter().all(|line| line.depth == 4));
+
+        // a single line is just the best move
+        let lines = searcher.search_multipv(&mut game, &SearchLimits::new_depth(4), 1);
+        assert_eq!(lines.len(), 1);
+        assert!(!lines[0].pv.is_empty());
+    }
+}
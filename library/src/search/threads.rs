@@ -0,0 +1,199 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use super::info::{SearchInfo, SearchResult};
+use super::limits::SearchLimits;
+use super::searcher::Searcher;
+use super::transposition::TranspositionTable;
+use crate::chess::game::Game;
+use crate::chess::moves::Move;
+
+/// Lazy SMP: every thread searches the same root position with its own killers and
+/// history, and they share work only through the transposition table.
+pub struct SearchPool {
+    tt: Arc<TranspositionTable>,
+    helper_stop: Arc<AtomicBool>,
+    helper_nodes: Arc<AtomicU64>,
+    main: Searcher,
+    helpers: Vec<Searcher>,
+}
+
+impl SearchPool {
+    /// `stop` aborts the main thread; helpers are stopped whenever the main thread returns.
+    pub fn new(tt: Arc<TranspositionTable>, stop: Arc<AtomicBool>, threads: usize) -> Self {
+        let helper_nodes = Arc::new(AtomicU64::new(0));
+        let mut pool = Self {
+            main: Searcher::new_thread(tt.clone(), stop, 0, helper_nodes.clone()),
+            tt,
+            helper_stop: Arc::new(AtomicBool::new(false)),
+            helper_nodes,
+            helpers: Vec::new(),
+        };
+        pool.set_threads(threads);
+
+        pool
+    }
+
+    pub fn threads(&self) -> usize {
+        self.helpers.len() + 1
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        let helper_count = threads.max(1) - 1;
+        self.helpers.truncate(helper_count);
+        while self.helpers.len() < helper_count {
+            let thread_index = self.helpers.len() + 1;
+            self.helpers.push(Searcher::new_thread(
+                self.tt.clone(),
+                self.helper_stop.clone(),
+                thread_index,
+                self.helper_nodes.clone(),
+            ));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.main.clear();
+        for helper in self.helpers.iter_mut() {
+            helper.clear();
+        }
+    }
+
+    /// Runs the main thread on the calling thread and the helpers on scoped threads.
+    /// Only the main thread reports progress and only it obeys the time limits.
+    /// Node-limited searches run on the main thread alone, so the limit is exact.
+    pub fn search(
+        &mut self,
+        game: &mut Game,
+        limits: &SearchLimits,
+        report: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchResult {
+        self.helper_stop.store(false, Ordering::Relaxed);
+        self.helper_nodes.store(0, Ordering::Relaxed);
+        if limits.nodes.is_some() {
+            return self.main.search(game, limits, report);
+        }
+
+        let helper_limits = SearchLimits {
+            mate: None,
+            movetime: None,
+            time_manager: None,
+            ponder: None,
+            multipv: 1,
+            ..limits.clone()
+        };
+        let helper_limits = &helper_limits;
+        let helper_stop = &self.helper_stop;
+        let main = &mut self.main;
+        let helpers = &mut self.helpers;
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = helpers
+                .iter_mut()
+                .map(|helper| {
+                    let mut helper_game = game.clone();
+                    scope.spawn(move || helper.search(&mut helper_game, helper_limits, &mut |_| {}))
+                })
+                .collect();
+
+            let main_result = main.search(game, limits, report);
+            helper_stop.store(true, Ordering::Relaxed);
+
+            let helper_results: Vec<SearchResult> = handles
+                .into_iter()
+                .map(|handle| handle.join().expect("helper search thread panicked"))
+                .collect();
+
+            select_result(main_result, helper_results)
+        })
+    }
+}
+
+/// Every thread votes for its best move, weighted by depth and by how much better its
+/// score is than the worst one. Ties go to the deeper search, then to the main thread.
+fn select_result(main_result: SearchResult, helper_results: Vec<SearchResult>) -> SearchResult {
+    // helpers only search one line, so they can't vote on a MultiPV ordering
+    if main_result.lines.len() > 1 {
+        return main_result;
+    }
+
+    let mut results = vec![main_result];
+    results.extend(
+        helper_results
+            .into_iter()
+            .filter(|result| result.best_move.is_some() && result.depth > 0),
+    );
+
+    let min_score = results.iter().map(|result| result.score).min().unwrap_or(0);
+    let mut votes: Vec<(Move, i64)> = Vec::new();
+    for result in results.iter() {
+        let Some(best_move) = result.best_move else {
+            continue;
+        };
+        let weight = (result.score - min_score + 14) as i64 * result.depth as i64;
+        match votes.iter_mut().find(|(mv, _)| *mv == best_move) {
+            Some((_, total)) => *total += weight,
+            None => votes.push((best_move, weight)),
+        }
+    }
+    let votes_for = |result: &SearchResult| {
+        votes
+            .iter()
+            .find(|(mv, _)| Some(*mv) == result.best_move)
+            .map_or(0, |(_, total)| *total)
+    };
+
+    let mut best_index = 0;
+    for index in 1..results.len() {
+        let candidate = &results[index];
+        let best = &results[best_index];
+        let (candidate_votes, best_votes) = (votes_for(candidate), votes_for(best));
+        if candidate_votes > best_votes
+            || (candidate_votes == best_votes && candidate.depth > best.depth)
+        {
+            best_index = index;
+        }
+    }
+
+    let mut best = results.swap_remove(best_index);
+    // extension counters are only meaningful for the main thread's tree
+    if best_index != 0 {
+        best.stats = results[0].stats;
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use super::SearchPool;
+    use crate::chess::game::Game;
+    use crate::search::limits::SearchLimits;
+    use crate::search::transposition::TranspositionTable;
+
+    #[test]
+    fn node_limited_searches_repeat() {
+        let search = || {
+            let tt = Arc::new(TranspositionTable::new(1));
+            let mut pool = SearchPool::new(tt, Arc::new(AtomicBool::new(false)), 4);
+            let mut game = Game::from_fen(
+                "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 1 5".to_string(),
+            );
+            let mut nodes = 0;
+            let result = pool.search(&mut game, &SearchLimits::new_nodes(20_000), &mut |info| {
+                nodes = info.nodes
+            });
+            (result.best_move, result.score, nodes)
+        };
+
+        // the helpers sit out, so more threads don't change the outcome
+        let first = search();
+        assert!(first.0.is_some());
+        assert!(first.2 > 0 && first.2 <= 20_000);
+        assert_eq!(search(), first);
+    }
+}
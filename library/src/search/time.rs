@@ -0,0 +1,283 @@
+use std::time::{Duration, Instant};
+
+use crate::chess::game::Side;
+use crate::chess::moves::Move;
+
+/// Moves we expect to still have to play when the GUI doesn't send `movestogo`.
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+const MAX_MOVES_TO_GO: u64 = 50;
+
+/// Iterations with an unchanged best move after which we stop using the full budget.
+const STABLE_ITERATIONS: u32 = 6;
+/// Score drop (in centipawns) between iterations that buys extra time.
+const SCORE_DROP_MARGIN: i32 = 30;
+
+/// The clock part of a UCI `go` command, in milliseconds.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TimeControl {
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: u64,
+    pub binc: u64,
+    pub movestogo: Option<u64>,
+    pub movetime: Option<u64>,
+}
+
+/// Turns a time control into a soft limit (don't start another iteration) and a hard
+/// limit (abort the search), and adjusts the soft limit as iterations complete.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Duration,
+    hard_limit: Duration,
+    fixed: bool,
+    best_move: Option<Move>,
+    stable_iterations: u32,
+    best_move_changes: u32,
+    previous_score: Option<i32>,
+    score_drop: i32,
+}
+
+impl TimeManager {
+    fn with_limits(
+        start: Instant,
+        soft_limit: Duration,
+        hard_limit: Duration,
+        fixed: bool,
+    ) -> Self {
+        Self {
+            start,
+            soft_limit,
+            hard_limit,
+            fixed,
+            best_move: None,
+            stable_iterations: 0,
+            best_move_changes: 0,
+            previous_score: None,
+            score_drop: 0,
+        }
+    }
+
+    /// Spend exactly `movetime`, less the overhead, on this move.
+    pub fn new_fixed(movetime: Duration, move_overhead: Duration) -> Self {
+        let limit = movetime
+            .saturating_sub(move_overhead)
+            .max(Duration::from_millis(1));
+        Self::with_limits(Instant::now(), limit, limit, true)
+    }
+
+    /// Covers sudden death (no increment, no movestogo), increment, repeating controls
+    /// (movestogo) and fixed movetime. Returns `None` when no clock was given.
+    pub fn new(side: Side, control: &TimeControl, move_overhead: Duration) -> Option<Self> {
+        if let Some(movetime) = control.movetime {
+            return Some(Self::new_fixed(
+                Duration::from_millis(movetime),
+                move_overhead,
+            ));
+        }
+
+        let (time, increment) = match side {
+            Side::White => (control.wtime?, control.winc),
+            Side::Black => (control.btime?, control.binc),
+        };
+        let overhead = move_overhead.as_millis() as u64;
+        let remaining = time.saturating_sub(overhead).max(1);
+        let moves_to_go = control
+            .movestogo
+            .unwrap_or(DEFAULT_MOVES_TO_GO)
+            .clamp(1, MAX_MOVES_TO_GO);
+
+        // The increment comes back after the move, so most of it can be spent now.
+        let ideal = remaining / moves_to_go + increment * 3 / 4;
+        let (soft_fraction, hard_fraction) = if moves_to_go == 1 {
+            // last move before the control: use most of what's left
+            (0.7, 0.9)
+        } else {
+            (0.4, 0.75)
+        };
+        let soft = ideal.min((remaining as f64 * soft_fraction) as u64).max(1);
+        let hard = (ideal * 4)
+            .min((remaining as f64 * hard_fraction) as u64)
+            .max(soft);
+
+        Some(Self::with_limits(
+            Instant::now(),
+            Duration::from_millis(soft),
+            Duration::from_millis(hard),
+            false,
+        ))
+    }
+
+    /// Starts the clock again from now, e.g. on a ponderhit.
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn soft_limit(&self) -> Duration {
+        self.soft_limit
+    }
+
+    pub fn hard_limit(&self) -> Duration {
+        self.hard_limit
+    }
+
+    pub fn hard_deadline(&self) -> Instant {
+        self.start + self.hard_limit
+    }
+
+    /// Soft limit scaled by how settled the search looks.
+    fn adjusted_soft_limit(&self) -> Duration {
+        let mut scale = 1.0;
+        if self.stable_iterations >= STABLE_ITERATIONS {
+            scale *= 0.5;
+        }
+        // the best move changed recently, keep looking
+        scale *= 1.0 + 0.3 * self.best_move_changes.min(3) as f64;
+        if self.score_drop > SCORE_DROP_MARGIN {
+            scale *= if self.score_drop > 3 * SCORE_DROP_MARGIN {
+                2.0
+            } else {
+                1.5
+            };
+        }
+
+        self.soft_limit.mul_f64(scale).min(self.hard_limit)
+    }
+
+    /// Called after each completed iteration; returns true when the search should stop.
+    pub fn iteration_complete(
+        &mut self,
+        best_move: Option<Move>,
+        score: i32,
+        root_move_count: usize,
+    ) -> bool {
+        if self.fixed {
+            return self.elapsed() >= self.hard_limit;
+        }
+
+        // nothing to think about
+        if root_move_count == 1 {
+            return true;
+        }
+
+        if best_move == self.best_move {
+            self.stable_iterations += 1;
+            self.best_move_changes = self.best_move_changes.saturating_sub(1);
+        } else {
+            self.stable_iterations = 0;
+            if self.best_move.is_some() {
+                self.best_move_changes += 2;
+            }
+            self.best_move = best_move;
+        }
+
+        self.score_drop = match self.previous_score {
+            Some(previous) => previous - score,
+            None => 0,
+        };
+        self.previous_score = Some(score);
+
+        self.elapsed() >= self.adjusted_soft_limit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{TimeControl, TimeManager};
+    use crate::chess::game::Side;
+
+    fn limits(side: Side, control: TimeControl, overhead: u64) -> (u64, u64) {
+        let manager = TimeManager::new(side, &control, Duration::from_millis(overhead)).unwrap();
+        (
+            manager.soft_limit().as_millis() as u64,
+            manager.hard_limit().as_millis() as u64,
+        )
+    }
+
+    fn white(wtime: u64, winc: u64, movestogo: Option<u64>) -> TimeControl {
+        TimeControl {
+            wtime: Some(wtime),
+            winc,
+            movestogo,
+            ..TimeControl::default()
+        }
+    }
+
+    #[test]
+    fn limits_for_each_time_control() {
+        // sudden death: a 30th of the clock, four times that at most
+        assert_eq!(limits(Side::White, white(60_000, 0, None), 0), (2000, 8000));
+        // increment: three quarters of it on top
+        assert_eq!(
+            limits(Side::White, white(10_000, 1000, None), 0),
+            (1083, 4332)
+        );
+        // repeating control
+        assert_eq!(
+            limits(Side::White, white(10_000, 0, Some(10)), 0),
+            (1000, 4000)
+        );
+        // movetime is spent exactly
+        let movetime = TimeControl {
+            movetime: Some(1000),
+            ..TimeControl::default()
+        };
+        assert_eq!(limits(Side::White, movetime, 0), (1000, 1000));
+        // black reads its own clock
+        let black = TimeControl {
+            wtime: Some(1000),
+            btime: Some(60_000),
+            ..TimeControl::default()
+        };
+        assert_eq!(limits(Side::Black, black, 0), (2000, 8000));
+        // no clock for the side to move
+        assert!(TimeManager::new(Side::Black, &white(1000, 0, None), Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn move_overhead_is_subtracted() {
+        assert_eq!(
+            limits(Side::White, white(60_000, 0, None), 3000),
+            (1900, 7600)
+        );
+        let movetime = TimeControl {
+            movetime: Some(1000),
+            ..TimeControl::default()
+        };
+        assert_eq!(limits(Side::White, movetime, 50), (950, 950));
+        // never below a millisecond, even when the overhead eats the whole clock
+        assert_eq!(limits(Side::White, white(100, 0, None), 500), (1, 1));
+    }
+
+    #[test]
+    fn single_root_move_stops_at_once() {
+        let mut manager =
+            TimeManager::new(Side::White, &white(60_000, 0, None), Duration::ZERO).unwrap();
+        assert!(manager.iteration_complete(None, 0, 1));
+    }
+
+    #[test]
+    fn limits_stay_within_the_remaining_time() {
+        for time in [100, 1000, 5000, 60_000, 600_000] {
+            for increment in [0, 100, 1000, 10_000] {
+                for movestogo in [None, Some(1), Some(2), Some(10), Some(40), Some(100)] {
+                    let (soft, hard) = limits(Side::White, white(time, increment, movestogo), 0);
+                    let (soft_share, hard_share) = match movestogo {
+                        Some(1) => (0.7, 0.9),
+                        _ => (0.4, 0.75),
+                    };
+                    let control = (time, increment, movestogo);
+                    assert!(soft <= hard, "{:?}", control);
+                    assert!(soft as f64 <= time as f64 * soft_share, "{:?}", control);
+                    assert!(hard as f64 <= time as f64 * hard_share, "{:?}", control);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use super::score::TB_WIN_BOUND;
+use crate::chess::moves::Move;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound {
+    Exact = 0,
+    Lower = 1,
+    Upper = 2,
+}
+
+/// Unpacked copy of a table entry.
+#[derive(Clone, Copy)]
+pub struct TtEntry {
+    pub packed_move: u16,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+}
+
+struct Slot {
+    // key ^ data, so a torn write from another thread fails verification
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Shared, lock-free transposition table.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    generation: AtomicU8,
+}
+
+/// Origin, target and promotion piece, six, six and four bits. Drops set the top bit
+/// of the last four, with the dropped piece below it.
+pub fn pack_move(mv: &Move) -> u16 {
+    let promotion = match (mv.promotion(), mv.dropped_piece()) {
+        (Some(piece), _) => piece as u16,
+        (None, Some(piece)) => 8 | piece as u16,
+        (None, None) => 0,
+    };
+
+    mv.from().index() as u16 | (mv.to().index() as u16) << 6 | promotion << 12
+}
+
+pub fn packed_move_matches(packed_move: u16, mv: &Move) -> bool {
+    packed_move != 0 && pack_move(mv) == packed_move
+}
+
+fn pack_data(packed_move: u16, score: i16, depth: u8, bound: Bound, generation: u8) -> u64 {
+    packed_move as u64
+        | (score as u16 as u64) << 16
+        | (depth as u64) << 32
+        | (bound as u64) << 40
+        | (generation as u64) << 48
+}
+
+fn unpack_data(data: u64) -> TtEntry {
+    TtEntry {
+        packed_move: data as u16,
+        score: (data >> 16) as u16 as i16 as i32,
+        depth: (data >> 32) as u8,
+        bound: match (data >> 40) & 3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        },
+    }
+}
+
+/// Mate and tablebase win scores are stored relative to the node rather than the root.
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= TB_WIN_BOUND {
+        score + ply as i32
+    } else if score <= -TB_WIN_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= TB_WIN_BOUND {
+        score - ply as i32
+    } else if score <= -TB_WIN_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let max_slots = size_mb.max(1) * 1024 * 1024 / std::mem::size_of::<Slot>();
+        // round down to a power of two so indexing is a mask
+        let slot_count = 1 << (usize::BITS - 1 - max_slots.leading_zeros());
+        let mut slots = Vec::with_capacity(slot_count);
+        for _ in 0..slot_count {
+            slots.push(Slot {
+                key: AtomicU64::new(0),
+                data: AtomicU64::new(0),
+            });
+        }
+
+        Self {
+            slots,
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    fn slot(&self, hash: u64) -> &Slot {
+        &self.slots[(hash as usize) & (self.slots.len() - 1)]
+    }
+
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Called once per search so entries from older searches get replaced first.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TtEntry> {
+        let slot = self.slot(hash);
+        let data = slot.data.load(Ordering::Relaxed);
+        if slot.key.load(Ordering::Relaxed) ^ data != hash || data == 0 {
+            return None;
+        }
+
+        Some(unpack_data(data))
+    }
+
+    pub fn store(&self, hash: u64, mv: Option<Move>, score: i32, depth: u8, bound: Bound) {
+        let slot = self.slot(hash);
+        let generation = self.generation.load(Ordering::Relaxed) & 0x3f;
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let same_position = slot.key.load(Ordering::Relaxed) ^ old_data == hash;
+        let old_entry = unpack_data(old_data);
+        let old_generation = (old_data >> 48) as u8;
+
+        // Keep deeper entries from the current search unless this one is exact.
+        if old_data != 0
+            && old_generation == generation
+            && old_entry.depth > depth.saturating_add(2)
+            && bound != Bound::Exact
+        {
+            return;
+        }
+
+        // Don't lose the best move of a position just because this search didn't find one.
+        let packed_move = match mv {
+            Some(mv) => pack_move(&mv),
+            None if same_position => old_entry.packed_move,
+            None => 0,
+        };
+
+        let data = pack_data(packed_move, score as i16, depth, bound, generation);
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    /// Permille of sampled slots written during the current search.
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation.load(Ordering::Relaxed) & 0x3f;
+        let sample = self.slots.len().min(1000);
+        let used = self.slots[..sample]
+            .iter()
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                data != 0 && (data >> 48) as u8 == generation
+            })
+            .count();
+
+        (used * 1000 / sample) as u32
+    }
+}
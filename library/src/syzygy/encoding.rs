@@ -0,0 +1,148 @@
+use crate::chess::board::Square;
+use crate::chess::move_tables;
+use crate::utils::bitboard::Bitboard;
+
+/// Positions of the leading group of a pawnless table with at least three unique pieces
+/// (kings included), after mirroring the first one into the a1-d1-d4 triangle.
+pub const UNIQUE_PIECES_SIZE: u64 = 31332;
+/// Legal placements of the two kings with the first one in the a1-d1-d4 triangle.
+pub const KINGS_SIZE: u64 = 462;
+
+/// The index tables shared by every Syzygy table, as laid out by the generator.
+pub struct Encoding {
+    /// a2-h7 to 0..47, higher for the pawns that lead: nearer the edge, then lower.
+    pub map_pawns: [u64; 64],
+    /// Squares below the a1-h8 diagonal to 0..27.
+    pub map_b1h1h7: [u64; 64],
+    /// The a1-d1-d4 triangle to 0..9, with the diagonal squares last.
+    pub map_a1d1d4: [u64; 64],
+    /// Both kings, the first one mapped by `map_a1d1d4`, to 0..461.
+    pub map_kk: [[u64; 64]; 10],
+    /// `binomial[k][n]` ways to choose `k` squares out of `n`.
+    pub binomial: [[u64; 64]; 7],
+    /// First index of the leading pawns, by their count and the square of the first.
+    pub lead_pawn_index: [[u64; 64]; 6],
+    /// Positions of the leading pawns, by their count and the file of the first.
+    pub lead_pawns_size: [[u64; 4]; 6],
+}
+
+/// How far `square` is above the a1-h8 diagonal; negative below it.
+pub fn off_diagonal(square: u8) -> i32 {
+    (square >> 3) as i32 - (square & 7) as i32
+}
+
+fn build_encoding() -> Encoding {
+    let mut encoding = Encoding {
+        map_pawns: [0; 64],
+        map_b1h1h7: [0; 64],
+        map_a1d1d4: [0; 64],
+        map_kk: [[0; 64]; 10],
+        binomial: [[0; 64]; 7],
+        lead_pawn_index: [[0; 64]; 6],
+        lead_pawns_size: [[0; 4]; 6],
+    };
+
+    let mut code = 0;
+    for square in 0..64 {
+        if off_diagonal(square) < 0 {
+            encoding.map_b1h1h7[square as usize] = code;
+            code += 1;
+        }
+    }
+
+    let mut diagonal = Vec::new();
+    code = 0;
+    for square in 0..28 {
+        if square & 7 > 3 {
+            continue;
+        }
+        if off_diagonal(square) < 0 {
+            encoding.map_a1d1d4[square as usize] = code;
+            code += 1;
+        } else if off_diagonal(square) == 0 {
+            diagonal.push(square);
+        }
+    }
+    for square in diagonal {
+        encoding.map_a1d1d4[square as usize] = code;
+        code += 1;
+    }
+
+    // Both kings on the diagonal come last. A first king on the diagonal never has the
+    // other one above it, that's mirrored away.
+    let mut both_on_diagonal = Vec::new();
+    code = 0;
+    for index in 0..10 {
+        for first in 0..28u8 {
+            let in_triangle = first & 7 <= 3 && off_diagonal(first) <= 0;
+            // b1 is the only square that maps to 0
+            if !in_triangle
+                || encoding.map_a1d1d4[first as usize] != index
+                || (index == 0 && first != 1)
+            {
+                continue;
+            }
+            let king_area = move_tables::attack_table_king(Bitboard::new(), Square::new(first))
+                | Bitboard::from_square(Square::new(first));
+            for second in 0..64u8 {
+                if king_area.is_set(Square::new(second)) {
+                    continue;
+                }
+                if off_diagonal(first) == 0 && off_diagonal(second) > 0 {
+                    continue;
+                }
+                if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                    both_on_diagonal.push((index, second));
+                } else {
+                    encoding.map_kk[index as usize][second as usize] = code;
+                    code += 1;
+                }
+            }
+        }
+    }
+    for (index, second) in both_on_diagonal {
+        encoding.map_kk[index as usize][second as usize] = code;
+        code += 1;
+    }
+
+    encoding.binomial[0][0] = 1;
+    for n in 1..64 {
+        for k in 0..7.min(n + 1) {
+            encoding.binomial[k][n] = if k > 0 {
+                encoding.binomial[k - 1][n - 1]
+            } else {
+                0
+            } + if k < n {
+                encoding.binomial[k][n - 1]
+            } else {
+                0
+            };
+        }
+    }
+
+    // There are 47 squares left for the other pawns when the leading one is on a2, and
+    // two fewer for every rank it advances, as it's mirrored to the edge.
+    let mut available = 47_i64;
+    for lead_count in 1..6 {
+        for file in 0..4u8 {
+            let mut index = 0;
+            for rank in 1..7u8 {
+                let square = (rank * 8 + file) as usize;
+                if lead_count == 1 {
+                    encoding.map_pawns[square] = available as u64;
+                    encoding.map_pawns[square ^ 7] = (available - 1) as u64;
+                    available -= 2;
+                }
+                encoding.lead_pawn_index[lead_count][square] = index;
+                index += encoding.binomial[lead_count - 1][encoding.map_pawns[square] as usize];
+            }
+            encoding.lead_pawns_size[lead_count][file as usize] = index;
+        }
+    }
+
+    encoding
+}
+
+lazy_static! {
+    pub static ref ENCODING: Encoding = build_encoding();
+}
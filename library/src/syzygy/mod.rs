@@ -0,0 +1,624 @@
+pub mod encoding;
+pub mod table;
+pub mod write;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::chess::board::Piece;
+use crate::chess::game::{Game, Side};
+use crate::chess::moves::{generate_legal_moves, Move};
+use crate::search::score::TB_WIN;
+
+use table::{material_key, piece_counts, DtzProbe, Kind, Material, Table};
+
+/// Root moves that win are ranked this high, losing ones this low.
+const MAX_DTZ: i32 = 1 << 18;
+
+/// Game-theoretical value of a position for the side to move. Cursed wins and blessed
+/// losses are wins and losses that the fifty move rule turns into draws.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+/// How the search uses the tablebases, set from the UCI options.
+#[derive(Clone, Copy, Debug)]
+pub struct ProbeSettings {
+    /// Minimum remaining depth for probing positions with as many pieces as the largest
+    /// tables.
+    pub probe_depth: i32,
+    /// Score cursed wins and blessed losses as draws.
+    pub rule50: bool,
+}
+
+/// A root move as ranked by the tablebases.
+#[derive(Clone, Copy, Debug)]
+pub struct RootMove {
+    pub mv: Move,
+    /// Higher is better; all moves that win without running into the fifty move rule
+    /// share the highest rank.
+    pub rank: i32,
+    pub score: i32,
+}
+
+struct Entry {
+    material: Material,
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+
+/// The Syzygy tables found in a set of directories. Files are only read the first time
+/// a position needs them, and are then kept in memory.
+pub struct Tablebases {
+    entries: Vec<Entry>,
+    by_key: HashMap<u64, usize>,
+    max_pieces: usize,
+}
+
+lazy_static! {
+    static ref TABLEBASES: RwLock<Option<Arc<Tablebases>>> = RwLock::new(None);
+}
+
+/// The tablebases searches pick up when they start.
+pub fn current_tablebases() -> Option<Arc<Tablebases>> {
+    TABLEBASES.read().expect("tablebase lock poisoned").clone()
+}
+
+/// Looks for tables in `paths`, separated like the PATH variable, and makes them the
+/// current ones. An empty path or `<empty>` turns probing off.
+pub fn set_path(paths: &str) -> io::Result<Option<Arc<Tablebases>>> {
+    let tablebases = if paths.is_empty() || paths == "<empty>" {
+        None
+    } else {
+        Some(Arc::new(Tablebases::open(paths)?))
+    };
+    *TABLEBASES.write().expect("tablebase lock poisoned") = tablebases.clone();
+
+    Ok(tablebases)
+}
+
+impl Default for ProbeSettings {
+    fn default() -> Self {
+        Self {
+            probe_depth: 1,
+            rule50: true,
+        }
+    }
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Option<Self> {
+        match value {
+            -2 => Some(Wdl::Loss),
+            -1 => Some(Wdl::BlessedLoss),
+            0 => Some(Wdl::Draw),
+            1 => Some(Wdl::CursedWin),
+            2 => Some(Wdl::Win),
+            _ => None,
+        }
+    }
+
+    /// The same result seen from the other side.
+    pub fn opposite(self) -> Self {
+        Self::from_value(-(self as i32)).expect("WDL values are symmetric")
+    }
+
+    /// DTZ of a position whose best move is a capture or a pawn move.
+    fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Wdl::Win => 1,
+            Wdl::CursedWin => 101,
+            Wdl::Draw => 0,
+            Wdl::BlessedLoss => -101,
+            Wdl::Loss => -1,
+        }
+    }
+}
+
+/// How a capture search settled the position.
+#[derive(PartialEq, Eq)]
+enum Found {
+    /// The table value stands.
+    Probed,
+    /// A capture or pawn move already achieves the result, the table may not be asked.
+    ZeroingBestMove,
+}
+
+fn sign(value: i32) -> i32 {
+    value.signum()
+}
+
+fn is_zeroing(game: &Game, mv: Move) -> bool {
+    let moved = game.board().side_pieces(game.active()).piece_at(mv.from());
+    mv.is_capture() || moved == Some(Piece::Pawn)
+}
+
+fn has_castling_rights(game: &Game) -> bool {
+    let (white_short, white_long) = game.castle_rights(Side::White);
+    let (black_short, black_long) = game.castle_rights(Side::Black);
+    white_short || white_long || black_short || black_long
+}
+
+fn piece_count(game: &Game) -> usize {
+    game.board().occupied().count()
+}
+
+impl Tablebases {
+    /// Finds every `.rtbw` file in the directories and its `.rtbz` partner, if any.
+    pub fn open(paths: &str) -> io::Result<Self> {
+        let directories: Vec<PathBuf> = env::split_paths(paths).collect();
+        let mut tablebases = Self {
+            entries: Vec::new(),
+            by_key: HashMap::new(),
+            max_pieces: 0,
+        };
+
+        for directory in directories.iter() {
+            for file in fs::read_dir(directory)? {
+                let path = file?.path();
+                if path.extension().and_then(|extension| extension.to_str()) != Some("rtbw") {
+                    continue;
+                }
+                let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let Some(material) = Material::from_name(name) else {
+                    continue;
+                };
+                if tablebases.by_key.contains_key(&material.key) {
+                    continue;
+                }
+
+                let dtz_path = directories
+                    .iter()
+                    .map(|directory| directory.join(format!("{}.rtbz", name)))
+                    .find(|path| path.is_file());
+                let index = tablebases.entries.len();
+                tablebases.by_key.insert(material.key, index);
+                tablebases.by_key.insert(material.mirrored_key, index);
+                tablebases.max_pieces = tablebases.max_pieces.max(material.piece_count);
+                tablebases.entries.push(Entry {
+                    material,
+                    wdl_path: path,
+                    dtz_path,
+                    wdl: OnceLock::new(),
+                    dtz: OnceLock::new(),
+                });
+            }
+        }
+
+        Ok(tablebases)
+    }
+
+    /// Number of WDL tables found.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Pieces, kings included, in the largest table.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Whether the tables can say anything about `game`: few enough pieces and no
+    /// castling rights, which the tables don't know about.
+    pub fn covers(&self, game: &Game) -> bool {
+        piece_count(game) <= self.max_pieces && !has_castling_rights(game)
+    }
+
+    fn entry(&self, game: &Game) -> Option<&Entry> {
+        let key = material_key(&piece_counts(game.board()));
+        self.by_key.get(&key).map(|index| &self.entries[*index])
+    }
+
+    fn table(entry: &Entry, kind: Kind) -> Option<&Table> {
+        let (cell, path) = match kind {
+            Kind::Wdl => (&entry.wdl, Some(&entry.wdl_path)),
+            Kind::Dtz => (&entry.dtz, entry.dtz_path.as_ref()),
+        };
+        cell.get_or_init(|| Table::load(path?, kind, &entry.material).ok())
+            .as_ref()
+    }
+
+    /// The WDL table's value for `game`, ignoring captures and en passant.
+    fn probe_wdl_table(&self, game: &Game) -> Option<Wdl> {
+        if piece_count(game) == 2 {
+            return Some(Wdl::Draw);
+        }
+        let entry = self.entry(game)?;
+        let table = Self::table(entry, Kind::Wdl)?;
+        Wdl::from_value(table.probe_wdl(&entry.material, game.board(), game.active())?)
+    }
+
+    fn probe_dtz_table(&self, game: &Game, wdl: Wdl) -> Option<DtzProbe> {
+        let entry = self.entry(game)?;
+        let table = Self::table(entry, Kind::Dtz)?;
+        table.probe_dtz(&entry.material, game.board(), game.active(), wdl as i32)
+    }
+
+    /// The tables don't store positions where a capture (or en passant) is the best
+    /// move, so those are searched first. With `zeroing_pawn_moves` pawn moves are
+    /// searched too, which DTZ probes need.
+    fn search(&self, game: &mut Game, zeroing_pawn_moves: bool) -> Option<(Wdl, Found)> {
+        let moves = generate_legal_moves(game);
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+
+        for mv in moves.iter() {
+            let moved = game.board().side_pieces(game.active()).piece_at(mv.from());
+            if !mv.is_capture() && (!zeroing_pawn_moves || moved != Some(Piece::Pawn)) {
+                continue;
+            }
+            searched += 1;
+
+            game.make_move(*mv);
+            let value = self.search(game, false).map(|(wdl, _)| wdl.opposite());
+            game.unmake_move();
+
+            let value = value?;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, Found::ZeroingBestMove));
+                }
+            }
+        }
+
+        // With every move searched there's nothing left for the table to add, and its
+        // value could be wrong anyway (en passant, or only captures available).
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            self.probe_wdl_table(game)?
+        };
+
+        // The DTZ table stores a "don't care" value when the best move is zeroing.
+        if best >= value {
+            let found = if best > Wdl::Draw || no_more_moves {
+                Found::ZeroingBestMove
+            } else {
+                Found::Probed
+            };
+            return Some((best, found));
+        }
+
+        Some((value, Found::Probed))
+    }
+
+    /// WDL value of `game` for the side to move, or `None` when no table covers it.
+    pub fn probe_wdl(&self, game: &mut Game) -> Option<Wdl> {
+        if !self.covers(game) {
+            return None;
+        }
+
+        self.search(game, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Distance to zeroing in plies for the side to move: positive when winning,
+    /// negative when losing, 0 for draws. Cursed wins and blessed losses are counted
+    /// beyond 100 plies, where the fifty move rule has already drawn the game.
+    pub fn probe_dtz(&self, game: &mut Game) -> Option<i32> {
+        if !self.covers(game) {
+            return None;
+        }
+
+        let (wdl, found) = self.search(game, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if found == Found::ZeroingBestMove {
+            return Some(wdl.dtz_before_zeroing());
+        }
+
+        match self.probe_dtz_table(game, wdl)? {
+            DtzProbe::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::BlessedLoss | Wdl::CursedWin);
+                Some((dtz + if cursed { 100 } else { 0 }) * sign(wdl as i32))
+            }
+            // only the other side to move is stored: try every move
+            DtzProbe::OtherSide => {
+                let mut min_dtz = i32::MAX;
+                for mv in generate_legal_moves(game) {
+                    let zeroing = is_zeroing(game, mv);
+                    game.make_move(mv);
+                    // after a zeroing move we want the DTZ before it, with the sign of
+                    // the resulting position
+                    let dtz = if zeroing {
+                        self.search(game, false)
+                            .map(|(wdl, _)| -wdl.dtz_before_zeroing())
+                    } else {
+                        self.probe_dtz(game).map(|dtz| -dtz)
+                    };
+                    let mates = game.is_in_check() && generate_legal_moves(game).is_empty();
+                    game.unmake_move();
+
+                    let mut dtz = dtz?;
+                    if dtz == 1 && mates {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += sign(dtz);
+                    }
+                    if dtz < min_dtz && sign(dtz) == sign(wdl as i32) {
+                        min_dtz = dtz;
+                    }
+                }
+
+                // no legal moves means we're mated
+                Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+            }
+        }
+    }
+
+    /// Ranks the root moves by DTZ, counting from the root's fifty move counter, so that
+    /// winning moves that would still be in time come first. `None` if a table is
+    /// missing.
+    pub fn rank_root_moves_dtz(
+        &self,
+        game: &mut Game,
+        moves: &[Move],
+        rule50: bool,
+    ) -> Option<Vec<RootMove>> {
+        let half_move = game.half_move() as i32;
+        let repeated = game.has_repeated();
+        let bound = if rule50 { MAX_DTZ - 100 } else { 1 };
+        let mut ranked = Vec::with_capacity(moves.len());
+
+        for mv in moves.iter() {
+            game.make_move(*mv);
+            let dtz = if game.half_move() == 0 {
+                self.probe_wdl(game)
+                    .map(|wdl| wdl.opposite().dtz_before_zeroing())
+            } else if game.is_draw() {
+                Some(0)
+            } else {
+                self.probe_dtz(game).map(|dtz| {
+                    let dtz = -dtz;
+                    dtz + sign(dtz)
+                })
+            };
+            let mates = game.is_in_check() && generate_legal_moves(game).is_empty();
+            game.unmake_move();
+
+            let mut dtz = dtz?;
+            if mates && dtz == 2 {
+                dtz = 1;
+            }
+
+            // Wins are all ranked the same unless the fifty move rule is in sight or the
+            // position repeated, then the quickest zeroing goes first. Losses are ranked
+            // the same unless the rule could still save us.
+            let rank = if dtz > 0 {
+                if dtz + half_move <= 99 && !repeated {
+                    MAX_DTZ
+                } else {
+                    MAX_DTZ - (dtz + half_move)
+                }
+            } else if dtz < 0 {
+                if -dtz * 2 + half_move < 100 {
+                    -MAX_DTZ
+                } else {
+                    -MAX_DTZ + (-dtz + half_move)
+                }
+            } else {
+                0
+            };
+
+            // Cursed wins get a small score that grows as the win gets closer.
+            let score = if rank >= bound {
+                TB_WIN
+            } else if rank > 0 {
+                (rank - (MAX_DTZ - 200)).max(3) / 2
+            } else if rank == 0 {
+                0
+            } else if rank > -bound {
+                (rank + (MAX_DTZ - 200)).min(-3) / 2
+            } else {
+                -TB_WIN
+            };
+            ranked.push(RootMove {
+                mv: *mv,
+                rank,
+                score,
+            });
+        }
+
+        Some(ranked)
+    }
+
+    /// Ranks the root moves by WDL alone, for when DTZ tables are missing.
+    pub fn rank_root_moves_wdl(
+        &self,
+        game: &mut Game,
+        moves: &[Move],
+        rule50: bool,
+    ) -> Option<Vec<RootMove>> {
+        let mut ranked = Vec::with_capacity(moves.len());
+
+        for mv in moves.iter() {
+            game.make_move(*mv);
+            let wdl = self.probe_wdl(game).map(Wdl::opposite);
+            game.unmake_move();
+
+            let mut wdl = wdl?;
+            if !rule50 {
+                wdl = match wdl {
+                    Wdl::CursedWin => Wdl::Win,
+                    Wdl::BlessedLoss => Wdl::Loss,
+                    wdl => wdl,
+                };
+            }
+            let (rank, score) = match wdl {
+                Wdl::Loss => (-MAX_DTZ, -TB_WIN),
+                Wdl::BlessedLoss => (-MAX_DTZ + 101, -1),
+                Wdl::Draw => (0, 0),
+                Wdl::CursedWin => (MAX_DTZ - 101, 1),
+                Wdl::Win => (MAX_DTZ, TB_WIN),
+            };
+            ranked.push(RootMove {
+                mv: *mv,
+                rank,
+                score,
+            });
+        }
+
+        Some(ranked)
+    }
+
+    /// Root moves ranked by DTZ if possible, otherwise by WDL. `None` when the tables
+    /// don't cover the position.
+    pub fn rank_root_moves(
+        &self,
+        game: &mut Game,
+        moves: &[Move],
+        rule50: bool,
+    ) -> Option<(Vec<RootMove>, Kind)> {
+        if !self.covers(game) {
+            return None;
+        }
+
+        if let Some(ranked) = self.rank_root_moves_dtz(game, moves, rule50) {
+            return Some((ranked, Kind::Dtz));
+        }
+        self.rank_root_moves_wdl(game, moves, rule50)
+            .map(|ranked| (ranked, Kind::Wdl))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Tablebases, Wdl};
+    use crate::chess::game::Game;
+    use crate::chess::moves::generate_legal_moves;
+    use crate::search::score::TB_WIN;
+
+    /// KQvK, KRvK, KPvK and KRvKP, with KBvK and KNvK for underpromotions, written by
+    /// `tbgen fixtures/syzygy syzygy KQvK KRvK KBvK KNvK KPvK KRvKP`.
+    fn fixtures() -> Tablebases {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/syzygy");
+        let tablebases = Tablebases::open(path.to_str().unwrap()).expect("no Syzygy tables");
+        assert!(!tablebases.is_empty(), "no Syzygy tables in {:?}", path);
+        tablebases
+    }
+
+    fn game(fen: &str) -> Game {
+        Game::from_fen(fen.to_string())
+    }
+
+    #[test]
+    fn wdl_depends_on_side_to_move() {
+        let tablebases = fixtures();
+
+        let mut winning = game("8/8/8/4k3/8/8/8/KQ6 w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut winning), Some(Wdl::Win));
+        let mut losing = game("8/8/8/4k3/8/8/8/KQ6 b - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut losing), Some(Wdl::Loss));
+        let mut bare_kings = game("8/8/8/4k3/8/8/8/K7 w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut bare_kings), Some(Wdl::Draw));
+        // the queen hangs
+        let mut hanging = game("8/8/8/8/8/8/1k6/1Q5K b - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut hanging), Some(Wdl::Draw));
+        // the king is too far from the pawn
+        let mut pawn = game("8/8/8/4k3/8/8/4P3/K7 w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut pawn), Some(Wdl::Draw));
+        let mut rook_against_pawn = game("8/8/8/4k3/8/8/7p/K6R w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut rook_against_pawn), Some(Wdl::Win));
+    }
+
+    #[test]
+    fn dtz_of_immediate_wins() {
+        let tablebases = fixtures();
+
+        // Qh8 mates
+        let mut mate = game("k7/8/1K6/8/8/8/8/7Q w - - 0 1");
+        assert_eq!(tablebases.probe_dtz(&mut mate), Some(1));
+        // Rxh2 resets the counter
+        let mut capture = game("8/8/8/4k3/8/8/7p/K6R w - - 0 1");
+        assert_eq!(tablebases.probe_dtz(&mut capture), Some(1));
+    }
+
+    #[test]
+    fn dtz_agrees_with_wdl() {
+        let tablebases = fixtures();
+
+        for fen in [
+            "8/8/8/4k3/8/8/8/KQ6 w - - 0 1",
+            "8/8/8/4k3/8/8/8/KQ6 b - - 0 1",
+            "8/8/8/4k3/8/8/8/KR6 w - - 0 1",
+            "8/8/8/4k3/8/8/4P3/K7 w - - 0 1",
+            "8/8/8/4k3/8/8/4P3/K7 b - - 0 1",
+            "8/8/8/3k4/3p4/8/8/KR6 w - - 0 1",
+            "8/8/8/3k4/3p4/8/8/KR6 b - - 0 1",
+        ] {
+            let mut position = game(fen);
+            let wdl = tablebases.probe_wdl(&mut position).expect(fen) as i32;
+            let dtz = tablebases.probe_dtz(&mut position).expect(fen);
+            assert_eq!(wdl.signum(), dtz.signum(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn best_dtz_moves_mate() {
+        let tablebases = fixtures();
+
+        let mut position = game("8/8/8/4k3/8/8/8/KR6 w - - 0 1");
+        for _ in 0..100 {
+            let moves = generate_legal_moves(&mut position);
+            if moves.is_empty() {
+                break;
+            }
+            let (ranked, _) = tablebases
+                .rank_root_moves(&mut position, &moves, true)
+                .expect("covered");
+            let best = ranked
+                .iter()
+                .max_by_key(|root_move| root_move.rank)
+                .expect("has moves");
+            position.make_move(best.mv);
+        }
+
+        assert!(generate_legal_moves(&mut position).is_empty());
+        assert!(position.is_in_check());
+    }
+
+    #[test]
+    fn cursed_wins_with_the_fifty_move_rule() {
+        let tablebases = fixtures();
+        let best = |fen: &str, rule50: bool| {
+            let mut position = game(fen);
+            let moves = generate_legal_moves(&mut position);
+            let (ranked, _) = tablebases
+                .rank_root_moves(&mut position, &moves, rule50)
+                .expect("covered");
+            *ranked
+                .iter()
+                .max_by_key(|root_move| root_move.rank)
+                .expect("has moves")
+        };
+
+        // the queen mates, but not before the fifty move counter runs out
+        let cursed = best("8/8/8/4k3/8/8/8/KQ6 w - - 90 1", true);
+        assert!(cursed.rank > 0);
+        assert!(cursed.score > 0 && cursed.score < TB_WIN);
+        assert_eq!(best("8/8/8/4k3/8/8/8/KQ6 w - - 90 1", false).score, TB_WIN);
+        assert_eq!(best("8/8/8/4k3/8/8/8/KQ6 w - - 0 1", true).score, TB_WIN);
+    }
+}
@@ -0,0 +1,886 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::chess::board::{Board, Piece, Square};
+use crate::chess::game::Side;
+
+use super::encoding::{off_diagonal, ENCODING, KINGS_SIZE, UNIQUE_PIECES_SIZE};
+
+/// The most pieces a Syzygy table can have.
+pub const MAX_PIECES: usize = 7;
+
+pub(super) const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+pub(super) const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// Flags of each compressed sub-table.
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+pub(super) const FLAG_WIN_PLIES: u8 = 4;
+pub(super) const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+pub(super) const FLAG_SINGLE_VALUE: u8 = 128;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Wdl,
+    Dtz,
+}
+
+/// A table's material signature, e.g. KRPvKP, taken from its file name. The side
+/// written first is the stronger one and the tables are stored with it as white.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    /// Material key with the stronger side as white, and with it as black.
+    pub key: u64,
+    pub mirrored_key: u64,
+    pub piece_count: usize,
+    pub has_pawns: bool,
+    /// At least one side has a piece other than the king that it has only one of.
+    pub has_unique_pieces: bool,
+    /// Pawns of the side whose pawns lead the encoding, then of the other side.
+    pub pawn_count: [usize; 2],
+}
+
+/// Key that identifies a material balance, the same for any placement of the pieces.
+pub fn material_key(counts: &[[u8; 6]; 2]) -> u64 {
+    let mut key = 0;
+    for (side, counts) in counts.iter().enumerate() {
+        for (piece, count) in counts.iter().enumerate() {
+            key |= (*count as u64) << (4 * (side * 6 + piece));
+        }
+    }
+
+    key
+}
+
+/// Piece counts of `board`, indexed by side and piece.
+pub fn piece_counts(board: &Board) -> [[u8; 6]; 2] {
+    [Side::White, Side::Black].map(|side| {
+        let pieces = board.side_pieces(side);
+        Piece::ALL.map(|piece| pieces.pieces(piece).count() as u8)
+    })
+}
+
+impl Material {
+    /// Parses a name like `KRPvKP`; `None` if it isn't one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (strong, weak) = name.split_once('v')?;
+        let mut counts = [[0u8; 6]; 2];
+        for (side, pieces) in [strong, weak].into_iter().enumerate() {
+            if !pieces.starts_with('K') {
+                return None;
+            }
+            for piece_char in pieces.chars() {
+                if !piece_char.is_ascii_uppercase() {
+                    return None;
+                }
+                counts[side][Piece::from_char(piece_char)? as usize] += 1;
+            }
+            if counts[side][Piece::King as usize] != 1 {
+                return None;
+            }
+        }
+
+        let piece_count = strong.len() + weak.len();
+        if piece_count > MAX_PIECES {
+            return None;
+        }
+        let pawns = [
+            counts[0][Piece::Pawn as usize] as usize,
+            counts[1][Piece::Pawn as usize] as usize,
+        ];
+        let has_unique_pieces = counts
+            .iter()
+            .any(|counts| counts[..Piece::King as usize].contains(&1));
+        // When both sides have pawns the side with fewer leads, it compresses better.
+        let white_leads = pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]);
+        let pawn_count = if white_leads {
+            pawns
+        } else {
+            [pawns[1], pawns[0]]
+        };
+
+        Some(Self {
+            name: name.to_string(),
+            key: material_key(&counts),
+            mirrored_key: material_key(&[counts[1], counts[0]]),
+            piece_count,
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces,
+            pawn_count,
+        })
+    }
+
+    /// The same material with the colours swapped.
+    pub fn is_symmetric(&self) -> bool {
+        self.key == self.mirrored_key
+    }
+}
+
+/// One compressed sub-table: a table is split by side to move and, with pawns, by the
+/// file of the leading pawn.
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    span: usize,
+    block_count: usize,
+    max_symbol_length: usize,
+    min_symbol_length: usize,
+    /// Offsets into the file of the tables below.
+    lowest_symbol: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    /// `base64[l]` is the lowest symbol of length `l + min_symbol_length`, left aligned.
+    base64: Vec<u64>,
+    /// Number of values (minus one) that each symbol expands to.
+    symbol_length: Vec<u8>,
+    /// Pieces in the order they're encoded, as the generator numbers them.
+    pieces: [u8; MAX_PIECES],
+    group_index: [u64; MAX_PIECES + 1],
+    group_length: [usize; MAX_PIECES + 1],
+    /// Start of the DTZ value maps for wins, losses, cursed wins and blessed losses.
+    map_index: [usize; 4],
+}
+
+/// A loaded WDL or DTZ table.
+pub struct Table {
+    kind: Kind,
+    bytes: Vec<u8>,
+    /// By side to move (WDL only) and file of the leading pawn (with pawns only).
+    items: [[PairsData; 4]; 2],
+    /// DTZ value maps.
+    map: usize,
+}
+
+/// What a DTZ table has to say about a position.
+pub enum DtzProbe {
+    /// Distance to zeroing in plies, adjusted as described by `Table::probe`.
+    Value(i32),
+    /// The table only stores the other side to move.
+    OtherSide,
+}
+
+fn byte(bytes: &[u8], offset: usize) -> Option<u8> {
+    bytes.get(offset).copied()
+}
+
+fn le_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn be_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl PairsData {
+    /// Left and right halves of the pair that `symbol` stands for.
+    fn children(&self, bytes: &[u8], symbol: usize) -> Option<(usize, usize)> {
+        let entry = bytes.get(self.btree + 3 * symbol..self.btree + 3 * symbol + 3)?;
+        let left = ((entry[1] as usize & 0xf) << 8) | entry[0] as usize;
+        let right = ((entry[2] as usize) << 4) | (entry[1] as usize >> 4);
+
+        Some((left, right))
+    }
+
+    /// Splits the pieces into groups that are encoded together and works out where each
+    /// group's index starts. `order` gives the position of the leading group and of the
+    /// remaining pawns in the encoding.
+    fn set_groups(&mut self, material: &Material, order: [u8; 2], file: usize) {
+        let mut first_length: i32 = if material.has_pawns {
+            0
+        } else if material.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let mut count = 0;
+        self.group_length[0] = 1;
+        for i in 1..material.piece_count {
+            first_length -= 1;
+            if first_length > 0 || self.pieces[i] == self.pieces[i - 1] {
+                self.group_length[count] += 1;
+            } else {
+                count += 1;
+                self.group_length[count] = 1;
+            }
+        }
+        count += 1;
+        self.group_length[count] = 0;
+
+        let encoding = &*ENCODING;
+        let both_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares =
+            64 - self.group_length[0] - if both_pawns { self.group_length[1] } else { 0 };
+        let mut index = 1;
+        let mut k = 0;
+        while next < count || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                self.group_index[0] = index;
+                index *= if material.has_pawns {
+                    encoding.lead_pawns_size[self.group_length[0]][file]
+                } else if material.has_unique_pieces {
+                    UNIQUE_PIECES_SIZE
+                } else {
+                    KINGS_SIZE
+                };
+            } else if k == order[1] as usize {
+                self.group_index[1] = index;
+                index *= encoding.binomial[self.group_length[1]][48 - self.group_length[0]];
+            } else {
+                self.group_index[next] = index;
+                index *= encoding.binomial[self.group_length[next]][free_squares];
+                free_squares -= self.group_length[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_index[count] = index;
+    }
+
+    /// Number of values, one per index.
+    fn size(&self) -> u64 {
+        let groups = self
+            .group_length
+            .iter()
+            .position(|length| *length == 0)
+            .unwrap_or(MAX_PIECES);
+        self.group_index[groups]
+    }
+
+    fn set_symbol_length(&mut self, bytes: &[u8], symbol: usize, visited: &mut [bool]) -> u8 {
+        visited[symbol] = true;
+        let Some((left, right)) = self.children(bytes, symbol) else {
+            return 0;
+        };
+        if right == 0xfff || left >= visited.len() || right >= visited.len() {
+            return 0;
+        }
+        for child in [left, right] {
+            if !visited[child] {
+                self.symbol_length[child] = self.set_symbol_length(bytes, child, visited);
+            }
+        }
+
+        self.symbol_length[left]
+            .wrapping_add(self.symbol_length[right])
+            .wrapping_add(1)
+    }
+
+    /// Reads the block sizes and the Huffman code description at `offset`, returning the
+    /// offset after them.
+    fn set_sizes(&mut self, bytes: &[u8], mut offset: usize) -> Option<usize> {
+        self.flags = byte(bytes, offset)?;
+        offset += 1;
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            // the single value is kept where the minimum symbol length would go
+            self.min_symbol_length = byte(bytes, offset)? as usize;
+            return Some(offset + 1);
+        }
+
+        let table_size = self.size();
+
+        self.block_size = 1 << byte(bytes, offset)?;
+        self.span = 1 << byte(bytes, offset + 1)?;
+        self.sparse_index_size = table_size.div_ceil(self.span as u64) as usize;
+        let padding = byte(bytes, offset + 2)? as usize;
+        self.block_count = le_u32(bytes, offset + 3)? as usize;
+        self.block_length_size = self.block_count + padding;
+        self.max_symbol_length = byte(bytes, offset + 7)? as usize;
+        self.min_symbol_length = byte(bytes, offset + 8)? as usize;
+        offset += 9;
+        self.lowest_symbol = offset;
+        if self.min_symbol_length == 0 || self.max_symbol_length < self.min_symbol_length {
+            return None;
+        }
+
+        // Canonical Huffman code: longer symbols have lower values, so base64 decreases
+        // with the length and a left aligned code of length l lies between the bases of
+        // lengths l - 1 and l.
+        let lengths = self.max_symbol_length - self.min_symbol_length + 1;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = le_u16(bytes, self.lowest_symbol + 2 * i)? as u64;
+            let next_lowest = le_u16(bytes, self.lowest_symbol + 2 * (i + 1))? as u64;
+            self.base64[i] = (self.base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            let shift = 64 - i - self.min_symbol_length;
+            *base = if shift >= 64 { 0 } else { *base << shift };
+        }
+        offset += 2 * lengths;
+
+        let symbols = le_u16(bytes, offset)? as usize;
+        offset += 2;
+        self.btree = offset;
+        bytes.get(self.btree..self.btree + 3 * symbols)?;
+
+        // Recursive pairing: every symbol above the literals stands for a pair of
+        // symbols, and expands to as many values as both of them together.
+        self.symbol_length = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symbol_length[symbol] = self.set_symbol_length(bytes, symbol, &mut visited);
+            }
+        }
+
+        Some(offset + 3 * symbols + (symbols & 1))
+    }
+
+    fn block_length(&self, bytes: &[u8], block: usize) -> Option<i64> {
+        Some(le_u16(bytes, self.block_length + 2 * block)? as i64)
+    }
+
+    /// The value stored at `index`.
+    fn decompress(&self, bytes: &[u8], index: u64) -> Option<u16> {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(self.min_symbol_length as u16);
+        }
+
+        // The sparse index points into the block lengths for every `span` values; from
+        // there walk to the block that holds `index`.
+        let k = (index / self.span as u64) as usize;
+        if k >= self.sparse_index_size {
+            return None;
+        }
+        let entry = self.sparse_index + 6 * k;
+        let mut block = le_u32(bytes, entry)? as i64;
+        let mut offset = le_u16(bytes, entry + 4)? as i64;
+        offset += (index % self.span as u64) as i64 - (self.span / 2) as i64;
+
+        while offset < 0 {
+            block -= 1;
+            if block < 0 {
+                return None;
+            }
+            offset += self.block_length(bytes, block as usize)? + 1;
+        }
+        while offset > self.block_length(bytes, block as usize)? {
+            offset -= self.block_length(bytes, block as usize)? + 1;
+            block += 1;
+        }
+        if block as usize >= self.block_count {
+            return None;
+        }
+
+        // Decode symbols until the one that covers our offset.
+        let mut pointer = self.data + block as usize * self.block_size;
+        let mut buffer = be_u64(bytes, pointer)?;
+        pointer += 8;
+        let mut buffer_size = 64;
+        let mut symbol;
+        loop {
+            let mut length = 0;
+            while length + 1 < self.base64.len() && buffer < self.base64[length] {
+                length += 1;
+            }
+            // symbols are 16 bit, the arithmetic wraps like the generator's
+            let offset_in_length =
+                ((buffer - self.base64[length]) >> (64 - length - self.min_symbol_length)) as u16;
+            symbol = offset_in_length.wrapping_add(le_u16(bytes, self.lowest_symbol + 2 * length)?)
+                as usize;
+            let covered = *self.symbol_length.get(symbol)? as i64 + 1;
+            if offset < covered {
+                break;
+            }
+
+            offset -= covered;
+            let length = length + self.min_symbol_length;
+            buffer = buffer.checked_shl(length as u32).unwrap_or(0);
+            buffer_size -= length as i32;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= (be_u32(bytes, pointer)? as u64) << (64 - buffer_size);
+                pointer += 4;
+            }
+        }
+
+        // Expand the symbol's pairs down to the single value at our offset.
+        while self.symbol_length[symbol] != 0 {
+            let (left, right) = self.children(bytes, symbol)?;
+            let left_covered = *self.symbol_length.get(left)? as i64 + 1;
+            if offset < left_covered {
+                symbol = left;
+            } else {
+                offset -= left_covered;
+                symbol = right;
+            }
+            self.symbol_length.get(symbol)?;
+        }
+
+        Some(self.children(bytes, symbol)?.0 as u16)
+    }
+}
+
+/// Piece code used inside the tables: 1 to 6 for pawn to king, plus 8 for black.
+pub(super) fn table_piece(side: Side, piece: Piece) -> u8 {
+    piece as u8 + 1 + if side == Side::Black { 8 } else { 0 }
+}
+
+impl Table {
+    /// Reads a whole table file. The file has to match `material`.
+    pub fn load(path: &Path, kind: Kind, material: &Material) -> io::Result<Self> {
+        let mut bytes = fs::read(path)?;
+        if bytes.len() % 64 != 16 {
+            return Err(invalid("table size is not a multiple of 64 plus 16"));
+        }
+        let magic = match kind {
+            Kind::Wdl => WDL_MAGIC,
+            Kind::Dtz => DTZ_MAGIC,
+        };
+        if bytes[..4] != magic {
+            return Err(invalid("not a Syzygy table"));
+        }
+        // The last symbols of a block may be read past the end of the file.
+        bytes.extend_from_slice(&[0; 8]);
+
+        let mut table = Self {
+            kind,
+            bytes,
+            items: Default::default(),
+            map: 0,
+        };
+        table
+            .parse(material)
+            .ok_or_else(|| invalid("corrupt Syzygy table"))?;
+
+        Ok(table)
+    }
+
+    /// A table without values that encodes the pieces, as table piece codes, in `order`
+    /// for every file and side to move, with the leading group at position `lead` of
+    /// the index, to write one.
+    pub(super) fn with_order(kind: Kind, material: &Material, order: &[u8], lead: u8) -> Self {
+        let mut table = Self {
+            kind,
+            bytes: Vec::new(),
+            items: Default::default(),
+            map: 0,
+        };
+        let files = if material.has_pawns { 4 } else { 1 };
+        for file in 0..files {
+            for side in table.items.iter_mut() {
+                let item = &mut side[file];
+                item.pieces[..order.len()].copy_from_slice(order);
+                item.set_groups(material, [lead, 0xf], file);
+            }
+        }
+
+        table
+    }
+
+    /// Number of groups the pieces are encoded in, the same for every sub-table.
+    pub(super) fn group_count(&self) -> usize {
+        self.items[0][0]
+            .group_length
+            .iter()
+            .take_while(|length| **length > 0)
+            .count()
+    }
+
+    /// Number of indices of the sub-table for a stored side to move and file.
+    pub(super) fn size(&self, stm: usize, file: usize) -> u64 {
+        self.items[stm][file].size()
+    }
+
+    fn parse(&mut self, material: &Material) -> Option<()> {
+        let bytes = std::mem::take(&mut self.bytes);
+        let result = self.parse_bytes(&bytes, material);
+        self.bytes = bytes;
+
+        result
+    }
+
+    fn parse_bytes(&mut self, bytes: &[u8], material: &Material) -> Option<()> {
+        let flags = byte(bytes, 4)?;
+        let split = material.key != material.mirrored_key;
+        if (flags & 2 != 0) != material.has_pawns
+            || (self.kind == Kind::Wdl && (flags & 1 != 0) != split)
+        {
+            return None;
+        }
+
+        let mut offset = 5;
+        let sides = if self.kind == Kind::Wdl && split {
+            2
+        } else {
+            1
+        };
+        let files = if material.has_pawns { 4 } else { 1 };
+        let both_pawns = material.has_pawns && material.pawn_count[1] > 0;
+
+        for file in 0..files {
+            let first = byte(bytes, offset)?;
+            let second = if both_pawns {
+                byte(bytes, offset + 1)?
+            } else {
+                0xff
+            };
+            let order = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+            offset += 1 + both_pawns as usize;
+
+            for k in 0..material.piece_count {
+                let pieces = byte(bytes, offset)?;
+                for side in 0..sides {
+                    self.items[side][file].pieces[k] =
+                        if side == 1 { pieces >> 4 } else { pieces & 0xf };
+                }
+                offset += 1;
+            }
+            for (side, order) in order.iter().enumerate().take(sides) {
+                self.items[side][file].set_groups(material, *order, file);
+            }
+        }
+        offset += offset & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                offset = self.items[side][file].set_sizes(bytes, offset)?;
+            }
+        }
+
+        if self.kind == Kind::Dtz {
+            self.map = offset;
+            for file in 0..files {
+                let item = &mut self.items[0][file];
+                if item.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if item.flags & FLAG_WIDE != 0 {
+                    offset += offset & 1;
+                    for i in 0..4 {
+                        item.map_index[i] = (offset - self.map) / 2 + 1;
+                        offset += 2 * le_u16(bytes, offset)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        item.map_index[i] = offset - self.map + 1;
+                        offset += byte(bytes, offset)? as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let item = &mut self.items[side][file];
+                item.sparse_index = offset;
+                offset += 6 * item.sparse_index_size;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let item = &mut self.items[side][file];
+                item.block_length = offset;
+                offset += 2 * item.block_length_size;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let item = &mut self.items[side][file];
+                offset = (offset + 0x3f) & !0x3f;
+                item.data = offset;
+                offset += item.block_count * item.block_size;
+            }
+        }
+
+        // the padding added on load doesn't count
+        if offset > bytes.len() - 8 {
+            return None;
+        }
+
+        Some(())
+    }
+
+    /// Where the table stores a position: the side to move as stored, the file of the
+    /// leading pawn and the index. DTZ tables only store one side to move.
+    pub(super) fn locate(
+        &self,
+        material: &Material,
+        board: &Board,
+        active: Side,
+    ) -> Option<(usize, usize, u64)> {
+        let encoding = &*ENCODING;
+        let key = material_key(&piece_counts(board));
+
+        // Tables have the stronger side as white, and symmetric ones only white to move.
+        let black_to_move = active == Side::Black;
+        let flip = (material.is_symmetric() && black_to_move) || key != material.key;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip ^ black_to_move) as usize;
+
+        let mut squares = [0u8; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0u64;
+        let mut lead_count = 0;
+        let mut file = 0;
+
+        // With pawns the table is split by the file of the leading pawn: the one that's
+        // nearest the edge, and of those the one furthest back.
+        if material.has_pawns {
+            let lead_piece = self.items[0][0].pieces[0] ^ flip_color;
+            let lead_side = if lead_piece & 8 != 0 {
+                Side::Black
+            } else {
+                Side::White
+            };
+            let pawns = board.side_pieces(lead_side).pawns();
+            lead_pawns = pawns.data();
+            for square in pawns {
+                squares[size] = square.index() ^ flip_squares;
+                size += 1;
+            }
+            lead_count = size;
+
+            let mut lead = 0;
+            for i in 1..lead_count {
+                if encoding.map_pawns[squares[i] as usize]
+                    > encoding.map_pawns[squares[lead] as usize]
+                {
+                    lead = i;
+                }
+            }
+            squares.swap(0, lead);
+            let lead_file = (squares[0] & 7) as usize;
+            file = lead_file.min(7 - lead_file);
+        }
+
+        let others = board.occupied().data() ^ lead_pawns;
+        for index in 0..64u8 {
+            if others & (1 << index) == 0 {
+                continue;
+            }
+            let (side, piece) = board.piece_at(Square::new(index))?;
+            if size >= MAX_PIECES {
+                return None;
+            }
+            squares[size] = index ^ flip_squares;
+            pieces[size] = table_piece(side, piece) ^ flip_color;
+            size += 1;
+        }
+        if size != material.piece_count {
+            return None;
+        }
+
+        let item = match self.kind {
+            Kind::Wdl => &self.items[stm][file],
+            Kind::Dtz => &self.items[0][file],
+        };
+
+        // Put the pieces in the order the table encodes them.
+        for i in lead_count..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if item.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Mirror so that the leading piece is on files a to d.
+        if squares[0] & 7 > 3 {
+            for square in squares[..size].iter_mut() {
+                *square ^= 7;
+            }
+        }
+
+        let mut index;
+        if material.has_pawns {
+            index = encoding.lead_pawn_index[lead_count][squares[0] as usize];
+            squares[1..lead_count].sort_by_key(|square| encoding.map_pawns[*square as usize]);
+            for (i, square) in squares.iter().enumerate().take(lead_count).skip(1) {
+                index += encoding.binomial[i][encoding.map_pawns[*square as usize] as usize];
+            }
+        } else {
+            // Without pawns, also mirror to ranks 1 to 4 and then below the diagonal.
+            if squares[0] >> 3 > 3 {
+                for square in squares[..size].iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..item.group_length[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in squares[i..size].iter_mut() {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            index = if material.has_unique_pieces {
+                let s = squares.map(|square| square as u64);
+                let adjust1 = (s[1] > s[0]) as u64;
+                let adjust2 = (s[2] > s[0]) as u64 + (s[2] > s[1]) as u64;
+                let rank = |square: u64| square >> 3;
+
+                if off_diagonal(squares[0]) != 0 {
+                    (encoding.map_a1d1d4[s[0] as usize] * 63 + (s[1] - adjust1)) * 62 + s[2]
+                        - adjust2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + rank(s[0]) * 28 + encoding.map_b1h1h7[s[1] as usize]) * 62 + s[2]
+                        - adjust2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(s[0]) * 7 * 28
+                        + (rank(s[1]) - adjust1) * 28
+                        + encoding.map_b1h1h7[s[2] as usize]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(s[0]) * 7 * 6
+                        + (rank(s[1]) - adjust1) * 6
+                        + (rank(s[2]) - adjust2)
+                }
+            } else {
+                encoding.map_kk[encoding.map_a1d1d4[squares[0] as usize] as usize]
+                    [squares[1] as usize]
+            };
+        }
+
+        // The remaining groups, each encoded as a combination of the squares that the
+        // groups before it leave free.
+        index *= item.group_index[0];
+        let mut group_start = item.group_length[0];
+        let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = 1;
+        while item.group_length[next] != 0 {
+            let length = item.group_length[next];
+            squares[group_start..group_start + length].sort_unstable();
+            let mut n = 0;
+            for i in 0..length {
+                let square = squares[group_start + i];
+                let adjust = squares[..group_start]
+                    .iter()
+                    .filter(|other| square > **other)
+                    .count();
+                let free = square as usize - adjust - if remaining_pawns { 8 } else { 0 };
+                n += encoding.binomial[i + 1][free];
+            }
+            remaining_pawns = false;
+            index += n * item.group_index[next];
+            group_start += length;
+            next += 1;
+        }
+
+        Some((stm, file, index))
+    }
+
+    /// Looks the position up. For WDL tables the result is -2 (loss) to 2 (win) for the
+    /// side to move, with -1 and 1 for results that the fifty move rule turns into draws.
+    /// For DTZ tables `wdl` has to be the position's WDL value; the result is the
+    /// distance to the next capture or pawn move in plies, plus one, rounded up to
+    /// whole moves where the table only stores moves.
+    fn probe(
+        &self,
+        material: &Material,
+        board: &Board,
+        active: Side,
+        wdl: i32,
+    ) -> Option<DtzProbe> {
+        let (stm, file, index) = self.locate(material, board, active)?;
+        let item = match self.kind {
+            Kind::Wdl => &self.items[stm][file],
+            Kind::Dtz => {
+                // symmetric pawnless tables are only stored for one side
+                let one_sided = material.is_symmetric() && !material.has_pawns;
+                if (self.items[0][file].flags & FLAG_STM) as usize != stm && !one_sided {
+                    return Some(DtzProbe::OtherSide);
+                }
+                &self.items[0][file]
+            }
+        };
+
+        let value = item.decompress(&self.bytes, index)? as i32;
+        Some(DtzProbe::Value(match self.kind {
+            Kind::Wdl => value - 2,
+            Kind::Dtz => self.map_dtz(file, value, wdl)?,
+        }))
+    }
+
+    fn map_dtz(&self, file: usize, mut value: i32, wdl: i32) -> Option<i32> {
+        let item = &self.items[0][file];
+        let flags = item.flags;
+        if flags & FLAG_MAPPED != 0 {
+            // wins, losses, cursed wins and blessed losses each have their own map
+            let map = match wdl {
+                2 => 0,
+                -2 => 1,
+                1 => 2,
+                _ => 3,
+            };
+            let start = item.map_index[map] + value as usize;
+            value = if flags & FLAG_WIDE != 0 {
+                le_u16(&self.bytes, self.map + 2 * start)? as i32
+            } else {
+                byte(&self.bytes, self.map + start)? as i32
+            };
+        }
+
+        if (wdl == 2 && flags & FLAG_WIN_PLIES == 0)
+            || (wdl == -2 && flags & FLAG_LOSS_PLIES == 0)
+            || wdl == 1
+            || wdl == -1
+        {
+            value *= 2;
+        }
+
+        Some(value + 1)
+    }
+
+    /// WDL value of a position without captures to consider; see `probe`.
+    pub fn probe_wdl(&self, material: &Material, board: &Board, active: Side) -> Option<i32> {
+        match self.probe(material, board, active, 0)? {
+            DtzProbe::Value(value) => Some(value),
+            DtzProbe::OtherSide => None,
+        }
+    }
+
+    /// Raw DTZ value of a position whose WDL value is `wdl`; see `probe`.
+    pub fn probe_dtz(
+        &self,
+        material: &Material,
+        board: &Board,
+        active: Side,
+        wdl: i32,
+    ) -> Option<DtzProbe> {
+        self.probe(material, board, active, wdl)
+    }
+}
@@ -0,0 +1,598 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::chess::board::{Board, Piece, SideBoard, Square};
+use crate::chess::game::Side;
+use crate::tablebase::dtz::{Dtz, DtzTables};
+use crate::tablebase::index::Layout;
+use crate::tablebase::material::Material as TablebaseMaterial;
+use crate::tablebase::{Position, MAX_PIECES as TABLEBASE_PIECES};
+
+use super::table::{
+    table_piece, Kind, Material, Table, DTZ_MAGIC, FLAG_LOSS_PLIES, FLAG_SINGLE_VALUE,
+    FLAG_WIN_PLIES, WDL_MAGIC,
+};
+
+/// Values a compressed block holds at most, so that offsets into it fit the sparse index.
+const MAX_BLOCK_VALUES: usize = 1 << 15;
+const BLOCK_SIZE_LOG: u8 = 6;
+/// Symbols are numbered with 12 bits, and 0xfff marks the literal ones.
+const MAX_SYMBOLS: usize = 0xfff;
+/// Values a symbol stands for at most, their count minus one is kept in a byte.
+const MAX_SYMBOL_VALUES: usize = 256;
+const MAX_CODE_LENGTH: usize = 32;
+/// A pair that occurs less often isn't worth a symbol.
+const MIN_PAIR_COUNT: u32 = 8;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[derive(Clone, Copy)]
+enum Symbol {
+    Literal(u16),
+    Pair(u16, u16),
+}
+
+/// One sub-table, compressed the way Syzygy tables are: runs of values are replaced by
+/// symbols that stand for pairs of symbols, and the symbols are Huffman coded into
+/// blocks of a fixed size.
+struct Compressed {
+    single_value: Option<u16>,
+    /// Symbols, renumbered so that longer codes have lower numbers.
+    symbols: Vec<Symbol>,
+    min_length: usize,
+    /// The lowest symbol with each code length, from `min_length` on.
+    lowest: Vec<u16>,
+    span_log: u8,
+    /// Values in each block and the blocks themselves.
+    block_values: Vec<usize>,
+    blocks: Vec<Vec<u8>>,
+    size: u64,
+}
+
+/// Huffman code lengths for symbols with these frequencies, no longer than
+/// `MAX_CODE_LENGTH`. Symbols that don't occur get no code.
+fn code_lengths(frequencies: &[u64]) -> Vec<usize> {
+    let mut frequencies = frequencies.to_vec();
+    loop {
+        let mut heap = BinaryHeap::new();
+        // nodes are the symbols, then the merged subtrees
+        let mut parents = vec![usize::MAX; frequencies.len()];
+        for (symbol, frequency) in frequencies.iter().enumerate() {
+            if *frequency > 0 {
+                heap.push(Reverse((*frequency, symbol)));
+            }
+        }
+        while heap.len() > 1 {
+            let Reverse((first, left)) = heap.pop().expect("two nodes");
+            let Reverse((second, right)) = heap.pop().expect("two nodes");
+            let node = parents.len();
+            parents.push(usize::MAX);
+            parents[left] = node;
+            parents[right] = node;
+            heap.push(Reverse((first + second, node)));
+        }
+
+        let mut lengths = vec![0; frequencies.len()];
+        for (symbol, length) in lengths.iter_mut().enumerate() {
+            if frequencies[symbol] == 0 {
+                continue;
+            }
+            let mut node = symbol;
+            while parents[node] != usize::MAX {
+                node = parents[node];
+                *length += 1;
+            }
+        }
+        if lengths.iter().all(|length| *length <= MAX_CODE_LENGTH) {
+            return lengths;
+        }
+
+        // flatten the distribution until the code is short enough
+        for frequency in frequencies.iter_mut().filter(|frequency| **frequency > 0) {
+            *frequency = *frequency / 2 + 1;
+        }
+    }
+}
+
+/// Replaces the most frequent pair of neighbouring symbols by a new symbol until no
+/// pair is frequent enough to pay for one.
+fn pair_symbols(values: &[u16]) -> (Vec<Symbol>, Vec<u16>) {
+    let literal_count = *values.iter().max().expect("values") as usize + 1;
+    let mut symbols: Vec<Symbol> = (0..literal_count as u16).map(Symbol::Literal).collect();
+    let mut lengths = vec![1; literal_count];
+    let mut sequence = values.to_vec();
+    let mut counts = vec![0u32; MAX_SYMBOLS * MAX_SYMBOLS];
+
+    while symbols.len() < MAX_SYMBOLS {
+        let key = |pair: &[u16]| pair[0] as usize * MAX_SYMBOLS + pair[1] as usize;
+        let mut best = (0, 0);
+        for pair in sequence.windows(2) {
+            if lengths[pair[0] as usize] + lengths[pair[1] as usize] > MAX_SYMBOL_VALUES {
+                continue;
+            }
+            let count = &mut counts[key(pair)];
+            *count += 1;
+            if *count > best.0 {
+                best = (*count, key(pair));
+            }
+        }
+        for pair in sequence.windows(2) {
+            counts[key(pair)] = 0;
+        }
+        if best.0 < MIN_PAIR_COUNT {
+            break;
+        }
+
+        let (left, right) = ((best.1 / MAX_SYMBOLS) as u16, (best.1 % MAX_SYMBOLS) as u16);
+        let symbol = symbols.len() as u16;
+        symbols.push(Symbol::Pair(left, right));
+        lengths.push(lengths[left as usize] + lengths[right as usize]);
+
+        let mut paired = Vec::with_capacity(sequence.len());
+        let mut i = 0;
+        while i < sequence.len() {
+            if i + 1 < sequence.len() && sequence[i] == left && sequence[i + 1] == right {
+                paired.push(symbol);
+                i += 2;
+            } else {
+                paired.push(sequence[i]);
+                i += 1;
+            }
+        }
+        sequence = paired;
+    }
+
+    (symbols, sequence)
+}
+
+fn compress(values: &[u16]) -> Compressed {
+    let size = values.len() as u64;
+    if values.iter().all(|value| *value == values[0]) {
+        return Compressed {
+            single_value: Some(values[0]),
+            symbols: Vec::new(),
+            min_length: 0,
+            lowest: Vec::new(),
+            span_log: 0,
+            block_values: Vec::new(),
+            blocks: Vec::new(),
+            size,
+        };
+    }
+
+    let (symbols, sequence) = pair_symbols(values);
+    let mut frequencies = vec![0u64; symbols.len()];
+    for symbol in sequence.iter() {
+        frequencies[*symbol as usize] += 1;
+    }
+    // a code needs two symbols
+    if frequencies
+        .iter()
+        .filter(|frequency| **frequency > 0)
+        .count()
+        == 1
+    {
+        let unused = frequencies.iter().position(|frequency| *frequency == 0);
+        frequencies[unused.expect("a literal is left over")] = 1;
+    }
+    let lengths = code_lengths(&frequencies);
+    let min_length = *lengths
+        .iter()
+        .filter(|length| **length > 0)
+        .min()
+        .expect("codes");
+    let max_length = *lengths.iter().max().expect("codes");
+
+    // Canonical code: longer codes get the lower symbol numbers, and within a length
+    // codes count up from the base that the longer ones leave.
+    let mut renumbered = vec![0u16; symbols.len()];
+    let mut order = Vec::with_capacity(symbols.len());
+    let mut lowest = vec![0u16; max_length - min_length + 1];
+    let mut counts = vec![0u64; max_length - min_length + 1];
+    for length in (min_length..=max_length).rev() {
+        lowest[length - min_length] = order.len() as u16;
+        for (symbol, symbol_length) in lengths.iter().enumerate() {
+            if *symbol_length == length {
+                renumbered[symbol] = order.len() as u16;
+                order.push(symbol);
+                counts[length - min_length] += 1;
+            }
+        }
+    }
+    for (symbol, length) in lengths.iter().enumerate() {
+        if *length == 0 {
+            renumbered[symbol] = order.len() as u16;
+            order.push(symbol);
+        }
+    }
+    let mut base = vec![0u64; lowest.len()];
+    for i in (0..lowest.len() - 1).rev() {
+        let next = base[i + 1] + counts[i + 1];
+        assert!(next.is_multiple_of(2), "incomplete Huffman code");
+        base[i] = next / 2;
+    }
+    assert_eq!(
+        base[0] + counts[0],
+        1 << min_length,
+        "incomplete Huffman code"
+    );
+
+    let renumber = |symbol: Symbol| match symbol {
+        Symbol::Literal(value) => Symbol::Literal(value),
+        Symbol::Pair(left, right) => {
+            Symbol::Pair(renumbered[left as usize], renumbered[right as usize])
+        }
+    };
+    let symbol_values: Vec<usize> = {
+        let mut symbol_values = vec![0; symbols.len()];
+        for (symbol, entry) in symbols.iter().enumerate() {
+            symbol_values[symbol] = match entry {
+                Symbol::Literal(_) => 1,
+                Symbol::Pair(left, right) => {
+                    symbol_values[*left as usize] + symbol_values[*right as usize]
+                }
+            };
+        }
+        symbol_values
+    };
+
+    // Pack whole symbols into blocks.
+    let block_bits = 8 << BLOCK_SIZE_LOG;
+    let mut blocks = Vec::new();
+    let mut block_values = Vec::new();
+    let mut block = vec![0u8; 1 << BLOCK_SIZE_LOG];
+    let mut bits = 0;
+    let mut in_block = 0;
+    for symbol in sequence.iter().map(|symbol| *symbol as usize) {
+        let length = lengths[symbol];
+        let covered = symbol_values[symbol];
+        if bits + length > block_bits || in_block + covered > MAX_BLOCK_VALUES {
+            blocks.push(std::mem::replace(
+                &mut block,
+                vec![0u8; 1 << BLOCK_SIZE_LOG],
+            ));
+            block_values.push(in_block);
+            bits = 0;
+            in_block = 0;
+        }
+
+        let class = length - min_length;
+        let code = base[class] + (renumbered[symbol] - lowest[class]) as u64;
+        for bit in (0..length).rev() {
+            if code >> bit & 1 != 0 {
+                block[bits / 8] |= 0x80 >> (bits % 8);
+            }
+            bits += 1;
+        }
+        in_block += covered;
+    }
+    blocks.push(block);
+    block_values.push(in_block);
+
+    // about one sparse index entry per block
+    let per_block = (size / blocks.len() as u64).max(1);
+    let span_log = (63 - per_block.leading_zeros() as u8).clamp(BLOCK_SIZE_LOG, 15);
+
+    Compressed {
+        single_value: None,
+        symbols: order
+            .iter()
+            .map(|symbol| renumber(symbols[*symbol]))
+            .collect(),
+        min_length,
+        lowest,
+        span_log,
+        block_values,
+        blocks,
+        size,
+    }
+}
+
+impl Compressed {
+    /// The block sizes and Huffman code description, after the flags.
+    fn write_sizes(&self, out: &mut Vec<u8>, flags: u8) {
+        if let Some(value) = self.single_value {
+            out.extend([flags | FLAG_SINGLE_VALUE, value as u8]);
+            return;
+        }
+
+        out.push(flags);
+        out.extend([BLOCK_SIZE_LOG, self.span_log, 0]);
+        out.extend((self.blocks.len() as u32).to_le_bytes());
+        let max_length = self.min_length + self.lowest.len() - 1;
+        out.extend([max_length as u8, self.min_length as u8]);
+        for lowest in self.lowest.iter() {
+            out.extend(lowest.to_le_bytes());
+        }
+        out.extend((self.symbols.len() as u16).to_le_bytes());
+        for symbol in self.symbols.iter() {
+            let (left, right) = match symbol {
+                Symbol::Literal(value) => (*value as usize, 0xfff),
+                Symbol::Pair(left, right) => (*left as usize, *right as usize),
+            };
+            out.extend([
+                left as u8,
+                ((left >> 8) & 0xf | (right & 0xf) << 4) as u8,
+                (right >> 4) as u8,
+            ]);
+        }
+        if self.symbols.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+
+    /// Block and offset of the value in the middle of every span.
+    fn write_sparse_index(&self, out: &mut Vec<u8>) {
+        if self.single_value.is_some() {
+            return;
+        }
+        let span = 1u64 << self.span_log;
+        let mut block = 0;
+        let mut start = 0u64;
+        for k in 0..self.size.div_ceil(span) {
+            let middle = k * span + span / 2;
+            while block + 1 < self.blocks.len()
+                && start + self.block_values[block] as u64 <= middle.min(self.size - 1)
+            {
+                start += self.block_values[block] as u64;
+                block += 1;
+            }
+            out.extend((block as u32).to_le_bytes());
+            out.extend(((middle - start) as u16).to_le_bytes());
+        }
+    }
+
+    fn write_block_lengths(&self, out: &mut Vec<u8>) {
+        if self.single_value.is_some() {
+            return;
+        }
+        for values in self.block_values.iter() {
+            out.extend((*values as u16 - 1).to_le_bytes());
+        }
+    }
+}
+
+/// The order in which the tables encode the pieces: the leading pawns, or without pawns
+/// the pieces there is only one of, then the others by kind.
+fn piece_order(material: &Material, counts: &[[u8; 6]; 2]) -> Vec<u8> {
+    let mut order = Vec::new();
+    for (side, counts) in [Side::White, Side::Black].into_iter().zip(counts) {
+        for piece in Piece::ALL {
+            for _ in 0..counts[piece as usize] {
+                order.push(table_piece(side, piece));
+            }
+        }
+    }
+
+    let count = |code: u8| order.iter().filter(|other| **other == code).count();
+    // only one side has pawns
+    let lead = material.has_pawns.then(|| {
+        let side = if counts[0][Piece::Pawn as usize] > 0 {
+            Side::White
+        } else {
+            Side::Black
+        };
+        table_piece(side, Piece::Pawn)
+    });
+    let mut sorted = order.clone();
+    sorted.sort_by_key(|code| (Some(*code) != lead, count(*code), *code));
+    sorted
+}
+
+/// The values an index may store, lowest and highest. The prober never reads the ones
+/// that no position maps to, or the ones whose best move it finds by searching.
+type Range = (u16, u16);
+
+const ANY: Range = (0, u16::MAX);
+
+/// The stored WDL value, `wdl + 2`. When a capture is as good as the position gets, the
+/// prober takes the better of the two and anything up to it will do.
+fn wdl_range(value: Dtz) -> Range {
+    let wdl = (value.wdl + 2) as u16;
+    if value.only_captures {
+        ANY
+    } else if value.capture == Some(value.wdl) {
+        (0, wdl)
+    } else {
+        (wdl, wdl)
+    }
+}
+
+/// Packs a value for a DTZ table: plies to zeroing less one, or for the results that
+/// the fifty move rule draws, moves beyond the 100th ply. Draws aren't stored, nor are
+/// positions where the prober finds a zeroing move that wins or that is all there is.
+fn dtz_range(value: Dtz) -> Range {
+    let plies = match value.wdl {
+        _ if value.only_zeroing => return ANY,
+        wdl if wdl > 0 && value.zeroing == Some(wdl) => return ANY,
+        2 | -2 => value.plies.saturating_sub(1),
+        1 | -1 => value.plies.saturating_sub(101) / 2,
+        _ => return ANY,
+    };
+    (plies, plies)
+}
+
+/// Picks for every index the value closest to the one before it, which compresses best.
+fn fill(ranges: &[Range]) -> Vec<u16> {
+    let mut previous = ranges
+        .iter()
+        .find(|range| **range != ANY)
+        .map_or(0, |range| range.0);
+    ranges
+        .iter()
+        .map(|(low, high)| {
+            previous = previous.clamp(*low, *high);
+            previous
+        })
+        .collect()
+}
+
+fn write_table(
+    kind: Kind,
+    material: &Material,
+    order: &[u8],
+    lead: u8,
+    values: &[[Vec<Range>; 4]; 2],
+    path: &Path,
+) -> io::Result<()> {
+    let split = material.key != material.mirrored_key;
+    let sides = if kind == Kind::Wdl && split { 2 } else { 1 };
+    let files = if material.has_pawns { 4 } else { 1 };
+    let item_flags = match kind {
+        Kind::Wdl => 0,
+        // only white to move is stored, in plies
+        Kind::Dtz => FLAG_WIN_PLIES | FLAG_LOSS_PLIES,
+    };
+
+    let mut compressed = Vec::new();
+    for file in 0..files {
+        for side_values in values.iter().take(sides) {
+            compressed.push(compress(&fill(&side_values[file])));
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend(match kind {
+        Kind::Wdl => WDL_MAGIC,
+        Kind::Dtz => DTZ_MAGIC,
+    });
+    out.push(split as u8 | (material.has_pawns as u8) << 1);
+    for _ in 0..files {
+        // the leading group first for both sides to move
+        out.push(lead | lead << 4);
+        out.extend(order.iter().map(|code| code | code << 4));
+    }
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    for item in compressed.iter() {
+        item.write_sizes(&mut out, item_flags);
+    }
+    if kind == Kind::Dtz && out.len() % 2 == 1 {
+        out.push(0);
+    }
+    for item in compressed.iter() {
+        item.write_sparse_index(&mut out);
+    }
+    for item in compressed.iter() {
+        item.write_block_lengths(&mut out);
+    }
+    for item in compressed.iter() {
+        out.resize(out.len().next_multiple_of(64), 0);
+        for block in item.blocks.iter() {
+            out.extend(block);
+        }
+    }
+    out.resize(out.len().next_multiple_of(64) + 16, 0);
+
+    fs::write(path, out)
+}
+
+/// Writes the Syzygy WDL and DTZ tables of a material like `KRvKP` to `directory`, from
+/// tables generated by `generate_dtz` for it and everything it captures or promotes
+/// into. DTZ tables only store white to move, and pawns of both sides aren't supported
+/// since the generator doesn't know about en passant.
+pub fn write_tables(name: &str, tables: &DtzTables, directory: &Path) -> io::Result<()> {
+    let material = Material::from_name(name).ok_or_else(|| invalid("invalid material"))?;
+    if material.piece_count > TABLEBASE_PIECES {
+        return Err(invalid("too many pieces"));
+    }
+    if material.pawn_count[1] > 0 {
+        return Err(invalid("pawns of both sides"));
+    }
+
+    let (strong, weak) = name.split_once('v').expect("parsed");
+    let mut counts = [[0u8; 6]; 2];
+    let mut pieces: [Vec<Piece>; 2] = Default::default();
+    for (side, letters) in [strong, weak].into_iter().enumerate() {
+        for piece in letters.chars().filter_map(Piece::from_char) {
+            counts[side][piece as usize] += 1;
+            if piece != Piece::King {
+                pieces[side].push(piece);
+            }
+        }
+    }
+    let [white, black] = pieces;
+    let generated = TablebaseMaterial::new(white, black);
+    if !tables.contains(&generated) {
+        return Err(invalid("no generated table for the material"));
+    }
+
+    // The leading pawns or unique pieces change least often, in the most significant
+    // position of the index, which makes for longer runs of equal values.
+    let order = piece_order(&material, &counts);
+    let lead = Table::with_order(Kind::Wdl, &material, &order, 0).group_count() as u8 - 1;
+    let wdl_table = Table::with_order(Kind::Wdl, &material, &order, lead);
+    let dtz_table = Table::with_order(Kind::Dtz, &material, &order, lead);
+    let empty = |table: &Table, stm: usize| -> [Vec<Range>; 4] {
+        std::array::from_fn(|file| {
+            let files = if material.has_pawns { 4 } else { 1 };
+            let size = if file < files {
+                table.size(stm, file)
+            } else {
+                0
+            };
+            vec![ANY; size as usize]
+        })
+    };
+    let mut wdl_values = [empty(&wdl_table, 0), empty(&wdl_table, 1)];
+    let mut dtz_values = [empty(&dtz_table, 0), empty(&dtz_table, 1)];
+
+    let layout = Layout::new(&generated);
+    let layout_pieces = layout.pieces().to_vec();
+    let mut squares = [0; TABLEBASE_PIECES];
+    for index in 0..layout.size() {
+        layout.decode(index, &mut squares);
+        let squares = &squares[..layout_pieces.len()];
+        if layout.encode(squares) != index {
+            continue;
+        }
+
+        for active in [Side::White, Side::Black] {
+            let mut position = Position::new(active);
+            let mut board = Board::new(SideBoard::new_empty(), SideBoard::new_empty());
+            for ((side, piece), square) in layout_pieces.iter().zip(squares) {
+                position.add(*side, *piece, *square);
+                board
+                    .side_pieces_mut(*side)
+                    .add_piece(*piece, Square::new(*square));
+            }
+            let Some(value) = tables.probe_position(&position) else {
+                continue;
+            };
+
+            let (stm, file, index) = wdl_table
+                .locate(&material, &board, active)
+                .ok_or_else(|| invalid("position outside the table"))?;
+            wdl_values[stm][file][index as usize] = wdl_range(value);
+            if active == Side::White {
+                let (_, file, index) = dtz_table
+                    .locate(&material, &board, active)
+                    .ok_or_else(|| invalid("position outside the table"))?;
+                dtz_values[0][file][index as usize] = dtz_range(value);
+            }
+        }
+    }
+
+    write_table(
+        Kind::Wdl,
+        &material,
+        &order,
+        lead,
+        &wdl_values,
+        &directory.join(format!("{}.rtbw", name)),
+    )?;
+    write_table(
+        Kind::Dtz,
+        &material,
+        &order,
+        lead,
+        &dtz_values,
+        &directory.join(format!("{}.rtbz", name)),
+    )
+}
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+
+use super::generate::{for_each_move, for_each_unmove, is_attacked, is_legal, Successor};
+use super::index::Layout;
+use super::material::Material;
+use super::{locate, Position, MAX_PIECES};
+use crate::chess::board::Piece;
+use crate::chess::game::Side;
+
+/// Plies without a capture or a pawn move after which the fifty move rule draws.
+pub const FIFTY_MOVE_PLIES: u16 = 100;
+
+/// A position's value under the fifty move rule, as Syzygy tables see it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Dtz {
+    /// -2 (lost) to 2 (won) for the side to move, with -1 and 1 for the losses and wins
+    /// that the fifty move rule turns into draws.
+    pub wdl: i8,
+    /// Plies until the winning side captures, moves a pawn or mates, 0 for draws. Wins
+    /// and losses that the rule draws are counted as if it didn't exist.
+    pub plies: u16,
+    /// The best `wdl` that a capture reaches, and that a capture or a pawn move does.
+    pub capture: Option<i8>,
+    pub zeroing: Option<i8>,
+    /// Every move captures, and every move captures or moves a pawn.
+    pub only_captures: bool,
+    pub only_zeroing: bool,
+}
+
+impl Dtz {
+    const ILLEGAL: Self = Self {
+        wdl: i8::MIN,
+        plies: 0,
+        capture: None,
+        zeroing: None,
+        only_captures: false,
+        only_zeroing: false,
+    };
+    const DRAW: Self = Self {
+        wdl: 0,
+        ..Self::ILLEGAL
+    };
+}
+
+/// Distance to zeroing for every position of one material, with either side to move.
+pub struct DtzTable {
+    material: Material,
+    layout: Layout,
+    values: [Vec<Dtz>; 2],
+}
+
+impl DtzTable {
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    /// The value of the position with this index, `None` if it's illegal or a
+    /// duplicate of another by symmetry.
+    pub fn value(&self, active: Side, index: usize) -> Option<Dtz> {
+        Some(self.values[active as usize][index]).filter(|value| *value != Dtz::ILLEGAL)
+    }
+
+    /// The most plies a win with `side` to move takes to the next capture, pawn move or
+    /// mate, if it ever wins within the fifty move rule.
+    pub fn longest_win(&self, side: Side) -> Option<u16> {
+        self.values[side as usize]
+            .iter()
+            .filter(|value| value.wdl == 2)
+            .map(|value| value.plies)
+            .max()
+    }
+}
+
+/// Tables generated by `generate_dtz`, by material.
+#[derive(Default)]
+pub struct DtzTables {
+    tables: HashMap<String, DtzTable>,
+}
+
+impl DtzTables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, table: DtzTable) {
+        self.tables.insert(table.material().name(), table);
+    }
+
+    pub fn get(&self, material: &Material) -> Option<&DtzTable> {
+        self.tables.get(&material.canonical().0.name())
+    }
+
+    /// Whether positions with this material can be probed. Bare kings always can.
+    pub fn contains(&self, material: &Material) -> bool {
+        material.is_bare_kings() || self.get(material).is_some()
+    }
+
+    /// The value of a legal position, or `None` without a table for its material.
+    pub fn probe_position(&self, position: &Position) -> Option<Dtz> {
+        let (material, flipped) = position.material().canonical();
+        if material.is_bare_kings() {
+            return Some(Dtz::DRAW);
+        }
+        let table = self.tables.get(&material.name())?;
+        let (active, index) = locate(&table.layout, position, flipped)?;
+        table.value(active, index)
+    }
+}
+
+/// What the moves of a position lead to.
+#[derive(Clone, Copy)]
+struct Node {
+    legal: bool,
+    mated: bool,
+    /// The best `Dtz::wdl` reached by a capture, promotion or pawn move, if there is one.
+    best_exit: Option<i8>,
+    best_capture: Option<i8>,
+    /// Every move captures.
+    only_captures: bool,
+    /// Distinct positions of the same table that the other moves reach.
+    successors: u16,
+}
+
+const NOT_LOSING: u16 = u16::MAX;
+
+/// Won (1) and lost (-1) positions, and how many plies they are from zeroing.
+struct Outcomes {
+    outcome: [Vec<i8>; 2],
+    plies: [Vec<u16>; 2],
+    counters: [Vec<u16>; 2],
+}
+
+fn advancement(pieces: &[(Side, Piece)], squares: &[u8]) -> usize {
+    pieces
+        .iter()
+        .zip(squares)
+        .filter(|((_, piece), _)| *piece == Piece::Pawn)
+        .map(|((side, _), square)| match side {
+            Side::White => (square >> 3) as usize,
+            Side::Black => 7 - (square >> 3) as usize,
+        })
+        .sum()
+}
+
+/// Retrograde analysis of the positions at `indices`, which only reach each other by
+/// moves that don't zero the fifty move counter. A position is won when it can zero
+/// into a position won by at least `threshold` or mate, and lost when every move does
+/// the opposite; with `limit`, only when that happens within `limit` plies.
+fn solve(
+    layout: &Layout,
+    nodes: &[Vec<Node>; 2],
+    indices: &[usize],
+    threshold: i8,
+    limit: Option<u16>,
+    outcomes: &mut Outcomes,
+) {
+    let pieces = layout.pieces();
+    let count = pieces.len();
+    let mut frontier = Vec::new();
+    let mut next = Vec::new();
+
+    for side in [Side::White, Side::Black] {
+        let active = side as usize;
+        for index in indices.iter().copied() {
+            let node = nodes[active][index];
+            outcomes.outcome[active][index] = 0;
+            outcomes.counters[active][index] = NOT_LOSING;
+            if !node.legal {
+                continue;
+            }
+
+            let losing = node.best_exit.is_none_or(|exit| exit <= -threshold);
+            if node.mated {
+                outcomes.outcome[active][index] = -1;
+                outcomes.plies[active][index] = 0;
+                frontier.push((side, index));
+            } else if node.best_exit.is_some_and(|exit| exit >= threshold) {
+                outcomes.outcome[active][index] = 1;
+                outcomes.plies[active][index] = 1;
+                next.push((side, index));
+            } else if losing && node.successors == 0 && node.best_exit.is_some() {
+                // every move zeroes into a lost position
+                outcomes.outcome[active][index] = -1;
+                outcomes.plies[active][index] = 1;
+                next.push((side, index));
+            } else if losing {
+                outcomes.counters[active][index] = node.successors;
+            }
+        }
+    }
+
+    let mut squares = [0; MAX_PIECES];
+    let mut predecessors = Vec::new();
+    let mut plies = 0;
+    while !frontier.is_empty() || !next.is_empty() {
+        let reachable = limit.is_none_or(|limit| plies < limit);
+        for (side, index) in frontier.drain(..) {
+            if !reachable {
+                break;
+            }
+            let (active, previous) = (side as usize, side.opposite() as usize);
+            let lost = outcomes.outcome[active][index] < 0;
+
+            layout.decode(index, &mut squares);
+            predecessors.clear();
+            for_each_unmove(
+                pieces,
+                &squares[..count],
+                side.opposite(),
+                false,
+                |before| predecessors.push(layout.encode(before)),
+            );
+            predecessors.sort_unstable();
+            predecessors.dedup();
+
+            for before in predecessors.iter().copied() {
+                if !nodes[previous][before].legal || outcomes.outcome[previous][before] != 0 {
+                    continue;
+                }
+                if lost {
+                    outcomes.outcome[previous][before] = 1;
+                } else {
+                    let counter = &mut outcomes.counters[previous][before];
+                    if *counter == NOT_LOSING {
+                        continue;
+                    }
+                    *counter -= 1;
+                    if *counter > 0 {
+                        continue;
+                    }
+                    outcomes.outcome[previous][before] = -1;
+                }
+                outcomes.plies[previous][before] = plies + 1;
+                next.push((side.opposite(), before));
+            }
+        }
+
+        std::mem::swap(&mut frontier, &mut next);
+        plies += 1;
+    }
+}
+
+/// Builds the table for `material` by retrograde analysis. Positions are solved from the
+/// most advanced pawns back, so that pawn moves, like captures and promotions, lead to
+/// positions whose value is known. Within the fifty move rule a position is won if it
+/// can capture, move a pawn or mate within 100 plies and reach a won position that way.
+///
+/// # Panics
+///
+/// If `tables` doesn't have the tables that captures and promotions lead to, see
+/// `Material::dependencies`.
+pub fn generate_dtz(material: &Material, tables: &DtzTables) -> DtzTable {
+    let layout = Layout::new(material);
+    let pieces = layout.pieces().to_vec();
+    let count = pieces.len();
+    let size = layout.size();
+
+    let mut levels = vec![Vec::new(); 7 * (MAX_PIECES - 2) + 1];
+    let mut squares = [0; MAX_PIECES];
+    for index in 0..size {
+        layout.decode(index, &mut squares);
+        let squares = &squares[..count];
+        if layout.encode(squares) == index {
+            levels[advancement(&pieces, squares)].push(index);
+        }
+    }
+
+    let illegal = Node {
+        legal: false,
+        mated: false,
+        best_exit: None,
+        best_capture: None,
+        only_captures: false,
+        successors: 0,
+    };
+    let mut nodes = [vec![illegal; size], vec![illegal; size]];
+    let mut values = [vec![Dtz::ILLEGAL; size], vec![Dtz::ILLEGAL; size]];
+    let mut outcomes = Outcomes {
+        outcome: [vec![0; size], vec![0; size]],
+        plies: [vec![0; size], vec![0; size]],
+        counters: [vec![0; size], vec![0; size]],
+    };
+    let mut successors = Vec::new();
+
+    for indices in levels.iter().rev().filter(|indices| !indices.is_empty()) {
+        for side in [Side::White, Side::Black] {
+            let (active, opponent) = (side as usize, side.opposite() as usize);
+            for index in indices.iter().copied() {
+                layout.decode(index, &mut squares);
+                let squares = &squares[..count];
+                if !is_legal(&pieces, squares, side) {
+                    continue;
+                }
+
+                successors.clear();
+                let mut has_moves = false;
+                let mut best_exit: Option<i8> = None;
+                let mut best_capture: Option<i8> = None;
+                let mut only_captures = true;
+                for_each_move(&pieces, squares, side, |successor| {
+                    has_moves = true;
+                    let (value, capture) = match successor {
+                        Successor::Moved(next) => {
+                            successors.push(layout.encode(next));
+                            only_captures = false;
+                            return;
+                        }
+                        Successor::Pushed(next) => (values[opponent][layout.encode(next)], false),
+                        Successor::Converted(position) => {
+                            let value = tables.probe_position(position).unwrap_or_else(|| {
+                                panic!("missing table {}", position.material().canonical().0.name())
+                            });
+                            (value, position.pieces().len() < count)
+                        }
+                    };
+                    let better = |best: Option<i8>| {
+                        Some(best.map_or(-value.wdl, |best| best.max(-value.wdl)))
+                    };
+                    best_exit = better(best_exit);
+                    if capture {
+                        best_capture = better(best_capture);
+                    } else {
+                        only_captures = false;
+                    }
+                });
+                successors.sort_unstable();
+                successors.dedup();
+
+                nodes[active][index] = Node {
+                    legal: true,
+                    mated: !has_moves
+                        && is_attacked(&pieces, squares, None, squares[active], side.opposite()),
+                    best_exit,
+                    best_capture,
+                    only_captures: has_moves && only_captures,
+                    successors: successors.len() as u16,
+                };
+            }
+        }
+
+        // Ignoring the fifty move rule first, then the wins and losses within it.
+        for (threshold, limit) in [(1, None), (2, Some(FIFTY_MOVE_PLIES))] {
+            solve(&layout, &nodes, indices, threshold, limit, &mut outcomes);
+            for side in [Side::White, Side::Black] {
+                let active = side as usize;
+                for index in indices.iter().copied() {
+                    let node = nodes[active][index];
+                    if !node.legal {
+                        continue;
+                    }
+                    let outcome = outcomes.outcome[active][index];
+                    if threshold == 1 {
+                        values[active][index] = Dtz {
+                            capture: node.best_capture,
+                            zeroing: node.best_exit,
+                            only_captures: node.only_captures,
+                            only_zeroing: node.best_exit.is_some() && node.successors == 0,
+                            ..Dtz::DRAW
+                        };
+                    }
+                    if outcome != 0 {
+                        let value = &mut values[active][index];
+                        value.wdl = outcome * threshold;
+                        value.plies = outcomes.plies[active][index];
+                    }
+                }
+            }
+        }
+    }
+
+    DtzTable {
+        material: material.clone(),
+        layout,
+        values,
+    }
+}
@@ -0,0 +1,369 @@
+use super::index::Layout;
+use super::material::Material;
+use super::table::Table;
+use super::{Dtm, Position, Tablebase, MAX_PIECES};
+use crate::chess::board::{Piece, Square};
+use crate::chess::game::Side;
+use crate::chess::move_tables;
+use crate::utils::bitboard::Bitboard;
+
+const PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
+/// Counter of an illegal position, or of an index that duplicates another by symmetry.
+const ILLEGAL: u8 = u8::MAX;
+/// Counter of a position that can't be lost, or whose value is already known.
+const SAFE: u8 = u8::MAX - 1;
+
+/// Where a move from a position of the table leads.
+pub(super) enum Successor<'a> {
+    /// Another position of the same table, as squares in layout order.
+    Moved(&'a [u8]),
+    /// The same, by a pawn move, which resets the fifty move counter.
+    Pushed(&'a [u8]),
+    /// A capture or promotion, which changes the material.
+    Converted(&'a Position),
+}
+
+fn attacks(side: Side, piece: Piece, square: u8, occupied: Bitboard) -> Bitboard {
+    let square = Square::new(square);
+    match piece {
+        Piece::Pawn => move_tables::pawn_attack_board(side, square),
+        Piece::Knight => move_tables::attack_table_knight(occupied, square),
+        Piece::Bishop => move_tables::attack_table_bishop(occupied, square),
+        Piece::Rook => move_tables::attack_table_rook(occupied, square),
+        Piece::Queen => move_tables::attack_table_queen(occupied, square),
+        Piece::King => move_tables::attack_table_king(occupied, square),
+    }
+}
+
+fn occupancy(squares: &[u8], captured: Option<usize>) -> Bitboard {
+    let mut occupied = Bitboard::new();
+    for (slot, square) in squares.iter().enumerate() {
+        if Some(slot) != captured {
+            occupied.set(Square::new(*square));
+        }
+    }
+    occupied
+}
+
+pub(super) fn is_attacked(
+    pieces: &[(Side, Piece)],
+    squares: &[u8],
+    captured: Option<usize>,
+    target: u8,
+    by: Side,
+) -> bool {
+    let occupied = occupancy(squares, captured);
+    pieces
+        .iter()
+        .zip(squares)
+        .enumerate()
+        .any(|(slot, ((side, piece), square))| {
+            Some(slot) != captured
+                && *side == by
+                && attacks(*side, *piece, *square, occupied).is_set(Square::new(target))
+        })
+}
+
+fn is_back_rank(square: u8) -> bool {
+    square >> 3 == 0 || square >> 3 == 7
+}
+
+/// No two pieces on a square, no pawns on the first or last rank, and the side that
+/// just moved not in check.
+pub(super) fn is_legal(pieces: &[(Side, Piece)], squares: &[u8], active: Side) -> bool {
+    if occupancy(squares, None).count() != squares.len() {
+        return false;
+    }
+    let pawn_on_back_rank = pieces
+        .iter()
+        .zip(squares)
+        .any(|((_, piece), square)| *piece == Piece::Pawn && is_back_rank(*square));
+    if pawn_on_back_rank {
+        return false;
+    }
+
+    let king = squares[active.opposite() as usize];
+    !is_attacked(pieces, squares, None, king, active)
+}
+
+/// Calls `f` with the result of each legal move of `active`.
+pub(super) fn for_each_move(
+    pieces: &[(Side, Piece)],
+    squares: &[u8],
+    active: Side,
+    mut f: impl FnMut(Successor),
+) {
+    let occupied = occupancy(squares, None);
+    let mut own = Bitboard::new();
+    for ((side, _), square) in pieces.iter().zip(squares) {
+        if *side == active {
+            own.set(Square::new(*square));
+        }
+    }
+
+    for (slot, (side, piece)) in pieces.iter().enumerate() {
+        if *side != active {
+            continue;
+        }
+        let from = squares[slot];
+        let targets = if *piece == Piece::Pawn {
+            let from_square = Square::new(from);
+            let mut targets = attacks(active, *piece, from, occupied) & occupied & !own;
+            let push = move_tables::pawn_move_board(active, from_square) & !occupied;
+            if !push.is_empty() {
+                targets |=
+                    push | (move_tables::pawn_double_move_board(active, from_square) & !occupied);
+            }
+            targets
+        } else {
+            attacks(active, *piece, from, occupied) & !own
+        };
+
+        for to in targets {
+            let to = to.index();
+            let captured = squares.iter().position(|square| *square == to);
+            let mut moved = [0; MAX_PIECES];
+            moved[..squares.len()].copy_from_slice(squares);
+            moved[slot] = to;
+            let moved = &moved[..squares.len()];
+
+            let king = moved[active as usize];
+            if is_attacked(pieces, moved, captured, king, active.opposite()) {
+                continue;
+            }
+
+            let promotes = *piece == Piece::Pawn && is_back_rank(to);
+            if captured.is_none() && !promotes {
+                f(if *piece == Piece::Pawn {
+                    Successor::Pushed(moved)
+                } else {
+                    Successor::Moved(moved)
+                });
+                continue;
+            }
+
+            let promotions: &[Piece] = if promotes {
+                &PROMOTION_PIECES
+            } else {
+                std::slice::from_ref(piece)
+            };
+            for promotion in promotions {
+                let mut position = Position::new(active.opposite());
+                for (other, (other_side, other_piece)) in pieces.iter().enumerate() {
+                    if Some(other) == captured {
+                        continue;
+                    }
+                    let other_piece = if other == slot {
+                        *promotion
+                    } else {
+                        *other_piece
+                    };
+                    position.add(*other_side, other_piece, moved[other]);
+                }
+                f(Successor::Converted(&position));
+            }
+        }
+    }
+}
+
+/// Calls `f` with each position from which `mover` reaches this one by a move that
+/// neither captures nor promotes, leaving out pawn moves without `pawns`. Some of them
+/// are illegal.
+pub(super) fn for_each_unmove(
+    pieces: &[(Side, Piece)],
+    squares: &[u8],
+    mover: Side,
+    pawns: bool,
+    mut f: impl FnMut(&[u8]),
+) {
+    let occupied = occupancy(squares, None);
+    for (slot, (side, piece)) in pieces.iter().enumerate() {
+        if *side != mover || (*piece == Piece::Pawn && !pawns) {
+            continue;
+        }
+        let to = squares[slot];
+        let origins = if *piece == Piece::Pawn {
+            // back towards the pawn's own side, two squares from the 4th rank
+            let (behind, double_rank) = if mover == Side::White {
+                (to.wrapping_sub(8), 3)
+            } else {
+                (to + 8, 4)
+            };
+            let mut origins = Bitboard::new();
+            if !is_back_rank(behind) && !occupied.is_set(Square::new(behind)) {
+                origins.set(Square::new(behind));
+                let start = if mover == Side::White {
+                    behind.wrapping_sub(8)
+                } else {
+                    behind + 8
+                };
+                if to >> 3 == double_rank && !occupied.is_set(Square::new(start)) {
+                    origins.set(Square::new(start));
+                }
+            }
+            origins
+        } else {
+            attacks(mover, *piece, to, occupied) & !occupied
+        };
+
+        for from in origins {
+            let mut previous = [0; MAX_PIECES];
+            previous[..squares.len()].copy_from_slice(squares);
+            previous[slot] = from.index();
+            f(&previous[..squares.len()]);
+        }
+    }
+}
+
+fn plies(value: Dtm) -> u32 {
+    match value {
+        Dtm::Win(plies) | Dtm::Loss(plies) => plies,
+        Dtm::Draw => 0,
+    }
+}
+
+/// Builds the table for `material` by retrograde analysis: mates first, then every
+/// position that can move into a lost one is won a ply later, and every position whose
+/// moves all reach won ones is lost.
+///
+/// # Panics
+///
+/// If `tablebase` doesn't have the tables that captures and promotions lead to, see
+/// `Material::dependencies`.
+pub fn generate(material: &Material, tablebase: &Tablebase) -> Table {
+    let layout = Layout::new(material);
+    let pieces = layout.pieces();
+    let count = pieces.len();
+    let size = layout.size();
+
+    // Values as in `Dtm::to_byte`, counters are the number of distinct positions a
+    // position can move to that aren't known to be won for the opponent yet.
+    let mut values = [vec![0; size], vec![0; size]];
+    let mut counters = [vec![ILLEGAL; size], vec![ILLEGAL; size]];
+    // the slowest loss through captures and promotions
+    let mut exit_losses = [vec![0; size], vec![0; size]];
+    let mut horizon = 0;
+    let mut squares = [0; MAX_PIECES];
+    let mut successors = Vec::new();
+
+    for side in [Side::White, Side::Black] {
+        let active = side as usize;
+        for index in 0..size {
+            layout.decode(index, &mut squares);
+            let squares = &squares[..count];
+            if layout.encode(squares) != index || !is_legal(pieces, squares, side) {
+                continue;
+            }
+
+            successors.clear();
+            let mut has_moves = false;
+            let mut win: Option<u32> = None;
+            let mut drawn_exit = false;
+            let mut exit_loss = 0;
+            for_each_move(pieces, squares, side, |successor| {
+                has_moves = true;
+                match successor {
+                    Successor::Moved(next) | Successor::Pushed(next) => {
+                        successors.push(layout.encode(next))
+                    }
+                    Successor::Converted(position) => {
+                        let value = tablebase.probe_position(position).unwrap_or_else(|| {
+                            panic!("missing table {}", position.material().canonical().0.name())
+                        });
+                        match value.parent() {
+                            Dtm::Win(plies) => win = Some(win.map_or(plies, |win| win.min(plies))),
+                            Dtm::Draw => drawn_exit = true,
+                            Dtm::Loss(plies) => exit_loss = exit_loss.max(plies),
+                        }
+                    }
+                }
+            });
+            successors.sort_unstable();
+            successors.dedup();
+
+            let value = if !has_moves {
+                if is_attacked(pieces, squares, None, squares[active], side.opposite()) {
+                    Dtm::Loss(0)
+                } else {
+                    Dtm::Draw
+                }
+            } else if let Some(plies) = win {
+                Dtm::Win(plies)
+            } else if drawn_exit {
+                Dtm::Draw
+            } else if successors.is_empty() {
+                Dtm::Loss(exit_loss)
+            } else {
+                counters[active][index] = successors.len() as u8;
+                exit_losses[active][index] = exit_loss as u8;
+                continue;
+            };
+            counters[active][index] = SAFE;
+            values[active][index] = value.to_byte();
+            horizon = horizon.max(plies(value));
+        }
+    }
+
+    let mut predecessors = Vec::new();
+    let mut ply = 0;
+    while ply <= horizon {
+        // positions lost in `ply` plies at even plies, won at odd ones
+        let decided = if ply % 2 == 0 {
+            Dtm::Loss(ply)
+        } else {
+            Dtm::Win(ply)
+        };
+        let decided = decided.to_byte();
+
+        for side in [Side::White, Side::Black] {
+            let (active, previous) = (side as usize, side.opposite() as usize);
+            for index in 0..size {
+                if values[active][index] != decided || counters[active][index] == ILLEGAL {
+                    continue;
+                }
+
+                layout.decode(index, &mut squares);
+                predecessors.clear();
+                for_each_unmove(pieces, &squares[..count], side.opposite(), true, |before| {
+                    predecessors.push(layout.encode(before))
+                });
+                predecessors.sort_unstable();
+                predecessors.dedup();
+
+                for before in predecessors.iter().copied() {
+                    if counters[previous][before] == ILLEGAL {
+                        continue;
+                    }
+                    let value = Dtm::from_byte(values[previous][before]);
+                    if ply % 2 == 0 {
+                        // moving here wins, unless a capture already wins quicker
+                        let quicker = match value {
+                            Dtm::Draw => true,
+                            Dtm::Win(plies) => plies > ply + 1,
+                            Dtm::Loss(_) => false,
+                        };
+                        if quicker {
+                            values[previous][before] = Dtm::Win(ply + 1).to_byte();
+                            counters[previous][before] = SAFE;
+                            horizon = horizon.max(ply + 1);
+                        }
+                    } else if value == Dtm::Draw && counters[previous][before] < SAFE {
+                        counters[previous][before] -= 1;
+                        if counters[previous][before] == 0 {
+                            let plies = (ply + 1).max(exit_losses[previous][before] as u32);
+                            values[previous][before] = Dtm::Loss(plies).to_byte();
+                            counters[previous][before] = SAFE;
+                            horizon = horizon.max(plies);
+                        }
+                    }
+                }
+            }
+        }
+
+        ply += 1;
+    }
+
+    let [white, black] = values;
+    Table::new(material.clone(), [white, black])
+}
@@ -0,0 +1,133 @@
+use std::ops::Range;
+
+use super::material::Material;
+use super::MAX_PIECES;
+use crate::chess::board::Piece;
+use crate::chess::game::Side;
+
+/// The white king's squares without pawns, the a1-d1-d4 triangle. Every position can be
+/// rotated or mirrored to put it there.
+const TRIANGLE: [u8; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+
+/// Maps `square` by one of the eight symmetries of the board: bit 2 mirrors it in the
+/// a1-h8 diagonal, bit 0 in the d/e line and bit 1 in the 4th/5th rank line.
+fn transform(square: u8, symmetry: u8) -> u8 {
+    let mut square = square;
+    if symmetry & 4 != 0 {
+        square = (square >> 3) | ((square & 7) << 3);
+    }
+    if symmetry & 1 != 0 {
+        square ^= 7;
+    }
+    if symmetry & 2 != 0 {
+        square ^= 56;
+    }
+    square
+}
+
+/// How the positions of one table are numbered: the white king's square in the reduced
+/// region, then a square for each other piece, 64 apiece.
+pub struct Layout {
+    /// The piece each square of a position stands for: both kings, then white's and
+    /// black's other pieces.
+    pieces: Vec<(Side, Piece)>,
+    /// Runs of identical pieces, which are interchangeable.
+    groups: Vec<Range<usize>>,
+    has_pawns: bool,
+    size: usize,
+}
+
+impl Layout {
+    pub fn new(material: &Material) -> Self {
+        let mut pieces = vec![(Side::White, Piece::King), (Side::Black, Piece::King)];
+        for side in [Side::White, Side::Black] {
+            pieces.extend(material.pieces(side).iter().map(|piece| (side, *piece)));
+        }
+        assert!(pieces.len() <= MAX_PIECES, "too many pieces for a table");
+
+        let mut groups = Vec::new();
+        let mut start = 2;
+        for end in 3..=pieces.len() {
+            if end == pieces.len() || pieces[end] != pieces[start] {
+                if end - start > 1 {
+                    groups.push(start..end);
+                }
+                start = end;
+            }
+        }
+
+        let has_pawns = material.has_pawns();
+        let king_squares = if has_pawns { 32 } else { TRIANGLE.len() };
+        Self {
+            size: king_squares * 64_usize.pow(pieces.len() as u32 - 1),
+            pieces,
+            groups,
+            has_pawns,
+        }
+    }
+
+    pub fn pieces(&self) -> &[(Side, Piece)] {
+        &self.pieces
+    }
+
+    /// Positions per side to move, including the illegal and duplicate ones.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn king_index(&self, square: u8) -> Option<usize> {
+        let (file, rank) = (square & 7, square >> 3);
+        if self.has_pawns {
+            (file < 4).then_some((rank * 4 + file) as usize)
+        } else {
+            TRIANGLE.iter().position(|triangle| *triangle == square)
+        }
+    }
+
+    fn king_square(&self, index: usize) -> u8 {
+        if self.has_pawns {
+            ((index / 4) * 8 + index % 4) as u8
+        } else {
+            TRIANGLE[index]
+        }
+    }
+
+    /// The index of a position, given the square of each piece in `pieces()` order. All
+    /// the symmetric copies of a position get the same one.
+    pub fn encode(&self, squares: &[u8]) -> usize {
+        // pawns only allow the left-right mirror
+        let symmetries = if self.has_pawns { 2 } else { 8 };
+        let mut mapped = [0; MAX_PIECES];
+        let mut best = usize::MAX;
+
+        for symmetry in 0..symmetries {
+            let Some(king) = self.king_index(transform(squares[0], symmetry)) else {
+                continue;
+            };
+            for (mapped, square) in mapped.iter_mut().zip(squares) {
+                *mapped = transform(*square, symmetry);
+            }
+            for group in &self.groups {
+                mapped[group.clone()].sort_unstable();
+            }
+
+            let index = mapped[1..self.pieces.len()]
+                .iter()
+                .fold(king, |index, square| index * 64 + *square as usize);
+            best = best.min(index);
+        }
+
+        best
+    }
+
+    /// The squares of the position with this index, the inverse of `encode` for
+    /// canonical indices.
+    pub fn decode(&self, index: usize, squares: &mut [u8]) {
+        let mut index = index;
+        for square in squares[1..self.pieces.len()].iter_mut().rev() {
+            *square = (index % 64) as u8;
+            index /= 64;
+        }
+        squares[0] = self.king_square(index);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::chess::board::Piece;
+use crate::chess::game::Side;
+
+const PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
+/// The pieces besides the kings, named like `KRKP`: white's pieces after the first K,
+/// black's after the second.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Material {
+    /// Each side's pieces, strongest first.
+    pieces: [Vec<Piece>; 2],
+}
+
+fn piece_value(piece: Piece) -> u32 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight | Piece::Bishop => 3,
+        Piece::Rook => 5,
+        Piece::Queen => 9,
+        Piece::King => 0,
+    }
+}
+
+impl Material {
+    pub fn new(mut white: Vec<Piece>, mut black: Vec<Piece>) -> Self {
+        white.sort_by_key(|piece| std::cmp::Reverse(*piece as u8));
+        black.sort_by_key(|piece| std::cmp::Reverse(*piece as u8));
+        Self {
+            pieces: [white, black],
+        }
+    }
+
+    /// Parses a name like `KQK` or `kbnk`. Returns `None` for anything else, including
+    /// extra kings.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_uppercase();
+        let (white, black) = name.strip_prefix('K')?.split_once('K')?;
+        let parse = |text: &str| -> Option<Vec<Piece>> {
+            text.chars()
+                .map(|piece_char| Piece::from_char(piece_char).filter(|p| *p != Piece::King))
+                .collect()
+        };
+
+        Some(Self::new(parse(white)?, parse(black)?))
+    }
+
+    pub fn name(&self) -> String {
+        let letters = |pieces: &[Piece]| -> String {
+            pieces
+                .iter()
+                .map(|piece| piece.to_char().to_ascii_uppercase())
+                .collect()
+        };
+        format!(
+            "K{}K{}",
+            letters(&self.pieces[Side::White as usize]),
+            letters(&self.pieces[Side::Black as usize])
+        )
+    }
+
+    pub fn pieces(&self, side: Side) -> &[Piece] {
+        &self.pieces[side as usize]
+    }
+
+    /// Pieces on the board, kings included.
+    pub fn piece_count(&self) -> usize {
+        2 + self.pieces[0].len() + self.pieces[1].len()
+    }
+
+    pub fn has_pawns(&self) -> bool {
+        self.pieces
+            .iter()
+            .flatten()
+            .any(|piece| *piece == Piece::Pawn)
+    }
+
+    /// Just the two kings, which is always a draw and needs no table.
+    pub fn is_bare_kings(&self) -> bool {
+        self.piece_count() == 2
+    }
+
+    /// Tables are only stored with the stronger side as white: more material, then more
+    /// pieces, then the stronger pieces.
+    pub fn is_canonical(&self) -> bool {
+        let strength = |pieces: &[Piece]| {
+            let total: u32 = pieces.iter().map(|piece| piece_value(*piece)).sum();
+            let order: Vec<u8> = pieces.iter().map(|piece| *piece as u8).collect();
+            (total, pieces.len(), order)
+        };
+        strength(&self.pieces[0]) >= strength(&self.pieces[1])
+    }
+
+    /// The same material with the colors swapped.
+    pub fn flipped(&self) -> Self {
+        Self {
+            pieces: [self.pieces[1].clone(), self.pieces[0].clone()],
+        }
+    }
+
+    /// The material of the table that holds these positions, and whether the colors
+    /// have to be swapped to look them up.
+    pub fn canonical(&self) -> (Self, bool) {
+        if self.is_canonical() {
+            (self.clone(), false)
+        } else {
+            (self.flipped(), true)
+        }
+    }
+
+    fn replaced(&self, side: Side, piece: Piece, by: Option<Piece>) -> Self {
+        let mut pieces = self.pieces.clone();
+        let own = &mut pieces[side as usize];
+        if let Some(position) = own.iter().position(|own_piece| *own_piece == piece) {
+            own.remove(position);
+        }
+        own.extend(by);
+        let [white, black] = pieces;
+        Self::new(white, black)
+    }
+
+    /// The tables the positions of this one can capture or promote into, in canonical
+    /// form and without bare kings.
+    pub fn dependencies(&self) -> Vec<Self> {
+        let mut dependencies = Vec::new();
+        for side in [Side::White, Side::Black] {
+            for piece in self.pieces(side) {
+                dependencies.push(self.replaced(side, *piece, None));
+                if *piece == Piece::Pawn {
+                    for promotion in PROMOTION_PIECES {
+                        let promoted = self.replaced(side, *piece, Some(promotion));
+                        // promoting with a capture
+                        for captured in self.pieces(side.opposite()) {
+                            dependencies.push(promoted.replaced(side.opposite(), *captured, None));
+                        }
+                        dependencies.push(promoted);
+                    }
+                }
+            }
+        }
+
+        let mut result: Vec<Self> = Vec::new();
+        for dependency in dependencies {
+            let (dependency, _) = dependency.canonical();
+            if !dependency.is_bare_kings() && !result.contains(&dependency) {
+                result.push(dependency);
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,384 @@
+pub mod dtz;
+pub mod generate;
+pub mod index;
+pub mod material;
+pub mod table;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::chess::board::Piece;
+use crate::chess::game::{Game, Side};
+use crate::chess::move_tables;
+use crate::chess::moves::{generate_legal_moves, Move};
+
+use index::Layout;
+use material::Material;
+use table::{Table, EXTENSION};
+
+/// Most pieces, kings included, that the generator handles.
+pub const MAX_PIECES: usize = 4;
+
+/// A position's value with perfect play, counted in plies until mate.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dtm {
+    Win(u32),
+    Draw,
+    Loss(u32),
+}
+
+impl Dtm {
+    /// Tables store draws as 0, wins in `n` plies as `n` (always odd) and losses in `n`
+    /// plies as `n + 2` (always even).
+    pub fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => Dtm::Draw,
+            _ if byte % 2 == 1 => Dtm::Win(byte as u32),
+            _ => Dtm::Loss(byte as u32 - 2),
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        let byte = match self {
+            Dtm::Win(plies) => plies,
+            Dtm::Draw => 0,
+            Dtm::Loss(plies) => plies + 2,
+        };
+        assert!(byte <= u8::MAX as u32, "mate too far away for a table");
+        byte as u8
+    }
+
+    /// The value for the side that played into a position with this value.
+    pub fn parent(self) -> Self {
+        match self {
+            Dtm::Win(plies) => Dtm::Loss(plies + 1),
+            Dtm::Draw => Dtm::Draw,
+            Dtm::Loss(plies) => Dtm::Win(plies + 1),
+        }
+    }
+
+    /// Higher is better for the side to move: quick wins, then draws, then slow losses.
+    pub fn preference(self) -> i64 {
+        match self {
+            Dtm::Win(plies) => 1000 - plies as i64,
+            Dtm::Draw => 0,
+            Dtm::Loss(plies) => plies as i64 - 1000,
+        }
+    }
+}
+
+/// A position as the tables see it: where each piece is and who is to move.
+#[derive(Clone, Copy)]
+pub struct Position {
+    pieces: [(Side, Piece, u8); MAX_PIECES],
+    count: usize,
+    active: Side,
+}
+
+impl Position {
+    pub fn new(active: Side) -> Self {
+        Self {
+            pieces: [(Side::White, Piece::King, 0); MAX_PIECES],
+            count: 0,
+            active,
+        }
+    }
+
+    /// Adds a piece, or returns `false` if the position already has `MAX_PIECES`.
+    pub fn add(&mut self, side: Side, piece: Piece, square: u8) -> bool {
+        if self.count == MAX_PIECES {
+            return false;
+        }
+        self.pieces[self.count] = (side, piece, square);
+        self.count += 1;
+        true
+    }
+
+    /// The pieces of `game`, or `None` if there are too many for any table.
+    pub fn from_game(game: &Game) -> Option<Self> {
+        let mut position = Self::new(game.active());
+        for side in [Side::White, Side::Black] {
+            for piece in Piece::ALL {
+                for square in game.board().side_pieces(side).pieces(piece) {
+                    if !position.add(side, piece, square.index()) {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(position)
+    }
+
+    pub fn pieces(&self) -> &[(Side, Piece, u8)] {
+        &self.pieces[..self.count]
+    }
+
+    pub fn active(&self) -> Side {
+        self.active
+    }
+
+    pub fn material(&self) -> Material {
+        let side_pieces = |side: Side| -> Vec<Piece> {
+            self.pieces()
+                .iter()
+                .filter(|(piece_side, piece, _)| *piece_side == side && *piece != Piece::King)
+                .map(|(_, piece, _)| *piece)
+                .collect()
+        };
+        Material::new(side_pieces(Side::White), side_pieces(Side::Black))
+    }
+}
+
+/// Where a table laid out by `layout` stores `position`: the side to move and the
+/// index. Tables only have the stronger side as white, so `flipped` swaps the colors.
+fn locate(layout: &Layout, position: &Position, flipped: bool) -> Option<(Side, usize)> {
+    let side = |side: Side| if flipped { side.opposite() } else { side };
+    let mut squares = [0; MAX_PIECES];
+    let mut used = [false; MAX_PIECES];
+    for (slot, (slot_side, slot_piece)) in layout.pieces().iter().enumerate() {
+        let found = position
+            .pieces()
+            .iter()
+            .enumerate()
+            .position(|(i, piece)| {
+                !used[i] && side(piece.0) == *slot_side && piece.1 == *slot_piece
+            })?;
+        used[found] = true;
+        let square = position.pieces()[found].2;
+        squares[slot] = if flipped { square ^ 56 } else { square };
+    }
+
+    Some((side(position.active()), layout.encode(&squares)))
+}
+
+/// Tables generated by `generate` or read from disk, by material.
+#[derive(Default)]
+pub struct Tablebase {
+    tables: HashMap<String, Table>,
+}
+
+impl Tablebase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads every table in `directory`.
+    pub fn open(directory: &Path) -> io::Result<Self> {
+        let mut tablebase = Self::new();
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) == Some(EXTENSION) {
+                tablebase.insert(Table::read(&path)?);
+            }
+        }
+        Ok(tablebase)
+    }
+
+    pub fn insert(&mut self, table: Table) {
+        self.tables.insert(table.material().name(), table);
+    }
+
+    pub fn get(&self, material: &Material) -> Option<&Table> {
+        self.tables.get(&material.canonical().0.name())
+    }
+
+    /// Whether positions with this material can be probed. Bare kings always can.
+    pub fn contains(&self, material: &Material) -> bool {
+        material.is_bare_kings() || self.get(material).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// The value of a legal position, or `None` without a table for its material.
+    pub fn probe_position(&self, position: &Position) -> Option<Dtm> {
+        let (material, flipped) = position.material().canonical();
+        if material.is_bare_kings() {
+            return Some(Dtm::Draw);
+        }
+        let table = self.tables.get(&material.name())?;
+        let (active, index) = locate(table.layout(), position, flipped)?;
+        Some(table.value(active, index))
+    }
+
+    /// The value of `game`, or `None` if it isn't in the tables. The tables know
+    /// nothing about castling or en passant, so positions where either is possible
+    /// aren't either.
+    pub fn probe(&self, game: &Game) -> Option<Dtm> {
+        let (white_short, white_long) = game.castle_rights(Side::White);
+        let (black_short, black_long) = game.castle_rights(Side::Black);
+        if white_short || white_long || black_short || black_long {
+            return None;
+        }
+        if let Some(ep_square) = game.ep_square() {
+            let capturers = move_tables::pawn_attack_board(game.active().opposite(), ep_square)
+                & game.board().side_pieces(game.active()).pawns();
+            if !capturers.is_empty() {
+                return None;
+            }
+        }
+
+        self.probe_position(&Position::from_game(game)?)
+    }
+
+    /// The move that mates quickest, or holds the draw, or loses slowest, along with
+    /// the value of the position. `None` if the game is over or not in the tables.
+    pub fn best_move(&self, game: &mut Game) -> Option<(Move, Dtm)> {
+        let mut best: Option<(Move, Dtm)> = None;
+        for mv in generate_legal_moves(game) {
+            game.make_move(mv);
+            let value = self.probe(game).map(Dtm::parent);
+            game.unmake_move();
+
+            let value = value?;
+            if best.is_none_or(|(_, best)| value.preference() > best.preference()) {
+                best = Some((mv, value));
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dtz::{generate_dtz, DtzTables};
+    use super::generate::generate;
+    use super::material::Material;
+    use super::table::Table;
+    use super::{Dtm, Position, Tablebase};
+    use crate::chess::game::{Game, Side};
+
+    fn tablebase(names: &[&str]) -> Tablebase {
+        let mut tablebase = Tablebase::new();
+        for name in names {
+            let material = Material::from_name(name).unwrap();
+            let table = generate(&material, &tablebase);
+            tablebase.insert(table);
+        }
+        tablebase
+    }
+
+    fn game(fen: &str) -> Game {
+        Game::from_fen(fen.to_string())
+    }
+
+    #[test]
+    fn longest_mates() {
+        let tablebase = tablebase(&["KQK", "KRK"]);
+        let longest = |name: &str| {
+            let table = tablebase.get(&Material::from_name(name).unwrap()).unwrap();
+            (
+                table.longest_win(Side::White),
+                table.longest_win(Side::Black),
+            )
+        };
+
+        assert_eq!(longest("KQK"), (Some(19), None));
+        assert_eq!(longest("KRK"), (Some(31), None));
+    }
+
+    #[test]
+    fn best_moves_mate_in_time() {
+        let tablebase = tablebase(&["KRK"]);
+        let mut game = game("8/8/8/4k3/8/8/8/KR6 w - - 0 1");
+        let Some(Dtm::Win(plies)) = tablebase.probe(&game) else {
+            panic!("KRK should be won");
+        };
+
+        for _ in 0..plies {
+            let (mv, _) = tablebase.best_move(&mut game).expect("not mated yet");
+            game.make_move(mv);
+        }
+        assert_eq!(tablebase.probe(&game), Some(Dtm::Loss(0)));
+        assert!(game.is_in_check());
+    }
+
+    #[test]
+    fn colors_swap() {
+        let tablebase = tablebase(&["KQK", "KRK", "KBK", "KNK", "KPK"]);
+        for (fen, flipped) in [
+            (
+                "8/8/8/8/8/2k5/8/r3K3 w - - 0 1",
+                "R3k3/8/2K5/8/8/8/8/8 b - - 0 1",
+            ),
+            (
+                "8/8/8/8/3k4/8/4P3/4K3 b - - 0 1",
+                "4k3/4p3/8/3K4/8/8/8/8 w - - 0 1",
+            ),
+        ] {
+            let value = tablebase.probe(&game(fen));
+            assert!(value.is_some());
+            assert_eq!(value, tablebase.probe(&game(flipped)), "{}", fen);
+        }
+
+        assert_eq!(
+            tablebase.probe(&game("k7/8/8/8/8/8/P7/K7 w - - 0 1")),
+            Some(Dtm::Draw)
+        );
+        assert!(matches!(
+            tablebase.probe(&game("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1")),
+            Some(Dtm::Loss(_))
+        ));
+    }
+
+    #[test]
+    fn tables_round_trip() {
+        let tablebase = tablebase(&["KQK"]);
+        let table = tablebase.get(&Material::from_name("KQK").unwrap()).unwrap();
+        let path = std::env::temp_dir().join(format!("KQK-{}.dtm", std::process::id()));
+        table.write(&path).unwrap();
+        let read = Table::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut reread = Tablebase::new();
+        reread.insert(read);
+        for fen in [
+            "8/8/8/4k3/8/8/8/KQ6 w - - 0 1",
+            "8/8/8/4k3/8/8/8/KQ6 b - - 0 1",
+            "7k/8/6K1/8/8/8/8/Q7 w - - 0 1",
+        ] {
+            assert_eq!(tablebase.probe(&game(fen)), reread.probe(&game(fen)));
+        }
+    }
+
+    #[test]
+    fn zeroing_without_pawns_is_mating() {
+        let tablebase = tablebase(&["KQK", "KRK", "KBK", "KNK", "KPK"]);
+        let mut tables = DtzTables::new();
+        for name in ["KQK", "KRK", "KBK", "KNK", "KPK"] {
+            tables.insert(generate_dtz(&Material::from_name(name).unwrap(), &tables));
+        }
+        let longest = |name: &str| {
+            let table = tables.get(&Material::from_name(name).unwrap()).unwrap();
+            table.longest_win(Side::White)
+        };
+        assert_eq!(longest("KQK"), Some(19));
+        assert_eq!(longest("KRK"), Some(31));
+        // the pawn moves within ten plies
+        assert!(longest("KPK").unwrap() < 20);
+
+        for fen in [
+            "8/8/8/4k3/8/8/8/KQ6 w - - 0 1",
+            "8/8/8/4k3/8/8/8/KR6 b - - 0 1",
+            "8/8/8/8/8/2k5/8/r3K3 w - - 0 1",
+            "k7/8/8/8/8/8/P7/K7 w - - 0 1",
+        ] {
+            let game = game(fen);
+            let position = Position::from_game(&game).unwrap();
+            let value = tables.probe_position(&position).unwrap();
+            let expected = match tablebase.probe(&game).unwrap() {
+                Dtm::Win(plies) => (2, plies),
+                Dtm::Draw => (0, 0),
+                Dtm::Loss(plies) => (-2, plies),
+            };
+            assert_eq!((value.wdl, value.plies as u32), expected, "{}", fen);
+        }
+    }
+}
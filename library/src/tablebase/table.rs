@@ -0,0 +1,151 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use super::index::Layout;
+use super::material::Material;
+use super::{Dtm, MAX_PIECES};
+use crate::chess::game::Side;
+
+const MAGIC: &[u8; 4] = b"DTMb";
+const VERSION: u8 = 1;
+/// Extension of the table files, named after their material as in `KRKP.dtm`.
+pub const EXTENSION: &str = "dtm";
+
+/// Distance to mate for every position of one material, with either side to move.
+pub struct Table {
+    material: Material,
+    layout: Layout,
+    /// One byte per position in the format of `Dtm::to_byte`, by side to move.
+    values: [Vec<u8>; 2],
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Values as runs of `(value, length)`, with the length in LEB128. Illegal and drawn
+/// positions, which are most of them, come in long runs of zeros.
+fn write_runs(out: &mut impl Write, values: &[u8]) -> io::Result<()> {
+    let mut start = 0;
+    while start < values.len() {
+        let value = values[start];
+        let length = values[start..]
+            .iter()
+            .take_while(|other| **other == value)
+            .count();
+
+        out.write_all(&[value])?;
+        let mut rest = length as u64;
+        loop {
+            let byte = (rest & 0x7f) as u8;
+            rest >>= 7;
+            if rest == 0 {
+                out.write_all(&[byte])?;
+                break;
+            }
+            out.write_all(&[byte | 0x80])?;
+        }
+        start += length;
+    }
+
+    Ok(())
+}
+
+fn read_runs(bytes: &mut &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut next = || -> io::Result<u8> {
+        let (first, rest) = bytes
+            .split_first()
+            .ok_or_else(|| invalid_data("truncated table"))?;
+        *bytes = rest;
+        Ok(*first)
+    };
+
+    let mut values = Vec::with_capacity(size);
+    while values.len() < size {
+        let value = next()?;
+        let mut length = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = next()?;
+            length |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        if values.len() as u64 + length > size as u64 {
+            return Err(invalid_data("table is longer than its material allows"));
+        }
+        values.resize(values.len() + length as usize, value);
+    }
+
+    Ok(values)
+}
+
+impl Table {
+    pub(super) fn new(material: Material, values: [Vec<u8>; 2]) -> Self {
+        Self {
+            layout: Layout::new(&material),
+            material,
+            values,
+        }
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    pub(super) fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    pub(super) fn value(&self, active: Side, index: usize) -> Dtm {
+        Dtm::from_byte(self.values[active as usize][index])
+    }
+
+    /// The longest forced mate in plies with `side` to move, if it ever wins.
+    pub fn longest_win(&self, side: Side) -> Option<u32> {
+        self.values[side as usize]
+            .iter()
+            .filter_map(|byte| match Dtm::from_byte(*byte) {
+                Dtm::Win(plies) => Some(plies),
+                _ => None,
+            })
+            .max()
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let name = self.material.name();
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION, name.len() as u8])?;
+        out.write_all(name.as_bytes())?;
+        for values in &self.values {
+            write_runs(&mut out, values)?;
+        }
+        out.flush()
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut contents = Vec::new();
+        fs::File::open(path)?.read_to_end(&mut contents)?;
+        let mut bytes = contents.as_slice();
+
+        if bytes.len() < 6 || &bytes[..4] != MAGIC || bytes[4] != VERSION {
+            return Err(invalid_data("not a distance to mate table"));
+        }
+        let name_length = bytes[5] as usize;
+        let name = bytes
+            .get(6..6 + name_length)
+            .and_then(|name| std::str::from_utf8(name).ok())
+            .ok_or_else(|| invalid_data("truncated table"))?;
+        let material = Material::from_name(name)
+            .filter(|material| material.piece_count() <= MAX_PIECES)
+            .ok_or_else(|| invalid_data("unknown table material"))?;
+        bytes = &bytes[6 + name_length..];
+
+        let size = Layout::new(&material).size();
+        let white = read_runs(&mut bytes, size)?;
+        let black = read_runs(&mut bytes, size)?;
+        Ok(Self::new(material, [white, black]))
+    }
+}
@@ -1,19 +1,23 @@
-use std::path::Iter;
+use std::fmt;
 
 use crate::chess::board::Square;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
 pub struct Bitboard {
     data: u64,
 }
 
 impl Bitboard {
     pub fn new() -> Self {
-        return Self::from_data(0);
+        Self::from_data(0)
     }
 
     pub fn from_data(data: u64) -> Self {
-        Self { data: data }
+        Self { data }
+    }
+
+    pub fn from_square(square: Square) -> Self {
+        Self::from_data(1 << square.index())
     }
 
     pub fn data(&self) -> u64 {
@@ -24,8 +28,16 @@ impl Bitboard {
         ((self.data >> square.index()) & 1) == 1
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data == 0
+    }
+
+    pub fn count(&self) -> u32 {
+        self.data.count_ones()
+    }
+
     pub fn set(&mut self, square: Square) {
-        self.data |= (1 << square.index());
+        self.data |= 1 << square.index();
     }
 
     pub fn unset(&mut self, index: u8) {
@@ -60,6 +72,12 @@ impl std::ops::BitAnd<Bitboard> for Bitboard {
     }
 }
 
+impl std::ops::BitAndAssign<Bitboard> for Bitboard {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.data &= rhs.data();
+    }
+}
+
 impl std::ops::BitOr<Bitboard> for Bitboard {
     type Output = Self;
 
@@ -86,6 +104,12 @@ impl std::ops::BitXor<Bitboard> for Bitboard {
     }
 }
 
+impl std::ops::BitXorAssign<Bitboard> for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.data ^= rhs.data();
+    }
+}
+
 impl std::ops::Not for Bitboard {
     type Output = Self;
 
@@ -110,8 +134,8 @@ impl Iterator for Bitboard {
     }
 }
 
-impl ToString for Bitboard {
-    fn to_string(&self) -> String {
+impl fmt::Display for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut rows = Vec::new();
 
         for rank in (0..8).rev() {
@@ -124,6 +148,6 @@ impl ToString for Bitboard {
             rows.push(row);
         }
 
-        rows.join("\n")
+        write!(f, "{}", rows.join("\n"))
     }
 }
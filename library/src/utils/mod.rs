@@ -1,2 +1,3 @@
 pub mod bitboard;
 pub mod notation;
+pub mod random;
@@ -1,16 +1,19 @@
 pub fn algebraic_square_to_bit(square: &str) -> u8 {
-    let file_char = square.chars().nth(0).unwrap();
-    let rank_char = square.chars().nth(1).unwrap();
+    parse_algebraic_square(square).unwrap_or_else(|| panic!("Invalid square {}", square))
+}
 
-    let file = file_char as u8 - 'a' as u8;
-    let rank = rank_char.to_digit(10).unwrap() as u8 - 1;
-    return (rank * 8) + file;
+/// The square named like `e4`, or `None` if it isn't one.
+pub fn parse_algebraic_square(square: &str) -> Option<u8> {
+    match square.as_bytes() {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some((rank - b'1') * 8 + (file - b'a')),
+        _ => None,
+    }
 }
 
 pub fn bit_to_algebraic_square(index: u8) -> String {
     let rank = ((index & 0b00111000) / 8) + 1;
     let file = index & 0b00000111;
-    let file_char = ('a' as u8 + file) as char;
+    let file_char = (b'a' + file) as char;
     let rank_char = char::from_digit(rank as u32, 10).unwrap();
-    return String::from(format!("{}{}", file_char, rank_char));
+    format!("{}{}", file_char, rank_char)
 }
@@ -1,3 +1,64 @@
+use std::env;
+use std::time::Instant;
+
+use library::chess::game::Game;
+use library::chess::moves::generate_legal_moves;
+
+fn perft(game: &mut Game, depth: u32) -> u64 {
+    let moves = generate_legal_moves(game);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0;
+    for mv in moves {
+        game.make_move(mv);
+        nodes += perft(game, depth - 1);
+        game.unmake_move();
+    }
+
+    nodes
+}
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("usage: perft <depth> [fen]");
+        return;
+    }
+
+    let depth: u32 = args[0].parse().expect("depth must be a number");
+    let mut game = if args.len() > 1 {
+        Game::from_fen(args[1..].join(" "))
+    } else {
+        Game::new_classical()
+    };
+
+    let start = Instant::now();
+    let mut total = 0;
+    if depth == 0 {
+        total = 1;
+    } else {
+        for mv in generate_legal_moves(&mut game) {
+            game.make_move(mv);
+            let nodes = if depth == 1 {
+                1
+            } else {
+                perft(&mut game, depth - 1)
+            };
+            game.unmake_move();
+
+            println!("{}: {}", mv, nodes);
+            total += nodes;
+        }
+    }
+
+    let elapsed = start.elapsed();
+    println!();
+    println!("Nodes searched: {}", total);
+    println!(
+        "Time: {} ms ({} nps)",
+        elapsed.as_millis(),
+        (total as f64 / elapsed.as_secs_f64().max(1e-9)) as u64
+    );
 }
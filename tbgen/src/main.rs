@@ -0,0 +1,193 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use library::chess::board::Piece;
+use library::chess::game::{Game, Side};
+use library::syzygy::write::write_tables;
+use library::tablebase::dtz::{generate_dtz, DtzTables};
+use library::tablebase::generate::generate;
+use library::tablebase::material::Material;
+use library::tablebase::table::EXTENSION;
+use library::tablebase::{Dtm, Tablebase, MAX_PIECES};
+
+const PIECES: [Piece; 5] = [
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+
+fn usage() {
+    eprintln!("usage: tbgen <directory> <material>...  generate tables like KQK or KRKP,");
+    eprintln!("                                         and the ones they depend on");
+    eprintln!("       tbgen <directory> all            every table with up to 4 pieces");
+    eprintln!("       tbgen <directory> probe <fen>    look a position up");
+    eprintln!("       tbgen <directory> syzygy <material>...");
+    eprintln!("                                         write Syzygy tables like KRvKP");
+}
+
+/// Every canonical material with three or four pieces.
+fn all_materials() -> Vec<Material> {
+    // each side's pieces besides the king, as multisets of at most two
+    let mut sets = vec![Vec::new()];
+    for (i, first) in PIECES.iter().enumerate() {
+        sets.push(vec![*first]);
+        for second in &PIECES[i..] {
+            sets.push(vec![*first, *second]);
+        }
+    }
+
+    let mut materials: Vec<Material> = Vec::new();
+    for white in &sets {
+        for black in &sets {
+            let material = Material::new(white.clone(), black.clone());
+            if material.is_bare_kings()
+                || material.piece_count() > MAX_PIECES
+                || !material.is_canonical()
+                || materials.contains(&material)
+            {
+                continue;
+            }
+            materials.push(material);
+        }
+    }
+    materials.sort_by_key(|material| material.piece_count());
+    materials
+}
+
+/// Generates `material` after everything it depends on, skipping tables that are
+/// already there.
+fn generate_all(material: &Material, tablebase: &mut Tablebase, directory: &Path) {
+    if tablebase.contains(material) {
+        return;
+    }
+    for dependency in material.dependencies() {
+        generate_all(&dependency, tablebase, directory);
+    }
+
+    let start = Instant::now();
+    let table = generate(material, tablebase);
+    let path = directory.join(format!("{}.{}", material.name(), EXTENSION));
+    table.write(&path).expect("could not write table");
+
+    let longest = |side: Side| match table.longest_win(side) {
+        Some(plies) => plies.to_string(),
+        None => "-".to_string(),
+    };
+    println!(
+        "{}: longest mate {} plies with white to move, {} with black, {:.1}s",
+        material.name(),
+        longest(Side::White),
+        longest(Side::Black),
+        start.elapsed().as_secs_f64()
+    );
+    tablebase.insert(table);
+}
+
+/// Generates the distance to zeroing table of `material` after everything it depends
+/// on, in memory.
+fn generate_dtz_all(material: &Material, tables: &mut DtzTables) {
+    if tables.contains(material) {
+        return;
+    }
+    for dependency in material.dependencies() {
+        generate_dtz_all(&dependency, tables);
+    }
+    tables.insert(generate_dtz(material, tables));
+}
+
+fn write_syzygy(directory: &Path, names: &[String]) {
+    let mut tables = DtzTables::new();
+    for name in names {
+        let start = Instant::now();
+        let Some(material) = Material::from_name(&name.replace('v', "")) else {
+            eprintln!("invalid material {}", name);
+            return;
+        };
+        if material.piece_count() > MAX_PIECES {
+            eprintln!("invalid material {}", name);
+            return;
+        }
+        generate_dtz_all(&material.canonical().0, &mut tables);
+        if let Err(error) = write_tables(name, &tables, directory) {
+            eprintln!("could not write {}: {}", name, error);
+            return;
+        }
+
+        let table = tables.get(&material).expect("generated");
+        let longest = |side: Side| match table.longest_win(side) {
+            Some(plies) => plies.to_string(),
+            None => "-".to_string(),
+        };
+        println!(
+            "{}: longest zeroing {} plies with white to move, {} with black, {:.1}s",
+            name,
+            longest(Side::White),
+            longest(Side::Black),
+            start.elapsed().as_secs_f64()
+        );
+    }
+}
+
+fn probe(tablebase: &Tablebase, fen: String) {
+    let mut game = Game::from_fen(fen);
+    let value = match tablebase.probe(&game) {
+        Some(Dtm::Win(plies)) => format!("win, mate in {} plies", plies),
+        Some(Dtm::Draw) => "draw".to_string(),
+        Some(Dtm::Loss(plies)) => format!("loss, mated in {} plies", plies),
+        None => {
+            println!("not in the tables");
+            return;
+        }
+    };
+    println!("{}", value);
+    if let Some((mv, _)) = tablebase.best_move(&mut game) {
+        println!("best move {}", mv);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        usage();
+        return;
+    }
+
+    let directory = Path::new(&args[0]);
+    fs::create_dir_all(directory).expect("could not create directory");
+    if args[1] == "syzygy" {
+        write_syzygy(directory, &args[2..]);
+        return;
+    }
+    let mut tablebase = Tablebase::open(directory).expect("could not read tables");
+
+    if args[1] == "probe" {
+        probe(&tablebase, args[2..].join(" "));
+        return;
+    }
+
+    let materials = if args[1] == "all" {
+        all_materials()
+    } else {
+        let mut materials = Vec::new();
+        for name in &args[1..] {
+            match Material::from_name(name) {
+                Some(material) if material.piece_count() <= MAX_PIECES => {
+                    materials.push(material.canonical().0)
+                }
+                _ => {
+                    eprintln!("invalid material {}", name);
+                    return;
+                }
+            }
+        }
+        materials
+    };
+
+    for material in &materials {
+        generate_all(material, &mut tablebase, directory);
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use library::chess::game::Game;
+use library::eval;
+use library::eval::endgame::{self, SCALE_NORMAL};
+use library::eval::params::PARAMS;
+
+/// One training position, reduced to what the tuner needs: the evaluation is
+/// `base + Σ count × PARAMS[index]`, tapered by `phase`, all from white's point of view.
+/// `base` is the part that isn't tuned, which is the king danger penalty.
+pub struct Entry {
+    pub coefficients: Vec<(u32, i32)>,
+    pub phase: f64,
+    pub base_mg: f64,
+    pub base_eg: f64,
+    /// Share of the endgame half that counts, from the scaling rules. It depends on
+    /// which side the endgame half favours, but is taken as fixed while tuning.
+    pub scale: f64,
+    /// 1 for a white win, 0.5 for a draw, 0 for a black win.
+    pub result: f64,
+}
+
+/// Game result from `1-0`, `0-1`, `1/2-1/2` or a number between 0 and 1, ignoring
+/// quotes, brackets and semicolons around it.
+fn parse_result(token: &str) -> Option<f64> {
+    let token = token.trim_matches(|c: char| matches!(c, '"' | '[' | ']' | ';' | '(' | ')'));
+    match token {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" | "½-½" => Some(0.5),
+        _ => token
+            .parse::<f64>()
+            .ok()
+            .filter(|result| (0.0..=1.0).contains(result)),
+    }
+}
+
+/// A FEN (or the four EPD fields) followed by the result, e.g.
+/// `rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 [0.5]`.
+fn parse_line(line: &str) -> Option<(String, f64)> {
+    let line = line.trim();
+    let split = line.rfind(char::is_whitespace)?;
+    let result = parse_result(&line[split + 1..])?;
+
+    let mut fields: Vec<&str> = line[..split]
+        .trim_end_matches(|c: char| c == ';' || c.is_whitespace())
+        .split_whitespace()
+        .collect();
+    // EPD operations such as `c9` in front of the result aren't part of the position
+    fields.truncate(6);
+    if fields.len() < 4 {
+        return None;
+    }
+    let mut fen = fields[..4].join(" ");
+    match fields.get(4..6) {
+        Some(counters) if counters.iter().all(|field| field.parse::<u32>().is_ok()) => {
+            fen.push(' ');
+            fen.push_str(&counters.join(" "));
+        }
+        _ => fen.push_str(" 0 1"),
+    }
+
+    Some((fen, result))
+}
+
+/// `None` for positions with a specialized endgame evaluation, which the parameters
+/// have no part in.
+pub fn entry(game: &Game, result: f64) -> Option<Entry> {
+    if endgame::evaluate(game.board(), game.active()).is_some() {
+        return None;
+    }
+    let trace = eval::trace(game);
+    let total = trace.total_score();
+
+    let mut base_mg = total.mg() as f64;
+    let mut base_eg = total.eg() as f64;
+    let mut coefficients = Vec::new();
+    for (index, &count) in trace.coefficients.values().iter().enumerate() {
+        if count != 0 {
+            base_mg -= (count * PARAMS[index].mg()) as f64;
+            base_eg -= (count * PARAMS[index].eg()) as f64;
+            coefficients.push((index as u32, count));
+        }
+    }
+
+    Some(Entry {
+        coefficients,
+        phase: trace.phase as f64,
+        base_mg,
+        base_eg,
+        scale: trace.scale as f64 / SCALE_NORMAL as f64,
+        result,
+    })
+}
+
+/// Reads every position in `path`, skipping lines it can't make sense of.
+pub fn load(path: &str) -> io::Result<Vec<Entry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    let mut endgames = 0;
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_line(&line) {
+            Some((fen, result)) => match entry(&Game::from_fen(fen), result) {
+                Some(entry) => entries.push(entry),
+                None => endgames += 1,
+            },
+            None => skipped += 1,
+        }
+    }
+
+    if skipped > 0 {
+        eprintln!("skipped {} lines without a position and result", skipped);
+    }
+    if endgames > 0 {
+        eprintln!(
+            "skipped {} positions with a specialized endgame evaluation",
+            endgames
+        );
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use library::chess::game::Game;
+
+    use super::{entry, parse_line};
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+
+    #[test]
+    fn results_in_any_notation() {
+        for (line, result) in [
+            (format!("{} 1-0", START), 1.0),
+            (format!("{} [0-1]", START), 0.0),
+            (format!("{} \"1/2-1/2\";", START), 0.5),
+            (format!("{} [0.25]", START), 0.25),
+            (format!("  {} (1.0)  ", START), 1.0),
+        ] {
+            assert_eq!(
+                parse_line(&line),
+                Some((START.to_string(), result)),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn epd_lines_get_counters() {
+        let epd = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -";
+        assert_eq!(
+            parse_line(&format!("{} 0.5", epd)),
+            Some((format!("{} 0 1", epd), 0.5))
+        );
+        // operations between the position and the result are dropped
+        assert_eq!(
+            parse_line(&format!("{} c9 \"1-0\";", epd)),
+            Some((format!("{} 0 1", epd), 1.0))
+        );
+        assert_eq!(
+            parse_line(&format!("{} 12 30 c9 0-1", epd)),
+            Some((format!("{} 12 30", epd), 0.0))
+        );
+    }
+
+    #[test]
+    fn lines_without_a_result_are_rejected() {
+        assert_eq!(parse_line("8/8/8/4k3/8/8/8/KQ6 w - -"), None);
+        assert_eq!(parse_line(&format!("{} 2-0", START)), None);
+        assert_eq!(parse_line(&format!("{} 1.5", START)), None);
+        assert_eq!(parse_line("8/8/8 w 1-0"), None);
+        assert_eq!(parse_line(""), None);
+    }
+
+    #[test]
+    fn specialized_endgames_are_skipped() {
+        let game = |fen: &str| Game::from_fen(fen.to_string());
+        assert!(entry(&game("8/8/8/4k3/8/8/7p/K6R w - - 0 1"), 1.0).is_none());
+
+        // a wrong rook pawn only scales the endgame half down
+        let scaled = entry(&game("k7/8/8/8/8/8/P7/K1B5 w - - 0 1"), 0.5).unwrap();
+        assert_eq!(scaled.scale, 0.0);
+        let normal = entry(&game(START), 0.5).unwrap();
+        assert_eq!(normal.scale, 1.0);
+    }
+}
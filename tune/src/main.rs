@@ -0,0 +1,161 @@
+mod data;
+mod tuner;
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::thread;
+use std::time::Instant;
+
+use library::eval::params::PARAM_INFO;
+
+use tuner::{Tuner, Weights};
+
+const DEFAULT_EPOCHS: usize = 2000;
+/// Epochs between progress reports, which also rewrite the output file.
+const REPORT_INTERVAL: usize = 50;
+
+/// `params.rs` as the tuner was built with, for the section comments that
+/// `eval_params!` doesn't see.
+const PARAMS_SOURCE: &str = include_str!("../../library/src/eval/params.rs");
+
+/// The plain comments inside `eval_params!`, by the parameter they come before.
+fn section_comments() -> HashMap<&'static str, Vec<&'static str>> {
+    let body = PARAMS_SOURCE
+        .split_once("\neval_params! {")
+        .map_or("", |(_, body)| body);
+    let mut sections = HashMap::new();
+    let mut comment = Vec::new();
+    for line in body.lines().map(str::trim) {
+        if line.starts_with("//") && !line.starts_with("///") {
+            comment.push(line);
+        } else if let Some((name, _)) = line.split_once(" = [") {
+            if !comment.is_empty() {
+                sections.insert(name, std::mem::take(&mut comment));
+            }
+        }
+    }
+    sections
+}
+
+/// Parameters in the form `params.rs` declares them, ready to paste into `eval_params!`.
+fn format_params(weights: &Weights, k: f64, error: f64, positions: usize) -> String {
+    let sections = section_comments();
+    let mut source = String::new();
+    writeln!(
+        source,
+        "// Tuned on {} positions, K = {:.4}, error = {:.6}.",
+        positions, k, error
+    )
+    .unwrap();
+    writeln!(source, "eval_params! {{").unwrap();
+
+    for (index, (name, param, doc)) in PARAM_INFO.iter().enumerate() {
+        if let Some(comment) = sections.get(name) {
+            if index > 0 {
+                writeln!(source).unwrap();
+            }
+            for line in comment {
+                writeln!(source, "    {}", line).unwrap();
+            }
+            writeln!(source).unwrap();
+        }
+        for line in doc.lines() {
+            writeln!(source, "    ///{}", line).unwrap();
+        }
+
+        let values: Vec<String> = (param.offset()..param.offset() + param.size())
+            .map(|index| {
+                format!(
+                    "s({}, {})",
+                    weights.mg[index].round() as i32,
+                    weights.eg[index].round() as i32
+                )
+            })
+            .collect();
+        if values.len() == 1 {
+            writeln!(source, "    {} = [{}];", name, values[0]).unwrap();
+        } else {
+            writeln!(source, "    {} = [", name).unwrap();
+            for row in values.chunks(4) {
+                writeln!(source, "        {},", row.join(", ")).unwrap();
+            }
+            writeln!(source, "    ];").unwrap();
+        }
+    }
+
+    writeln!(source, "}}").unwrap();
+    source
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("usage: tune <positions> <output> [epochs] [threads]");
+        eprintln!();
+        eprintln!("Each line of <positions> is a FEN followed by the game result, as 1-0,");
+        eprintln!("0-1, 1/2-1/2 or a number from 0 to 1. King danger is not tuned, see");
+        eprintln!("the note in eval/params.rs.");
+        return;
+    }
+
+    let epochs = match args.get(2) {
+        Some(epochs) => epochs.parse().expect("epochs must be a number"),
+        None => DEFAULT_EPOCHS,
+    };
+    let threads = match args.get(3) {
+        Some(threads) => threads.parse().expect("threads must be a number"),
+        None => thread::available_parallelism().map_or(1, |threads| threads.get()),
+    };
+
+    let start = Instant::now();
+    let entries = data::load(&args[0]).expect("could not read positions");
+    if entries.is_empty() {
+        eprintln!("no positions in {}", args[0]);
+        return;
+    }
+    println!(
+        "Loaded {} positions in {} ms",
+        entries.len(),
+        start.elapsed().as_millis()
+    );
+
+    let weights = Weights::current();
+    let k = tuner::fit_k(&entries, &weights, threads);
+    let mut tuner = Tuner::new(weights, k, threads);
+    println!("K = {:.4}, error = {:.6}", k, tuner.error(&entries));
+
+    for epoch in 1..=epochs {
+        tuner.step(&entries);
+
+        if epoch % REPORT_INTERVAL == 0 || epoch == epochs {
+            let error = tuner.error(&entries);
+            println!(
+                "Epoch {}: error = {:.6} ({} s)",
+                epoch,
+                error,
+                start.elapsed().as_secs()
+            );
+            let source = format_params(&tuner.weights, k, error, entries.len());
+            fs::write(&args[1], source).expect("could not write output");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_params, PARAMS_SOURCE};
+    use crate::tuner::Weights;
+
+    #[test]
+    fn current_params_format_as_declared() {
+        let source = format_params(&Weights::current(), 1.0, 0.1, 1);
+        let (header, params) = source.split_once('\n').unwrap();
+        assert!(header.starts_with("// Tuned on 1 positions"));
+
+        let start = PARAMS_SOURCE.find("\neval_params! {").unwrap() + 1;
+        let end = start + PARAMS_SOURCE[start..].find("\n}\n").unwrap() + 3;
+        assert_eq!(params, &PARAMS_SOURCE[start..end]);
+    }
+}
@@ -0,0 +1,272 @@
+use std::thread;
+
+use library::eval::params::{PARAMS, PARAM_COUNT};
+use library::eval::MAX_PHASE;
+
+use crate::data::Entry;
+
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+/// Middlegame and endgame value of every parameter, as floats while tuning.
+#[derive(Clone)]
+pub struct Weights {
+    pub mg: Vec<f64>,
+    pub eg: Vec<f64>,
+}
+
+impl Weights {
+    pub fn current() -> Self {
+        Self {
+            mg: PARAMS.iter().map(|score| score.mg() as f64).collect(),
+            eg: PARAMS.iter().map(|score| score.eg() as f64).collect(),
+        }
+    }
+
+    fn zero() -> Self {
+        Self {
+            mg: vec![0.0; PARAM_COUNT],
+            eg: vec![0.0; PARAM_COUNT],
+        }
+    }
+
+    /// White's evaluation of `entry` in centipawns.
+    fn evaluate(&self, entry: &Entry) -> f64 {
+        let (mut mg, mut eg) = (entry.base_mg, entry.base_eg);
+        for &(index, count) in entry.coefficients.iter() {
+            mg += count as f64 * self.mg[index as usize];
+            eg += count as f64 * self.eg[index as usize];
+        }
+        let max_phase = MAX_PHASE as f64;
+
+        (mg * entry.phase + eg * entry.scale * (max_phase - entry.phase)) / max_phase
+    }
+}
+
+/// Expected result for white from an evaluation, the logistic curve the tuner fits.
+fn sigmoid(k: f64, eval: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+/// Splits `entries` over `threads` threads and adds up what `work` returns for each chunk.
+fn parallel<T: Send>(
+    entries: &[Entry],
+    threads: usize,
+    work: impl Fn(&[Entry]) -> T + Sync,
+    combine: impl Fn(T, T) -> T,
+) -> T {
+    let chunk_size = entries.len().div_ceil(threads.max(1)).max(1);
+    let work = &work;
+    thread::scope(|scope| {
+        let handles: Vec<_> = entries
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || work(chunk)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("tuner thread panicked"))
+            .reduce(combine)
+            .expect("no positions to tune on")
+    })
+}
+
+/// Mean squared difference between the results and what the evaluation predicts.
+pub fn error(entries: &[Entry], weights: &Weights, k: f64, threads: usize) -> f64 {
+    let total = parallel(
+        entries,
+        threads,
+        |chunk| {
+            chunk
+                .iter()
+                .map(|entry| (entry.result - sigmoid(k, weights.evaluate(entry))).powi(2))
+                .sum::<f64>()
+        },
+        |a, b| a + b,
+    );
+
+    total / entries.len() as f64
+}
+
+/// Finds the scaling constant that best fits the current evaluation to the results,
+/// so that the parameters don't have to absorb it.
+pub fn fit_k(entries: &[Entry], weights: &Weights, threads: usize) -> f64 {
+    let (mut low, mut high) = (0.0, 4.0);
+    // the error is convex in K, a ternary search is enough
+    for _ in 0..50 {
+        let a = low + (high - low) / 3.0;
+        let b = high - (high - low) / 3.0;
+        if error(entries, weights, a, threads) < error(entries, weights, b, threads) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+
+    (low + high) / 2.0
+}
+
+/// Derivative of `error` by every weight.
+fn gradient(entries: &[Entry], weights: &Weights, k: f64, threads: usize) -> Weights {
+    let max_phase = MAX_PHASE as f64;
+    let mut gradient = parallel(
+        entries,
+        threads,
+        |chunk| {
+            let mut gradient = Weights::zero();
+            for entry in chunk {
+                let predicted = sigmoid(k, weights.evaluate(entry));
+                // d(error)/d(eval), without the constant factors
+                let slope = (predicted - entry.result) * predicted * (1.0 - predicted);
+                let mg_slope = slope * entry.phase / max_phase;
+                let eg_slope = slope * entry.scale * (max_phase - entry.phase) / max_phase;
+                for &(index, count) in entry.coefficients.iter() {
+                    gradient.mg[index as usize] += mg_slope * count as f64;
+                    gradient.eg[index as usize] += eg_slope * count as f64;
+                }
+            }
+            gradient
+        },
+        |mut a, b| {
+            for index in 0..PARAM_COUNT {
+                a.mg[index] += b.mg[index];
+                a.eg[index] += b.eg[index];
+            }
+            a
+        },
+    );
+
+    let scale = 2.0 * k * 10f64.ln() / 400.0 / entries.len() as f64;
+    for value in gradient.mg.iter_mut().chain(gradient.eg.iter_mut()) {
+        *value *= scale;
+    }
+    gradient
+}
+
+/// Gradient descent with Adam step sizes, which copes with parameters that appear in
+/// very different numbers of positions.
+pub struct Tuner {
+    pub weights: Weights,
+    k: f64,
+    threads: usize,
+    momentum: Weights,
+    velocity: Weights,
+    steps: i32,
+}
+
+impl Tuner {
+    pub fn new(weights: Weights, k: f64, threads: usize) -> Self {
+        Self {
+            weights,
+            k,
+            threads,
+            momentum: Weights::zero(),
+            velocity: Weights::zero(),
+            steps: 0,
+        }
+    }
+
+    pub fn error(&self, entries: &[Entry]) -> f64 {
+        error(entries, &self.weights, self.k, self.threads)
+    }
+
+    /// One pass over all positions.
+    pub fn step(&mut self, entries: &[Entry]) {
+        let gradient = gradient(entries, &self.weights, self.k, self.threads);
+
+        self.steps += 1;
+        let momentum_correction = 1.0 - BETA1.powi(self.steps);
+        let velocity_correction = 1.0 - BETA2.powi(self.steps);
+        for (gradient, weights, momentum, velocity) in [
+            (
+                &gradient.mg,
+                &mut self.weights.mg,
+                &mut self.momentum.mg,
+                &mut self.velocity.mg,
+            ),
+            (
+                &gradient.eg,
+                &mut self.weights.eg,
+                &mut self.momentum.eg,
+                &mut self.velocity.eg,
+            ),
+        ] {
+            for index in 0..PARAM_COUNT {
+                let g = gradient[index];
+                momentum[index] = BETA1 * momentum[index] + (1.0 - BETA1) * g;
+                velocity[index] = BETA2 * velocity[index] + (1.0 - BETA2) * g * g;
+                let m = momentum[index] / momentum_correction;
+                let v = velocity[index] / velocity_correction;
+                weights[index] -= LEARNING_RATE * m / (v.sqrt() + EPSILON);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use library::chess::game::Game;
+
+    use super::{error, gradient, Weights};
+    use crate::data::{entry, Entry};
+
+    fn entries() -> Vec<Entry> {
+        [
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+                0.5,
+            ),
+            (
+                "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 0 1",
+                1.0,
+            ),
+            ("8/5pk1/6p1/8/3B4/8/1P3PPP/6K1 b - - 0 1", 1.0),
+            ("4k3/8/3b4/8/3PP3/5B2/8/4K3 w - - 0 1", 0.5),
+            ("2r3k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 0.0),
+        ]
+        .into_iter()
+        .map(|(fen, result)| entry(&Game::from_fen(fen.to_string()), result).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        let entries = entries();
+        let weights = Weights::current();
+        let k = 1.2;
+        let analytic = gradient(&entries, &weights, k, 2);
+
+        let step = 1e-3;
+        let mut indices: Vec<usize> = entries
+            .iter()
+            .flat_map(|entry| entry.coefficients.iter().map(|(index, _)| *index as usize))
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        for index in indices {
+            for eg in [false, true] {
+                let nudged = |delta: f64| {
+                    let mut weights = weights.clone();
+                    let values = if eg { &mut weights.eg } else { &mut weights.mg };
+                    values[index] += delta;
+                    error(&entries, &weights, k, 1)
+                };
+                let numeric = (nudged(step) - nudged(-step)) / (2.0 * step);
+                let analytic = if eg {
+                    analytic.eg[index]
+                } else {
+                    analytic.mg[index]
+                };
+                assert!(
+                    (analytic - numeric).abs() <= 1e-9 + 1e-4 * numeric.abs(),
+                    "parameter {} ({}): {} against {}",
+                    index,
+                    if eg { "eg" } else { "mg" },
+                    analytic,
+                    numeric
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use library::book::{Book, BookSettings, Selection};
+use library::chess::game::{Game, Variant};
+use library::chess::moves::{parse_uci_move, Move};
+use library::eval;
+#[cfg(feature = "nnue")]
+use library::nnue;
+use library::search::info::SearchInfo;
+use library::search::limits::SearchLimits;
+use library::search::score::mate_distance;
+use library::search::threads::SearchPool;
+use library::search::time::{TimeControl, TimeManager};
+use library::search::transposition::{Bound, TranspositionTable};
+use library::syzygy::{self, ProbeSettings};
+use library::utils::random::Random;
+
+const DEFAULT_HASH_MB: usize = 16;
+const DEFAULT_MOVE_OVERHEAD_MS: u64 = 30;
+const MAX_THREADS: usize = 1024;
+const MAX_MULTIPV: usize = 256;
+/// Chess960 numbers the classical start position 518.
+const CLASSICAL_CHESS960_INDEX: u32 = 518;
+/// `UCI_Variant` values, as named by lichess and the GUIs that support them.
+const VARIANTS: [(&str, Variant); 6] = [
+    ("chess", Variant::Classical),
+    ("crazyhouse", Variant::Crazyhouse),
+    ("atomic", Variant::Atomic),
+    ("3check", Variant::ThreeCheck),
+    ("kingofthehill", Variant::KingOfTheHill),
+    ("antichess", Variant::Antichess),
+];
+
+pub struct Engine {
+    game: Option<Game>,
+    pool: Option<SearchPool>,
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    /// Set by `go ponder`, cleared by `ponderhit`.
+    pondering: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<(Game, SearchPool)>>,
+    move_overhead: Duration,
+    multipv: usize,
+    syzygy: ProbeSettings,
+    book: Option<Book>,
+    own_book: bool,
+    book_settings: BookSettings,
+    /// Picks between weighted book moves.
+    random: Random,
+    /// Set by `UCI_Chess960`: castling rights can name any rook, and castling moves are
+    /// written as the king taking its rook.
+    chess960: bool,
+    /// Set by `debug on`: search statistics are printed before the best move.
+    debug: bool,
+    /// Set by `UCI_Variant`. Classical games are played as Chess960 when `chess960`
+    /// is set.
+    variant: Variant,
+}
+
+pub fn format_info(info: &SearchInfo, chess960: bool) -> String {
+    let score = match mate_distance(info.score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", info.score),
+    };
+    let bound = match info.bound {
+        Bound::Exact => "",
+        Bound::Lower => " lowerbound",
+        Bound::Upper => " upperbound",
+    };
+    let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_uci(chess960)).collect();
+
+    format!(
+        "info depth {} seldepth {} multipv {} score {}{} nodes {} nps {} hashfull {} tbhits {} time {} pv {}",
+        info.depth,
+        info.seldepth,
+        info.multipv,
+        score,
+        bound,
+        info.nodes,
+        info.nps(),
+        info.hashfull,
+        info.tb_hits,
+        info.time.as_millis(),
+        pv.join(" ")
+    )
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        eval::endgame::init();
+        let tt = Arc::new(TranspositionTable::new(DEFAULT_HASH_MB));
+        let stop = Arc::new(AtomicBool::new(false));
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+
+        Self {
+            game: Some(Game::new_classical()),
+            pool: Some(SearchPool::new(tt.clone(), stop.clone(), 1)),
+            tt,
+            stop,
+            pondering: Arc::new(AtomicBool::new(false)),
+            search_thread: None,
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS),
+            multipv: 1,
+            syzygy: ProbeSettings::default(),
+            book: None,
+            own_book: false,
+            book_settings: BookSettings::default(),
+            random: Random::new(seed),
+            chess960: false,
+            debug: false,
+            variant: Variant::Classical,
+        }
+    }
+
+    /// Blocks until the running search (if any) has printed its best move.
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            let (game, pool) = handle.join().expect("search thread panicked");
+            self.game = Some(game);
+            self.pool = Some(pool);
+        }
+    }
+
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(command) = tokens.first() else {
+            return true;
+        };
+
+        match *command {
+            "uci" => {
+                println!("id name chess-engine-rs");
+                println!("id author 4825764518");
+                println!(
+                    "option name Hash type spin default {} min 1 max 65536",
+                    DEFAULT_HASH_MB
+                );
+                // `go nodes` ignores this and searches on one thread, so that a node
+                // limit gives the same move every time
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTIPV
+                );
+                println!(
+                    "option name Move Overhead type spin default {} min 0 max 5000",
+                    DEFAULT_MOVE_OVERHEAD_MS
+                );
+                println!("option name Ponder type check default false");
+                println!("option name SyzygyPath type string default <empty>");
+                println!(
+                    "option name SyzygyProbeDepth type spin default {} min 1 max 100",
+                    ProbeSettings::default().probe_depth
+                );
+                println!("option name Syzygy50MoveRule type check default true");
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!(
+                    "option name BookDepth type spin default {} min 1 max 1000",
+                    BookSettings::default().max_depth
+                );
+                println!("option name BookBestMove type check default false");
+                println!("option name UCI_Chess960 type check default false");
+                let names: Vec<String> = VARIANTS
+                    .iter()
+                    .map(|(name, _)| format!("var {}", name))
+                    .collect();
+                println!(
+                    "option name UCI_Variant type combo default chess {}",
+                    names.join(" ")
+                );
+                #[cfg(feature = "nnue")]
+                println!("option name EvalFile type string default <empty>");
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "debug" => self.debug = tokens.get(1) == Some(&"on"),
+            "ucinewgame" => {
+                self.wait();
+                if let Some(pool) = self.pool.as_mut() {
+                    pool.clear();
+                }
+            }
+            "setoption" => {
+                self.wait();
+                self.set_option(&tokens[1..]);
+            }
+            "position" => {
+                self.wait();
+                self.set_position(&tokens[1..]);
+            }
+            "go" => {
+                self.wait();
+                self.go(&tokens[1..]);
+            }
+            "eval" => {
+                self.wait();
+                if let Some(game) = self.game.as_ref() {
+                    println!("{}", eval::trace(game));
+                }
+            }
+            "ponderhit" => self.pondering.store(false, Ordering::Relaxed),
+            "stop" => {
+                self.stop.store(true, Ordering::Relaxed);
+                self.wait();
+            }
+            "quit" => {
+                self.stop.store(true, Ordering::Relaxed);
+                self.wait();
+                return false;
+            }
+            _ => println!("info string unknown command {}", command),
+        }
+
+        true
+    }
+
+    fn set_option(&mut self, tokens: &[&str]) {
+        // setoption name <name...> value <value...>
+        let value_index = tokens.iter().position(|token| *token == "value");
+        let name_end = value_index.unwrap_or(tokens.len());
+        let name = tokens.get(1..name_end).unwrap_or(&[]).join(" ");
+        let value = match value_index {
+            Some(index) => tokens[index + 1..].join(" "),
+            None => String::new(),
+        };
+
+        match name.to_lowercase().as_str() {
+            "hash" => {
+                let Ok(size_mb) = value.parse::<usize>() else {
+                    println!("info string invalid hash size {}", value);
+                    return;
+                };
+                let threads = self.pool.as_ref().map_or(1, |pool| pool.threads());
+                self.tt = Arc::new(TranspositionTable::new(size_mb));
+                self.pool = Some(SearchPool::new(self.tt.clone(), self.stop.clone(), threads));
+            }
+            "threads" => match value.parse::<usize>() {
+                Ok(threads) => {
+                    if let Some(pool) = self.pool.as_mut() {
+                        pool.set_threads(threads.clamp(1, MAX_THREADS));
+                    }
+                }
+                Err(_) => println!("info string invalid thread count {}", value),
+            },
+            "multipv" => match value.parse::<usize>() {
+                Ok(lines) => self.multipv = lines.clamp(1, MAX_MULTIPV),
+                Err(_) => println!("info string invalid multipv {}", value),
+            },
+            "move overhead" => match value.parse::<u64>() {
+                Ok(ms) => self.move_overhead = Duration::from_millis(ms),
+                Err(_) => println!("info string invalid move overhead {}", value),
+            },
+            "syzygypath" => match syzygy::set_path(&value) {
+                Ok(Some(tablebases)) => println!(
+                    "info string found {} tablebases up to {} pieces",
+                    tablebases.len(),
+                    tablebases.max_pieces()
+                ),
+                Ok(None) => {}
+                Err(error) => println!("info string could not read tablebases: {}", error),
+            },
+            "syzygyprobedepth" => match value.parse::<i32>() {
+                Ok(depth) => self.syzygy.probe_depth = depth.clamp(1, 100),
+                Err(_) => println!("info string invalid probe depth {}", value),
+            },
+            "syzygy50moverule" => self.syzygy.rule50 = value == "true",
+            "ownbook" => self.own_book = value == "true",
+            "bookfile" => self.set_book_file(&value),
+            "bookdepth" => match value.parse::<u16>() {
+                Ok(depth) => self.book_settings.max_depth = depth.clamp(1, 1000),
+                Err(_) => println!("info string invalid book depth {}", value),
+            },
+            "uci_chess960" => self.chess960 = value == "true",
+            "uci_variant" => match VARIANTS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(&value))
+            {
+                Some((_, variant)) => self.variant = *variant,
+                None => println!("info string unknown variant {}", value),
+            },
+            "bookbestmove" => {
+                self.book_settings.selection = if value == "true" {
+                    Selection::Best
+                } else {
+                    Selection::Weighted
+                }
+            }
+            #[cfg(feature = "nnue")]
+            "evalfile" => self.set_eval_file(&value),
+            // the GUI decides when to ponder, we only need to know about `go ponder`
+            "ponder" => {}
+            _ => println!("info string unknown option {}", name),
+        }
+    }
+
+    /// Loads a Polyglot book, or unloads it for an empty path.
+    fn set_book_file(&mut self, path: &str) {
+        if path.is_empty() || path == "<empty>" {
+            self.book = None;
+            return;
+        }
+        match Book::open(Path::new(path)) {
+            Ok(book) => {
+                println!("info string loaded book with {} entries", book.len());
+                self.book = Some(book);
+            }
+            Err(error) => println!("info string could not load book {}: {}", path, error),
+        }
+    }
+
+    /// The move to play straight from the book, if the book is on and knows the position.
+    fn book_move(&mut self, tokens: &[&str]) -> Option<Move> {
+        // analysis, pondering and restricted searches want a search, not an instant answer
+        if !self.own_book
+            || ["infinite", "ponder", "searchmoves"]
+                .iter()
+                .any(|token| tokens.contains(token))
+        {
+            return None;
+        }
+        let book = self.book.as_ref()?;
+        let game = self
+            .game
+            .as_mut()
+            .filter(|game| game.kind().has_standard_rules())?;
+        book.pick(game, &self.book_settings, &mut self.random)
+    }
+
+    /// Loads a network, or goes back to the hand-crafted evaluation for an empty path.
+    #[cfg(feature = "nnue")]
+    fn set_eval_file(&mut self, path: &str) {
+        if path.is_empty() || path == "<empty>" {
+            nnue::set_network(None);
+        } else if let Err(error) = nnue::load(path) {
+            println!("info string could not load network {}: {}", path, error);
+            return;
+        } else {
+            println!("info string loaded network {}", path);
+        }
+
+        if let Some(game) = self.game.as_mut() {
+            game.set_network(nnue::current_network());
+        }
+    }
+
+    fn set_position(&mut self, tokens: &[&str]) {
+        let moves_index = tokens.iter().position(|token| *token == "moves");
+        let setup = &tokens[..moves_index.unwrap_or(tokens.len())];
+
+        let variant = match self.variant {
+            Variant::Classical if self.chess960 => Variant::Chess960,
+            variant => variant,
+        };
+        let mut game = match setup.first() {
+            Some(&"startpos") if variant == Variant::Chess960 => {
+                Game::new_chess960(CLASSICAL_CHESS960_INDEX)
+            }
+            Some(&"startpos") => Game::new_variant(variant),
+            Some(&"fen") => Game::from_fen_variant(setup[1..].join(" "), variant),
+            _ => {
+                println!("info string invalid position command");
+                return;
+            }
+        };
+
+        if let Some(moves_index) = moves_index {
+            for text in &tokens[moves_index + 1..] {
+                match parse_uci_move(&mut game, text) {
+                    Some(mv) => game.make_move(mv),
+                    None => {
+                        println!("info string illegal move {}", text);
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.game = Some(game);
+    }
+
+    fn parse_limits(&self, tokens: &[&str], game: &mut Game) -> SearchLimits {
+        let mut limits = SearchLimits {
+            multipv: self.multipv,
+            syzygy: self.syzygy,
+            ..Default::default()
+        };
+        let mut control = TimeControl::default();
+
+        let mut index = 0;
+        while index < tokens.len() {
+            let value = tokens
+                .get(index + 1)
+                .and_then(|value| value.parse::<u64>().ok());
+            match (tokens[index], value) {
+                ("depth", Some(depth)) => limits.depth = Some(depth.min(u8::MAX as u64) as u8),
+                ("nodes", Some(nodes)) => limits.nodes = Some(nodes),
+                ("mate", Some(moves)) => limits.mate = Some(moves.min(u32::MAX as u64) as u32),
+                ("movetime", Some(ms)) => control.movetime = Some(ms),
+                ("wtime", Some(ms)) => control.wtime = Some(ms),
+                ("btime", Some(ms)) => control.btime = Some(ms),
+                ("winc", Some(ms)) => control.winc = ms,
+                ("binc", Some(ms)) => control.binc = ms,
+                ("movestogo", Some(moves)) => control.movestogo = Some(moves),
+                ("infinite", _) => limits.infinite = true,
+                ("ponder", _) => {
+                    self.pondering.store(true, Ordering::Relaxed);
+                    limits.ponder = Some(self.pondering.clone());
+                }
+                ("searchmoves", _) => {
+                    // the move list runs until the first token that isn't a legal move
+                    while let Some(mv) = tokens
+                        .get(index + 1)
+                        .and_then(|token| parse_uci_move(game, token))
+                    {
+                        limits.searchmoves.push(mv);
+                        index += 1;
+                    }
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+
+        if !limits.infinite {
+            limits.time_manager = TimeManager::new(game.active(), &control, self.move_overhead);
+        }
+
+        limits
+    }
+
+    fn go(&mut self, tokens: &[&str]) {
+        if let Some(mv) = self.book_move(tokens) {
+            println!("bestmove {}", mv.to_uci(self.chess960));
+            return;
+        }
+
+        let mut game = self.game.take().expect("no position to search");
+        let mut pool = self.pool.take().expect("search already running");
+        let limits = self.parse_limits(tokens, &mut game);
+
+        self.stop.store(false, Ordering::Relaxed);
+        let stop = self.stop.clone();
+        let pondering = self.pondering.clone();
+        let chess960 = self.chess960;
+        let debug = self.debug;
+        self.search_thread = Some(thread::spawn(move || {
+            let result = pool.search(&mut game, &limits, &mut |info| {
+                println!("{}", format_info(info, chess960));
+            });
+
+            // In infinite mode the GUI expects no best move until it says stop, and while
+            // pondering not until it says stop or ponderhit.
+            while (limits.infinite || pondering.load(Ordering::Relaxed))
+                && !stop.load(Ordering::Relaxed)
+            {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            if debug {
+                let stats = result.stats;
+                println!(
+                    "info string extensions check {} singular {} recapture {} pawnpush {}",
+                    stats.check_extensions,
+                    stats.singular_extensions,
+                    stats.recapture_extensions,
+                    stats.pawn_push_extensions
+                );
+            }
+
+            pondering.store(false, Ordering::Relaxed);
+            match (result.best_move, result.ponder_move) {
+                (Some(best_move), Some(ponder_move)) => println!(
+                    "bestmove {} ponder {}",
+                    best_move.to_uci(chess960),
+                    ponder_move.to_uci(chess960)
+                ),
+                (Some(best_move), None) => println!("bestmove {}", best_move.to_uci(chess960)),
+                (None, _) => println!("bestmove 0000"),
+            }
+
+            (game, pool)
+        }));
+    }
+}
@@ -0,0 +1,21 @@
+mod engine;
+
+use std::io::{self, BufRead};
+
+use engine::Engine;
+
+fn main() {
+    let mut engine = Engine::new();
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if !engine.handle_command(line.trim()) {
+            return;
+        }
+    }
+
+    // stdin closed without a quit: let a running search finish
+    engine.wait();
+}